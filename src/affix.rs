@@ -0,0 +1,85 @@
+use equipment::{Brand, Equipment};
+
+/// A word added before or after the name of a piece of equipment, that
+/// changes its bonuses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Affix {
+    // Prefixes, for how well a weapon or armor is made.
+    Rusty,
+    Sturdy,
+    Fine,
+    Masterwork,
+    // Suffixes.
+    OfTheBear,
+    OfWarding,
+    OfMight,
+    OfFireResistance,
+    OfWarmth,
+    OfGrounding,
+    OfThorns,
+    OfReflection,
+}
+
+impl Affix {
+    pub fn name(self) -> &'static str {
+        match self {
+            Affix::Rusty => "rusty",
+            Affix::Sturdy => "sturdy",
+            Affix::Fine => "fine",
+            Affix::Masterwork => "masterwork",
+            Affix::OfTheBear => "of the bear",
+            Affix::OfWarding => "of warding",
+            Affix::OfMight => "of might",
+            Affix::OfFireResistance => "of fire resistance",
+            Affix::OfWarmth => "of warmth",
+            Affix::OfGrounding => "of grounding",
+            Affix::OfThorns => "of thorns",
+            Affix::OfReflection => "of reflection",
+        }
+    }
+
+    /// Whether it goes before the name, instead of after.
+    pub fn is_prefix(self) -> bool {
+        match self {
+            Affix::Rusty | Affix::Sturdy | Affix::Fine | Affix::Masterwork => true,
+            _ => false,
+        }
+    }
+
+    /// Change the bonuses of a piece of equipment. Prefixes change the power
+    /// of weapons, or the defense of armor.
+    pub fn apply(self, equipment: &mut Equipment) {
+        let quality = match self {
+            Affix::Rusty => -1,
+            Affix::Sturdy => 1,
+            Affix::Fine => 2,
+            Affix::Masterwork => 3,
+            _ => 0,
+        };
+        if equipment.is_weapon() {
+            equipment.power_bonus += quality;
+        } else {
+            equipment.defense_bonus += quality;
+        }
+        match self {
+            Affix::OfTheBear => equipment.max_hp_bonus += 10,
+            Affix::OfWarding => equipment.defense_bonus += 1,
+            Affix::OfMight => equipment.power_bonus += 1,
+            Affix::OfFireResistance => equipment.resistance = Some(Brand::Flaming),
+            Affix::OfWarmth => equipment.resistance = Some(Brand::Freezing),
+            Affix::OfGrounding => equipment.resistance = Some(Brand::Shocking),
+            Affix::OfThorns => equipment.thorns += 30,
+            Affix::OfReflection => equipment.reflection = true,
+            _ => {}
+        }
+    }
+
+    /// The name of a piece of equipment with this affix.
+    pub fn rename(self, name: &str) -> String {
+        if self.is_prefix() {
+            format!("{} {}", self.name(), name)
+        } else {
+            format!("{} {}", name, self.name())
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use object::Item;
+
+// Chance, in percent, that a pair of potions mixes well in a given run.
+const SUCCESS_CHANCE: u64 = 60;
+
+/// Whether mixing two potions makes a stronger brew, or blows up. It's
+/// decided by the run's seed, so the same pair always does the same thing
+/// within a run, but not from one run to the next.
+pub fn mixture_succeeds(seed: u32, first: Item, second: Item) -> bool {
+    let mut ingredients = [format!("{:?}", first), format!("{:?}", second)];
+    ingredients.sort();
+    let mut hasher = DefaultHasher::new();
+    (seed, ingredients).hash(&mut hasher);
+    hasher.finish() % 100 < SUCCESS_CHANCE
+}
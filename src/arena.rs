@@ -0,0 +1,53 @@
+use rand::{Rng, StdRng};
+
+use map::{self, Map, Rect, Tile};
+
+// Size of the rooms on either side of the arena.
+const SIDE_ROOM_SIZE: i32 = 8;
+// How far apart the pillars in the arena stand, and the chance for each spot
+// to get one.
+const PILLAR_SPACING: i32 = 5;
+const PILLAR_CHANCE: u32 = 50;
+
+/// Lay out a boss floor: a small room to start in, a wide arena with pillars
+/// for cover, and a back room with the stairs that can only be reached across
+/// the arena.
+///
+/// Returns the three rooms, from the start to the stairs.
+pub fn make_arena(map: &mut Map, rng: &mut StdRng) -> Vec<Rect> {
+    let middle = map.height / 2;
+    let start = Rect::new(2, middle - SIDE_ROOM_SIZE / 2, SIDE_ROOM_SIZE, SIDE_ROOM_SIZE);
+    let stairs = Rect::new(map.width - SIDE_ROOM_SIZE - 4, middle - SIDE_ROOM_SIZE / 2, SIDE_ROOM_SIZE,
+                           SIDE_ROOM_SIZE);
+    let (width, height) = (map.width / 2, map.height * 2 / 3);
+    let arena = Rect::new((map.width - width) / 2, (map.height - height) / 2, width, height);
+    for &room in &[start, arena, stairs] {
+        map::create_room(room, map);
+    }
+
+    // A corridor from each side room into the arena, through the middle.
+    for x in start.x2..(arena.x1 + 1) {
+        map[(x, middle)] = Tile::empty();
+    }
+    for x in arena.x2..(stairs.x1 + 1) {
+        map[(x, middle)] = Tile::empty();
+    }
+
+    // Pillars, the same on both sides. They stand apart, so they never cut
+    // the arena in two.
+    let center_x = (arena.x1 + arena.x2) / 2;
+    let mut x = arena.x1 + PILLAR_SPACING / 2 + 1;
+    while x < center_x {
+        let mut y = arena.y1 + PILLAR_SPACING / 2 + 1;
+        while y < arena.y2 - 1 {
+            if y != middle && rng.gen_range(0, 100) < PILLAR_CHANCE {
+                map[(x, y)] = Tile::wall();
+                map[(arena.x1 + arena.x2 - x, y)] = Tile::wall();
+            }
+            y += PILLAR_SPACING;
+        }
+        x += PILLAR_SPACING;
+    }
+
+    vec![start, arena, stairs]
+}
@@ -0,0 +1,63 @@
+use object::Item;
+
+/// A one of a kind piece of equipment. Each shows up at most once per game,
+/// and does something plain equipment can't while it's equipped.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Artifact {
+    /// A flaming sword that lights up the dungeon around it.
+    Sunsword,
+    /// Regenerates its wearer every turn.
+    RingOfTheTrollKing,
+    /// Keeps its wearer's mind clear of confusion.
+    AmuletOfClarity,
+    /// Its wearer floats over traps without springing them.
+    BootsOfTheWisp,
+}
+
+impl Artifact {
+    pub const ALL: [Artifact; 4] = [
+        Artifact::Sunsword,
+        Artifact::RingOfTheTrollKing,
+        Artifact::AmuletOfClarity,
+        Artifact::BootsOfTheWisp,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Artifact::Sunsword => "Sunsword",
+            Artifact::RingOfTheTrollKing => "Ring of the Troll King",
+            Artifact::AmuletOfClarity => "Amulet of Clarity",
+            Artifact::BootsOfTheWisp => "Boots of the Wisp",
+        }
+    }
+
+    /// The kind of item it's based on.
+    pub fn item(self) -> Item {
+        match self {
+            Artifact::Sunsword => Item::Sword,
+            Artifact::RingOfTheTrollKing => Item::Ring,
+            Artifact::AmuletOfClarity => Item::Amulet,
+            Artifact::BootsOfTheWisp => Item::Boots,
+        }
+    }
+
+    /// The shallowest level it can be found on.
+    pub fn min_level(self) -> u32 {
+        match self {
+            Artifact::Sunsword => 4,
+            Artifact::RingOfTheTrollKing => 5,
+            Artifact::AmuletOfClarity => 3,
+            Artifact::BootsOfTheWisp => 3,
+        }
+    }
+
+    /// What it does, beyond its bonuses.
+    pub fn description(self) -> &'static str {
+        match self {
+            Artifact::Sunsword => "sheds light around its wielder",
+            Artifact::RingOfTheTrollKing => "closes wounds as fast as they open",
+            Artifact::AmuletOfClarity => "keeps the mind clear",
+            Artifact::BootsOfTheWisp => "never springs a trap",
+        }
+    }
+}
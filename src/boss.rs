@@ -0,0 +1,84 @@
+use tcod::colors::{self, Color};
+
+use object::{DeathCallback, Fighter};
+
+/// Something a boss does once, as it gets hurt badly enough to enter a new
+/// phase of the fight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PhaseEffect {
+    /// Call some monsters of a kind to its side.
+    Summon(&'static str, u32),
+    /// Hit harder, by half its power, for the rest of the fight.
+    Enrage,
+    /// Heal back some percent of its max HP.
+    Regenerate(i32),
+}
+
+/// The unique monsters guarding the stairs on every fifth level of the
+/// dungeon. Deeper down, they come back in turn, tougher each time.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Boss {
+    OrcWarlord,
+    TrollKing,
+    RatKing,
+}
+
+impl Boss {
+    pub const ALL: [Boss; 3] = [Boss::OrcWarlord, Boss::TrollKing, Boss::RatKing];
+
+    /// The boss guarding the given boss floor, numbering them from the first.
+    pub fn for_floor(floor: u32) -> Self {
+        Boss::ALL[floor as usize % Boss::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Boss::OrcWarlord => "Gorbash the Orc Warlord",
+            Boss::TrollKing => "the Troll King",
+            Boss::RatKing => "the Rat King",
+        }
+    }
+
+    pub fn glyph(self) -> (char, Color) {
+        match self {
+            Boss::OrcWarlord => ('O', colors::DARK_GREEN),
+            Boss::TrollKing => ('T', colors::LIGHT_PURPLE),
+            Boss::RatKing => ('R', colors::DARK_SEPIA),
+        }
+    }
+
+    /// Its stats, before being made tougher for the depth.
+    pub fn fighter(self) -> Fighter {
+        let (max_hp, defense, power, xp) = match self {
+            Boss::OrcWarlord => (80, 2, 8, 400),
+            Boss::TrollKing => (120, 4, 11, 700),
+            Boss::RatKing => (90, 3, 9, 600),
+        };
+        Fighter {
+            max_hp,
+            hp: max_hp,
+            defense,
+            power,
+            xp,
+            flee_below: 0,
+            on_death: DeathCallback::Monster,
+        }
+    }
+
+    /// What it does as it enters each phase after the first. The fight has one
+    /// more phase than this, split evenly by how much HP it has left.
+    pub fn phase_effects(self) -> &'static [PhaseEffect] {
+        match self {
+            Boss::OrcWarlord => &[PhaseEffect::Summon("orc", 2), PhaseEffect::Enrage],
+            Boss::TrollKing => &[PhaseEffect::Regenerate(30), PhaseEffect::Enrage],
+            Boss::RatKing => &[PhaseEffect::Summon("rat", 3), PhaseEffect::Summon("rat", 4),
+                               PhaseEffect::Enrage],
+        }
+    }
+
+    /// The phase of the fight it's in with the given HP, counting from 0.
+    pub fn phase(self, hp: i32, max_hp: i32) -> usize {
+        let effects = self.phase_effects().len() as i32;
+        (0..effects).filter(|&k| hp * (effects + 1) < max_hp * (effects - k)).count()
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use boss::Boss;
+
+// Every this many levels of the dungeon, a boss guards the stairs.
+const BOSS_FLOOR_INTERVAL: u32 = 5;
+// The relic lies on the last level of the dungeon.
+const DUNGEON_DEPTH: u32 = 15;
+
+/// A line of levels going down. The main dungeon has side branches, entered
+/// by their own stairs from one of its levels, and there are more dungeons
+/// out in the wilderness, reached from the town.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Branch {
+    Dungeon,
+    /// Dug by orcs looking for gold, with caves everywhere and a hoard at the bottom.
+    Mines,
+    /// Old burial mounds close to town, for a gentle start.
+    Barrows,
+    /// Caves the sea has flooded, further out.
+    Grotto,
+    /// Deep and hot, far away in the mountains.
+    FirePits,
+}
+
+impl Branch {
+    pub const ALL: [Branch; 5] = [Branch::Dungeon, Branch::Mines, Branch::Barrows, Branch::Grotto, Branch::FirePits];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Branch::Dungeon => "Dungeon",
+            Branch::Mines => "Mines",
+            Branch::Barrows => "Barrows",
+            Branch::Grotto => "Grotto",
+            Branch::FirePits => "Fire Pits",
+        }
+    }
+
+    /// Whether it's out in the wilderness, traveled to from the town instead
+    /// of entered by stairs.
+    pub fn in_wilderness(self) -> bool {
+        match self {
+            Branch::Dungeon | Branch::Mines => false,
+            Branch::Barrows | Branch::Grotto | Branch::FirePits => true,
+        }
+    }
+
+    /// How many levels harder than its depth it is, for the dungeons out in
+    /// the wilderness.
+    pub fn difficulty(self) -> u32 {
+        match self {
+            Branch::Dungeon | Branch::Mines | Branch::Barrows => 0,
+            Branch::Grotto => 3,
+            Branch::FirePits => 7,
+        }
+    }
+
+    /// The level with the stairs into the branch, if it's a side branch.
+    pub fn entrance(self) -> Option<Location> {
+        match self {
+            Branch::Mines => Some(Location { branch: Branch::Dungeon, depth: 3 }),
+            Branch::Dungeon | Branch::Barrows | Branch::Grotto | Branch::FirePits => None,
+        }
+    }
+
+    /// The depth of its last level, if it has one.
+    pub fn bottom(self) -> Option<u32> {
+        match self {
+            Branch::Dungeon => Some(DUNGEON_DEPTH),
+            Branch::Mines => Some(4),
+            Branch::Barrows => Some(3),
+            Branch::Grotto => Some(4),
+            Branch::FirePits => Some(4),
+        }
+    }
+
+    /// Name of the stairs leading into it.
+    pub fn stairs_name(self) -> String {
+        format!("stairs to the {}", self.name().to_lowercase())
+    }
+}
+
+/// Where a level is: which branch, and how deep into it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub branch: Branch,
+    pub depth: u32,
+}
+
+impl Location {
+    /// The first level of the dungeon.
+    pub fn start() -> Self {
+        Location {
+            branch: Branch::Dungeon,
+            depth: 1,
+        }
+    }
+
+    /// The town on the surface.
+    pub fn town() -> Self {
+        Location {
+            branch: Branch::Dungeon,
+            depth: 0,
+        }
+    }
+
+    /// How many levels down from the surface it is, counting the levels above
+    /// a branch's entrance. Levels get harder with it, and in the harder
+    /// dungeons of the wilderness, they start out deeper.
+    pub fn level(&self) -> u32 {
+        self.branch.entrance().map_or(0, |entrance| entrance.level()) + self.branch.difficulty() + self.depth
+    }
+
+    /// The level some number of levels up or down in the same branch.
+    pub fn offset(&self, delta: i32) -> Self {
+        Location {
+            branch: self.branch,
+            depth: (self.depth as i32 + delta) as u32,
+        }
+    }
+
+    /// Whether it's the last level of its branch, with no stairs down.
+    pub fn is_bottom(&self) -> bool {
+        self.branch.bottom() == Some(self.depth)
+    }
+
+    /// Whether it's the town on the surface, above the first level of the
+    /// dungeon.
+    pub fn is_town(&self) -> bool {
+        self.branch == Branch::Dungeon && self.depth == 0
+    }
+
+    /// The boss guarding the stairs, if it's a boss floor.
+    pub fn boss(&self) -> Option<Boss> {
+        if self.branch == Branch::Dungeon && self.depth > 0 && self.depth % BOSS_FLOOR_INTERVAL == 0 {
+            Some(Boss::for_floor(self.depth / BOSS_FLOOR_INTERVAL - 1))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the relic lies here, at the bottom of the dungeon.
+    pub fn has_relic(&self) -> bool {
+        self.branch == Branch::Dungeon && self.is_bottom()
+    }
+
+    /// Where the stairs up lead: the level above, or the branch's entrance.
+    /// The ones on the first level of the dungeon lead up to the town, which
+    /// has none, and those of the dungeons in the wilderness back out to it.
+    pub fn above(&self) -> Option<Self> {
+        if self.depth > 1 || (self.depth == 1 && self.branch == Branch::Dungeon) {
+            Some(self.offset(-1))
+        } else if self.depth == 1 && self.branch.in_wilderness() {
+            Some(Location::town())
+        } else {
+            self.branch.entrance()
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_town() {
+            write!(f, "Town")
+        } else {
+            write!(f, "{} level {}", self.branch.name(), self.depth)
+        }
+    }
+}
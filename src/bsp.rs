@@ -0,0 +1,69 @@
+use std::cmp;
+
+use rand::{Rng, StdRng};
+
+use map::{self, Corridor, Map, Rect, RoomShape, ROOM_MAX_SIZE, ROOM_MIN_SIZE};
+
+// Smallest part of the map that still fits a room, with walls around it.
+const MIN_LEAF_SIZE: i32 = ROOM_MIN_SIZE + 2;
+// Parts smaller than this may stop being split, leaving some rooms roomier.
+const MAX_LEAF_SIZE: i32 = ROOM_MAX_SIZE * 2;
+
+/// Lay out rooms by splitting the map in two, again and again, and putting a
+/// room in each of the smallest parts. Each pair of parts split from the same
+/// one gets joined by a tunnel, so every room can be reached.
+///
+/// The rooms are returned in the order they were carved, so the first and
+/// last ones end up far apart.
+pub fn make_rooms(map: &mut Map, corridor: Corridor, rng: &mut StdRng) -> Vec<Rect> {
+    let mut rooms = vec![];
+    // Keep the outer edge of the map solid.
+    split(Rect::new(0, 0, map.width - 1, map.height - 1), corridor, map, &mut rooms, rng);
+    rooms
+}
+
+/// Fill an area with rooms. Returns one of them, for the caller to connect
+/// to the rooms in the area next to it.
+fn split(area: Rect, corridor: Corridor, map: &mut Map, rooms: &mut Vec<Rect>, rng: &mut StdRng) -> Rect {
+    let width = area.x2 - area.x1;
+    let height = area.y2 - area.y1;
+    let can_split_x = width >= 2 * MIN_LEAF_SIZE;
+    let can_split_y = height >= 2 * MIN_LEAF_SIZE;
+    let small_enough = width <= MAX_LEAF_SIZE && height <= MAX_LEAF_SIZE;
+
+    if !(can_split_x || can_split_y) || (small_enough && rng.gen()) {
+        // Put a room somewhere inside the area.
+        let room_width = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, width) + 1);
+        let room_height = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, height) + 1);
+        let x = rng.gen_range(area.x1, area.x2 - room_width + 1);
+        let y = rng.gen_range(area.y1, area.y2 - room_height + 1);
+        let room = Rect::new(x, y, room_width, room_height);
+        let shape = RoomShape::random(rng);
+        map::create_shaped_room(room, shape, map, rng);
+        rooms.push(room);
+        return room;
+    }
+
+    // Split across the longer side, so the parts don't get too thin.
+    let split_x = if can_split_x && can_split_y {
+        width > height || (width == height && rng.gen())
+    } else {
+        can_split_x
+    };
+    let (first, second) = if split_x {
+        let at = rng.gen_range(area.x1 + MIN_LEAF_SIZE, area.x2 - MIN_LEAF_SIZE + 1);
+        (Rect::new(area.x1, area.y1, at - area.x1, height), Rect::new(at, area.y1, area.x2 - at, height))
+    } else {
+        let at = rng.gen_range(area.y1 + MIN_LEAF_SIZE, area.y2 - MIN_LEAF_SIZE + 1);
+        (Rect::new(area.x1, area.y1, width, at - area.y1), Rect::new(area.x1, at, width, area.y2 - at))
+    };
+
+    let first_room = split(first, corridor, map, rooms, rng);
+    let second_room = split(second, corridor, map, rooms, rng);
+    map::join_rooms(first_room, second_room, corridor, map, rng);
+    if rng.gen() {
+        first_room
+    } else {
+        second_room
+    }
+}
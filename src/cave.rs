@@ -0,0 +1,104 @@
+use std::cmp;
+
+use rand::{Rng, StdRng};
+
+use map::{self, Corridor, Map, Rect, Tile};
+
+// Percent of tiles that start out as walls, before smoothing.
+const WALL_CHANCE: u32 = 45;
+const SMOOTHING_PASSES: u32 = 5;
+// Caves smaller than this are filled back in.
+const MIN_CAVE_SIZE: usize = 15;
+
+/// Grow caverns out of random noise, by smoothing it over a few times like a
+/// cellular automaton, then dig tunnels to join separate caves to the biggest.
+///
+/// There are no rooms, so this returns areas of the map to put things in,
+/// each centered on an open tile. The player starts in the first, and the
+/// stairs go in the last, both in the biggest cave and far apart.
+pub fn make_caves(map: &mut Map, corridor: Corridor, rng: &mut StdRng) -> Vec<Rect> {
+    // Keep the outer edge of the map solid.
+    for x in 1..(map.width - 1) {
+        for y in 1..(map.height - 1) {
+            if rng.gen_range(0, 100) >= WALL_CHANCE {
+                map[(x, y)] = Tile::empty();
+            }
+        }
+    }
+    for _ in 0..SMOOTHING_PASSES {
+        smooth(map);
+    }
+
+    let mut caves = find_caves(map);
+    for cave in caves.iter().filter(|cave| cave.len() < MIN_CAVE_SIZE) {
+        for &(x, y) in cave {
+            map[(x, y)] = Tile::wall();
+        }
+    }
+    caves.retain(|cave| cave.len() >= MIN_CAVE_SIZE);
+    caves.sort_by_key(|cave| cmp::Reverse(cave.len()));
+    if caves.is_empty() {
+        return vec![];
+    }
+
+    // Join every other cave to the biggest, from the closest tile in it.
+    for cave in &caves[1..] {
+        let from = *rng.choose(cave).unwrap();
+        let to = *caves[0].iter().min_by_key(|&&to| map::distance(from, to)).unwrap();
+        map::dig_tunnel(from, to, corridor, map, rng);
+    }
+
+    let mut areas = map::spread_areas(map, rng);
+    let biggest = &caves[0];
+    map::pick_start_and_stairs(&mut areas, &|pos| biggest.contains(&pos));
+    areas
+}
+
+/// Turn tiles surrounded by walls into walls, and the ones out in the open
+/// into floor. The border of the map counts as walls.
+fn smooth(map: &mut Map) {
+    let before = map.clone();
+    for x in 1..(map.width - 1) {
+        for y in 1..(map.height - 1) {
+            let mut walls = 0;
+            for dx in -1..2 {
+                for dy in -1..2 {
+                    if (dx, dy) != (0, 0) && before[(x + dx, y + dy)].blocked {
+                        walls += 1;
+                    }
+                }
+            }
+            let was_wall = before[(x, y)].blocked;
+            map[(x, y)] = if walls >= 5 || (was_wall && walls >= 4) {
+                Tile::wall()
+            } else {
+                Tile::empty()
+            };
+        }
+    }
+}
+
+/// The open tiles of each separate cave.
+fn find_caves(map: &Map) -> Vec<Vec<(i32, i32)>> {
+    let mut seen = vec![vec![false; map.height as usize]; map.width as usize];
+    let mut caves = vec![];
+    for x in 0..map.width {
+        for y in 0..map.height {
+            if seen[x as usize][y as usize] || map[(x, y)].blocked {
+                continue;
+            }
+            let reachable = map::reachable_tiles(map, x, y);
+            let mut cave = vec![];
+            for cave_x in 0..map.width {
+                for cave_y in 0..map.height {
+                    if reachable[cave_x as usize][cave_y as usize] {
+                        seen[cave_x as usize][cave_y as usize] = true;
+                        cave.push((cave_x, cave_y));
+                    }
+                }
+            }
+            caves.push(cave);
+        }
+    }
+    caves
+}
@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use tcod::{FontLayout, FontType};
+use tcod::image::Image;
+
+use json;
+use object::Item;
+
+const CONFIG_FILE: &str = "config.json";
+pub const DEFAULT_FONT: &str = "assets/arial10x10.png";
+
+/// How the glyphs are laid out in a font image.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+    Tcod,
+    AsciiInRow,
+    AsciiInCol,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 3] = [Layout::Tcod, Layout::AsciiInRow, Layout::AsciiInCol];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Layout::Tcod => "libtcod layout",
+            Layout::AsciiInRow => "ASCII, in rows",
+            Layout::AsciiInCol => "ASCII, in columns",
+        }
+    }
+
+    pub fn to_tcod(self) -> FontLayout {
+        match self {
+            Layout::Tcod => FontLayout::Tcod,
+            Layout::AsciiInRow => FontLayout::AsciiInRow,
+            Layout::AsciiInCol => FontLayout::AsciiInCol,
+        }
+    }
+}
+
+/// Which kinds of items are picked up just by walking over them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoPickup {
+    pub gold: bool,
+    pub potions: bool,
+    pub scrolls: bool,
+    pub ammo: bool,
+}
+
+impl Default for AutoPickup {
+    fn default() -> Self {
+        AutoPickup {
+            gold: true,
+            potions: false,
+            scrolls: false,
+            ammo: false,
+        }
+    }
+}
+
+impl AutoPickup {
+    /// Whether to pick up an item of this kind.
+    pub fn wants(&self, item: Item) -> bool {
+        match item {
+            Item::Gold => self.gold,
+            Item::Arrow | Item::Stone => self.ammo,
+            item if item.is_potion() => self.potions,
+            item if item.is_scroll() => self.scrolls,
+            _ => false,
+        }
+    }
+}
+
+/// Settings that are read once at startup, from `config.json`. Settings
+/// missing from the file keep their defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub font: String,
+    pub font_layout: Layout,
+    /// Whether the font is greyscale with antialiasing, instead of black and white.
+    pub font_greyscale: bool,
+    /// Whether to show projectiles flying, or have them land right away.
+    pub animations: bool,
+    pub auto_pickup: AutoPickup,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            font: DEFAULT_FONT.into(),
+            font_layout: Layout::Tcod,
+            font_greyscale: true,
+            animations: true,
+            auto_pickup: AutoPickup::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config. A missing or corrupt file gives the defaults.
+    pub fn load() -> Self {
+        read_config().unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Box<Error>> {
+        let mut file = File::create(format!("{}.tmp", CONFIG_FILE))?;
+        file.write_all(json::to_string_pretty(self)?.as_bytes())?;
+        fs::rename(format!("{}.tmp", CONFIG_FILE), CONFIG_FILE)?;
+        Ok(())
+    }
+
+    pub fn font_type(&self) -> FontType {
+        if self.font_greyscale {
+            FontType::Greyscale
+        } else {
+            FontType::Default
+        }
+    }
+
+    /// Check that the font is an image that can be loaded. If it isn't, switch
+    /// back to the default font and return a warning to show.
+    pub fn validate_font(&mut self) -> Option<String> {
+        if self.font == DEFAULT_FONT || Image::from_file(Path::new(&self.font)).is_ok() {
+            return None;
+        }
+        let warning = format!("Could not load the font {}, using the default one instead.", self.font);
+        let defaults = Config::default();
+        self.font = defaults.font;
+        self.font_layout = defaults.font_layout;
+        self.font_greyscale = defaults.font_greyscale;
+        Some(warning)
+    }
+}
+
+/// The font images available in the assets folder.
+pub fn available_fonts() -> Vec<String> {
+    let mut fonts: Vec<String> = fs::read_dir("assets")
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().map_or(false, |ext| ext == "png"))
+                .filter(|path| !path.ends_with("menu_background.png"))
+                .filter_map(|path| path.to_str().map(|path| path.replace('\\', "/")))
+                .collect()
+        })
+        .unwrap_or_else(|_| Vec::new());
+    fonts.sort();
+    fonts
+}
+
+fn read_config() -> Result<Config, Box<Error>> {
+    let mut json_config = String::new();
+    let mut file = File::open(CONFIG_FILE)?;
+    file.read_to_string(&mut json_config)?;
+    Ok(json::from_str(&json_config)?)
+}
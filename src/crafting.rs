@@ -0,0 +1,30 @@
+use object::Item;
+
+/// Two items that can be combined into another.
+pub struct Recipe {
+    pub ingredients: [Item; 2],
+    pub result: Item,
+}
+
+/// Everything that can be crafted. New recipes only need adding here.
+pub const RECIPES: &[Recipe] = &[
+    Recipe {
+        ingredients: [Item::Heal, Item::Heal],
+        result: Item::GreaterHeal,
+    },
+    Recipe {
+        ingredients: [Item::Lightning, Item::Lightning],
+        result: Item::WandLightning,
+    },
+    Recipe {
+        ingredients: [Item::Confuse, Item::Confuse],
+        result: Item::WandConfusion,
+    },
+];
+
+/// The recipe combining two items, in either order.
+pub fn find_recipe(first: Item, second: Item) -> Option<&'static Recipe> {
+    RECIPES.iter().find(|recipe| {
+        recipe.ingredients == [first, second] || recipe.ingredients == [second, first]
+    })
+}
@@ -0,0 +1,43 @@
+use object::Item;
+
+/// Flavor text for each kind of item, shown when examining it.
+pub fn flavor_text(item: Item) -> &'static str {
+    match item {
+        Item::Heal => "A small vial of violet liquid. It smells faintly of herbs, and closes wounds when drunk.",
+        Item::GreaterHeal => "A brew of several healing potions, thick and glowing. It can mend almost any wound.",
+        Item::Lightning => "A scroll crackling with static. Reading it calls a bolt of lightning down on the \
+                            closest enemy.",
+        Item::Confuse => "A scroll covered in dizzying spirals. Reading it muddles the mind of an enemy.",
+        Item::Fireball => "A scroll that is warm to the touch. Reading it hurls a ball of fire that burns \
+                           everything where it lands.",
+        Item::Torch => "A stick wrapped in pitch-soaked rags. It burns smoky and short, but it's light.",
+        Item::Lantern => "A brass oil lantern. It burns brighter and longer than a torch.",
+        Item::OilFlask => "A stoppered flask of lamp oil, enough to fill a lantern or soak a torch.",
+        Item::MagicMapping => "A scroll with a map drawn on it that keeps changing. Reading it reveals the \
+                               whole level.",
+        Item::Sword => "A straight, double-edged blade. A reliable weapon.",
+        Item::Greatsword => "A huge blade that takes both hands to swing, and hits just as hard.",
+        Item::Shield => "A round wooden shield banded with iron.",
+        Item::Armor => "A suit of hardened leather, stiff but protective.",
+        Item::Boots => "Sturdy boots, made for long walks in dark places.",
+        Item::Ring => "A plain gold ring. Whoever wears it feels stronger.",
+        Item::Amulet => "An amulet on a leather cord. It beats softly, like a second heart.",
+        Item::RemoveCurse => "A scroll of blessings. Reading it breaks the curses on what you wear.",
+        Item::Dagger => "A short, light blade. Good in a pinch, and better thrown.",
+        Item::Bow => "A bow of yew, to shoot arrows with. It takes both hands to draw.",
+        Item::Sling => "A leather sling, to hurl stones with.",
+        Item::Arrow => "Arrows with grey goose feathers, for a bow.",
+        Item::Stone => "Smooth, round stones that fit a sling just right.",
+        Item::WandLightning => "A wand humming with stored lightning, a bolt for each charge.",
+        Item::WandConfusion => "A twisted wand that leaves the eyes swimming.",
+        Item::Key => "An iron key that fits any chest lock, but breaks in it.",
+        Item::EnchantWeapon => "A scroll that makes a weapon keener when read.",
+        Item::EnchantArmor => "A scroll that makes armor tougher when read.",
+        Item::DoorKey(_) => "A heavy brass key, for the locked doors of the level it was found on.",
+        Item::Mortar => "A stone mortar and pestle. Potions can be mixed in it, though not all mix well.",
+        Item::Pickaxe => "A miner's pick, heavy and blunt. Wielded, it breaks through walls in the way.",
+        Item::Digging => "A scroll that smells of wet earth. Reading it bores a tunnel through the rock.",
+        Item::Gold => "Gold coins, stamped with the faces of forgotten kings.",
+        Item::Relic => "The crown of the last of the Ancient Kings, cold and heavy. Bring it back to the surface!",
+    }
+}
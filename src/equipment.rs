@@ -0,0 +1,185 @@
+use std::fmt;
+
+use artifact::Artifact;
+use object::Object;
+
+/// Where a piece of equipment is worn or wielded.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Slot {
+    LeftHand,
+    RightHand,
+    Head,
+    Body,
+    Feet,
+    LeftRing,
+    RightRing,
+    Neck,
+    /// Holds the ammunition shot first.
+    Quiver,
+    /// A torch or lantern, hung off the belt.
+    Light,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 10] = [
+        Slot::LeftHand,
+        Slot::RightHand,
+        Slot::Head,
+        Slot::Body,
+        Slot::Feet,
+        Slot::LeftRing,
+        Slot::RightRing,
+        Slot::Neck,
+        Slot::Quiver,
+        Slot::Light,
+    ];
+
+    pub fn is_hand(self) -> bool {
+        match self {
+            Slot::LeftHand | Slot::RightHand => true,
+            _ => false,
+        }
+    }
+
+    /// Whether it's for rings or amulets.
+    pub fn is_jewelry(self) -> bool {
+        match self {
+            Slot::LeftRing | Slot::RightRing | Slot::Neck => true,
+            _ => false,
+        }
+    }
+
+    /// For a ring slot, the ring slot on the other hand.
+    pub fn other_ring(self) -> Option<Slot> {
+        match self {
+            Slot::LeftRing => Some(Slot::RightRing),
+            Slot::RightRing => Some(Slot::LeftRing),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Slot::LeftHand => write!(f, "left hand"),
+            Slot::RightHand => write!(f, "right hand"),
+            Slot::Head => write!(f, "head"),
+            Slot::Body => write!(f, "body"),
+            Slot::Feet => write!(f, "feet"),
+            Slot::LeftRing => write!(f, "left ring finger"),
+            Slot::RightRing => write!(f, "right ring finger"),
+            Slot::Neck => write!(f, "neck"),
+            Slot::Quiver => write!(f, "quiver"),
+            Slot::Light => write!(f, "light"),
+        }
+    }
+}
+
+/// An item that can be equipped, and whether it currently is.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equipment {
+    pub slot: Slot,
+    /// Two-handed weapons fill both hands while equipped.
+    pub two_handed: bool,
+    pub equipped: bool,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    pub max_hp_bonus: i32,
+    /// A cursed item can't be taken off once equipped.
+    pub cursed: bool,
+    /// Whether the player found out about the curse.
+    pub curse_known: bool,
+    /// Extra power for a weapon, or defense for armor.
+    pub enchantment: i32,
+    /// Elemental damage a weapon deals on top of its power.
+    pub brand: Option<Brand>,
+    /// Halves the damage of weapons with this brand, and wards off its effect.
+    pub resistance: Option<Brand>,
+    pub artifact: Option<Artifact>,
+    /// Percent of melee damage taken that's dealt back to the attacker.
+    pub thorns: i32,
+    /// Bounces bolt spells back at whoever cast them.
+    pub reflection: bool,
+}
+
+impl Equipment {
+    /// Whether it takes up the given slot once equipped.
+    pub fn occupies(&self, slot: Slot) -> bool {
+        self.slot == slot || (self.two_handed && slot.is_hand())
+    }
+
+    /// Weapons are wielded in the right hand. Everything else is armor.
+    pub fn is_weapon(&self) -> bool {
+        self.slot == Slot::RightHand
+    }
+
+    /// The power bonus, with the enchantment of a weapon.
+    pub fn power(&self) -> i32 {
+        if self.is_weapon() {
+            self.power_bonus + self.enchantment
+        } else {
+            self.power_bonus
+        }
+    }
+
+    /// The defense bonus, with the enchantment of armor.
+    pub fn defense(&self) -> i32 {
+        if self.is_weapon() {
+            self.defense_bonus
+        } else {
+            self.defense_bonus + self.enchantment
+        }
+    }
+}
+
+/// An element a weapon can be imbued with. Its damage ignores defense.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Brand {
+    /// Sets the target on fire for a few turns.
+    Flaming,
+    /// Slows the target down for a few turns.
+    Freezing,
+    /// Hits harder, with no lasting effect.
+    Shocking,
+}
+
+impl Brand {
+    pub const ALL: [Brand; 3] = [Brand::Flaming, Brand::Freezing, Brand::Shocking];
+
+    /// The bonus damage dealt on every hit.
+    pub fn damage(self) -> i32 {
+        match self {
+            Brand::Flaming => 2,
+            Brand::Freezing => 2,
+            Brand::Shocking => 4,
+        }
+    }
+
+    /// The kind of damage, for combat messages.
+    pub fn element(self) -> &'static str {
+        match self {
+            Brand::Flaming => "fire",
+            Brand::Freezing => "cold",
+            Brand::Shocking => "lightning",
+        }
+    }
+}
+
+impl fmt::Display for Brand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Brand::Flaming => write!(f, "flaming"),
+            Brand::Freezing => write!(f, "freezing"),
+            Brand::Shocking => write!(f, "shocking"),
+        }
+    }
+}
+
+/// The equipment currently equipped among some items.
+pub fn get_all_equipped(items: &[Object]) -> Vec<Equipment> {
+    items.iter()
+        .filter_map(|item| item.equipment)
+        .filter(|equipment| equipment.equipped)
+        .collect()
+}
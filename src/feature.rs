@@ -0,0 +1,52 @@
+use tcod::colors::{self, Color};
+
+use theme::ThemeKind;
+
+/// Something lying on the floor of a level, apart from its items and
+/// monsters. Nothing gets in anyone's way, but some can be dealt with.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Feature {
+    /// Can be searched through, and may have something buried in it.
+    Rubble,
+    Bones,
+    /// Can be pushed around by walking into it.
+    Statue,
+    Bloodstain,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 4] = [Feature::Rubble, Feature::Bones, Feature::Statue, Feature::Bloodstain];
+
+    pub fn glyph(self) -> (char, Color) {
+        match self {
+            Feature::Rubble => (',', colors::GREY),
+            Feature::Bones => (';', colors::LIGHTEST_SEPIA),
+            Feature::Statue => ('8', colors::LIGHT_GREY),
+            Feature::Bloodstain => ('.', colors::DARK_RED),
+        }
+    }
+
+    /// What the player is told on looking at it.
+    pub fn description(self) -> &'static str {
+        match self {
+            Feature::Rubble => "A heap of rubble. Something might be buried in it.",
+            Feature::Bones => "Some old bones.",
+            Feature::Statue => "A stone statue. It could be pushed.",
+            Feature::Bloodstain => "A dried bloodstain.",
+        }
+    }
+
+    /// How common it is in a kind of place, relative to the others.
+    pub fn weight(self, theme: ThemeKind) -> u32 {
+        match (self, theme) {
+            (Feature::Rubble, ThemeKind::Mines) | (Feature::Rubble, ThemeKind::Volcanic) => 60,
+            (Feature::Bones, ThemeKind::Tombs) | (Feature::Bones, ThemeKind::Crypt) => 50,
+            (Feature::Statue, ThemeKind::Tombs) | (Feature::Statue, ThemeKind::Crypt) => 20,
+            (Feature::Statue, ThemeKind::Mines) => 0,
+            (Feature::Rubble, _) => 30,
+            (Feature::Bones, _) => 20,
+            (Feature::Statue, _) => 10,
+            (Feature::Bloodstain, _) => 20,
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+
+use json;
+
+const HIGH_SCORES_FILE: &str = "highscores.json";
+const MAX_HIGH_SCORES: usize = 20;
+
+/// A finished run, as recorded in the high-score table.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub dungeon_level: u32,
+    pub xp: i32,
+    pub turns: u32,
+    pub cause_of_death: String,
+    /// Whether the player escaped with the relic. Older tables don't have it.
+    #[serde(default)]
+    pub won: bool,
+}
+
+/// Load the high-score table. A missing or corrupt file gives an empty table.
+pub fn load() -> Vec<HighScore> {
+    read_high_scores().unwrap_or_else(|_| Vec::new())
+}
+
+fn read_high_scores() -> Result<Vec<HighScore>, Box<Error>> {
+    let mut json_scores = String::new();
+    let mut file = File::open(HIGH_SCORES_FILE)?;
+    file.read_to_string(&mut json_scores)?;
+    Ok(json::from_str(&json_scores)?)
+}
+
+/// Add a finished run to the table and return the updated table.
+///
+/// The file is re-read right before writing, so runs recorded by another
+/// session since this one started are kept.
+pub fn record(score: HighScore) -> Result<Vec<HighScore>, Box<Error>> {
+    let mut scores = load();
+    scores.push(score);
+
+    // Best runs first: won, then deepest, then most experienced.
+    scores.sort_by(|a, b| (b.won, b.dungeon_level, b.xp).cmp(&(a.won, a.dungeon_level, a.xp)));
+    scores.truncate(MAX_HIGH_SCORES);
+
+    // Write to a temporary file and move it over the real one, so dying
+    // mid-write can't corrupt the table.
+    let temp_file = format!("{}.tmp", HIGH_SCORES_FILE);
+    {
+        let mut file = File::create(&temp_file)?;
+        file.write_all(json::to_string(&scores)?.as_bytes())?;
+    }
+    fs::rename(&temp_file, HIGH_SCORES_FILE)?;
+
+    Ok(scores)
+}
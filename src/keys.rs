@@ -0,0 +1,321 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+
+use tcod::input::{Key, KeyCode};
+
+use json;
+
+const KEYS_FILE: &str = "keys.json";
+
+/// Keys without a printable character that can be bound, by name.
+const SPECIAL_KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Spacebar, "Space"),
+    (KeyCode::NumPad0, "NumPad0"),
+    (KeyCode::NumPad1, "NumPad1"),
+    (KeyCode::NumPad2, "NumPad2"),
+    (KeyCode::NumPad3, "NumPad3"),
+    (KeyCode::NumPad4, "NumPad4"),
+    (KeyCode::NumPad5, "NumPad5"),
+    (KeyCode::NumPad6, "NumPad6"),
+    (KeyCode::NumPad7, "NumPad7"),
+    (KeyCode::NumPad8, "NumPad8"),
+    (KeyCode::NumPad9, "NumPad9"),
+    (KeyCode::F1, "F1"),
+    (KeyCode::F2, "F2"),
+    (KeyCode::F3, "F3"),
+    (KeyCode::F4, "F4"),
+    (KeyCode::F5, "F5"),
+    (KeyCode::F6, "F6"),
+    (KeyCode::F7, "F7"),
+    (KeyCode::F8, "F8"),
+    (KeyCode::F9, "F9"),
+    (KeyCode::F10, "F10"),
+    (KeyCode::F11, "F11"),
+    (KeyCode::F12, "F12"),
+];
+
+/// Something the player can do with a key press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Wait,
+    Rest,
+    Search,
+    Sneak,
+    PickUp,
+    Inventory,
+    Examine,
+    Drop,
+    Throw,
+    Craft,
+    Fire,
+    QuickFire,
+    Open,
+    Descend,
+    Ascend,
+    Look,
+    Character,
+    MessageLog,
+    Minimap,
+    FilterMessages,
+}
+
+impl Action {
+    pub const ALL: [Action; 24] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Wait,
+        Action::Rest,
+        Action::Search,
+        Action::Sneak,
+        Action::PickUp,
+        Action::Inventory,
+        Action::Examine,
+        Action::Drop,
+        Action::Throw,
+        Action::Craft,
+        Action::Fire,
+        Action::QuickFire,
+        Action::Open,
+        Action::Descend,
+        Action::Ascend,
+        Action::Look,
+        Action::Character,
+        Action::MessageLog,
+        Action::Minimap,
+        Action::FilterMessages,
+    ];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Wait => "Wait a turn",
+            Action::Rest => "Rest",
+            Action::Search => "Search for traps and rubble",
+            Action::Sneak => "Toggle sneaking",
+            Action::PickUp => "Pick up",
+            Action::Inventory => "Inventory",
+            Action::Examine => "Examine an item",
+            Action::Drop => "Drop",
+            Action::Throw => "Throw",
+            Action::Craft => "Combine items",
+            Action::Fire => "Fire a ranged weapon",
+            Action::QuickFire => "Fire at the last target",
+            Action::Open => "Open a chest",
+            Action::Descend => "Descend stairs",
+            Action::Ascend => "Climb stairs",
+            Action::Look => "Look around",
+            Action::Character => "Character info",
+            Action::MessageLog => "Message log",
+            Action::Minimap => "Toggle minimap",
+            Action::FilterMessages => "Filter messages",
+        }
+    }
+}
+
+/// A key, by its printable character or the name of a special key, and
+/// whether Shift is held with it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyBinding {
+    pub key: String,
+    pub shift: bool,
+}
+
+impl KeyBinding {
+    fn new(key: &str, shift: bool) -> Self {
+        KeyBinding {
+            key: key.into(),
+            shift,
+        }
+    }
+
+    /// The binding for a pressed key, if it's one that can be bound.
+    pub fn from_key(key: Key) -> Option<Self> {
+        if key.code != KeyCode::Spacebar && key.printable.is_ascii_graphic() {
+            return Some(KeyBinding {
+                key: key.printable.to_string(),
+                shift: key.shift,
+            });
+        }
+        SPECIAL_KEYS.iter()
+            .find(|&&(code, _)| code == key.code)
+            .map(|&(_, name)| KeyBinding::new(name, key.shift))
+    }
+
+    /// Whether the pressed key is this one, leaving Shift out of it.
+    fn matches_ignoring_shift(&self, key: Key) -> bool {
+        let mut chars = self.key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(printable), None) => key.printable == printable,
+            _ => SPECIAL_KEYS.iter().any(|&(code, name)| code == key.code && name == self.key),
+        }
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.shift {
+            write!(f, "Shift+{}", self.key)
+        } else {
+            write!(f, "{}", self.key)
+        }
+    }
+}
+
+/// Which key does what, loaded from `keys.json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyBindings(BTreeMap<Action, KeyBinding>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut bindings = BTreeMap::new();
+        for &action in Action::ALL.iter() {
+            let binding = match action {
+                Action::MoveUp => KeyBinding::new("Up", false),
+                Action::MoveDown => KeyBinding::new("Down", false),
+                Action::MoveLeft => KeyBinding::new("Left", false),
+                Action::MoveRight => KeyBinding::new("Right", false),
+                Action::Wait => KeyBinding::new(".", false),
+                Action::Rest => KeyBinding::new("r", true),
+                Action::Search => KeyBinding::new("s", false),
+                Action::Sneak => KeyBinding::new("s", true),
+                Action::PickUp => KeyBinding::new("g", false),
+                Action::Inventory => KeyBinding::new("i", false),
+                Action::Examine => KeyBinding::new("e", false),
+                Action::Drop => KeyBinding::new("d", false),
+                Action::Throw => KeyBinding::new("t", false),
+                Action::Craft => KeyBinding::new("c", true),
+                Action::Fire => KeyBinding::new("f", false),
+                Action::QuickFire => KeyBinding::new("v", false),
+                Action::Open => KeyBinding::new("o", false),
+                Action::Descend => KeyBinding::new(".", true),
+                Action::Ascend => KeyBinding::new(",", true),
+                Action::Look => KeyBinding::new("x", false),
+                Action::Character => KeyBinding::new("c", false),
+                Action::MessageLog => KeyBinding::new("m", false),
+                Action::Minimap => KeyBinding::new("m", true),
+                Action::FilterMessages => KeyBinding::new("f", true),
+            };
+            bindings.insert(action, binding);
+        }
+        KeyBindings(bindings)
+    }
+}
+
+impl KeyBindings {
+    /// Load the key bindings. A missing or corrupt file gives the defaults,
+    /// and so does any action missing from the file.
+    pub fn load() -> Self {
+        match read_key_bindings() {
+            Ok(loaded) => KeyBindings::over_defaults(loaded),
+            Err(_) => KeyBindings::default(),
+        }
+    }
+
+    /// Lay bindings over the defaults. Where a key ends up doing two things,
+    /// like one an older file bound that the game has since given to a new
+    /// action, the action it was rebound for goes back to its default key.
+    fn over_defaults(KeyBindings(loaded): KeyBindings) -> Self {
+        let defaults = KeyBindings::default();
+        let mut bindings = defaults.clone();
+        bindings.0.extend(loaded);
+        while let Some(action) = bindings.rebound_clashing(&defaults) {
+            bindings.0.insert(action, defaults.0[&action].clone());
+        }
+        bindings
+    }
+
+    /// An action that isn't on its default key, and shares its key with
+    /// another action.
+    fn rebound_clashing(&self, defaults: &KeyBindings) -> Option<Action> {
+        self.0.iter()
+            .filter(|&(&action, binding)| defaults.get(action) != Some(binding))
+            .find(|&(&action, binding)| self.0.iter().any(|(&other, other_binding)| {
+                other != action && other_binding == binding
+            }))
+            .map(|(&action, _)| action)
+    }
+
+    pub fn save(&self) -> Result<(), Box<Error>> {
+        let mut file = File::create(format!("{}.tmp", KEYS_FILE))?;
+        file.write_all(json::to_string_pretty(self)?.as_bytes())?;
+        fs::rename(format!("{}.tmp", KEYS_FILE), KEYS_FILE)?;
+        Ok(())
+    }
+
+    pub fn get(&self, action: Action) -> Option<&KeyBinding> {
+        self.0.get(&action)
+    }
+
+    /// The action bound to a pressed key. A binding with the same Shift state
+    /// wins, otherwise Shift is ignored.
+    pub fn action_for(&self, key: Key) -> Option<Action> {
+        let matching = || self.0.iter().filter(move |&(_, binding)| binding.matches_ignoring_shift(key));
+        matching().find(|&(_, binding)| binding.shift == key.shift)
+            .or_else(|| matching().next())
+            .map(|(&action, _)| action)
+    }
+
+    /// Bind a key to an action, unless another action already uses it. In
+    /// that case, return the other action.
+    pub fn bind(&mut self, action: Action, binding: KeyBinding) -> Result<(), Action> {
+        let taken_by = self.0.iter()
+            .find(|&(&other, other_binding)| other != action && *other_binding == binding)
+            .map(|(&other, _)| other);
+        match taken_by {
+            Some(other) => Err(other),
+            None => {
+                self.0.insert(action, binding);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn read_key_bindings() -> Result<KeyBindings, Box<Error>> {
+    let mut json_keys = String::new();
+    let mut file = File::open(KEYS_FILE)?;
+    file.read_to_string(&mut json_keys)?;
+    Ok(json::from_str(&json_keys)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_filter_binding_gives_way_to_fire() {
+        let mut loaded = BTreeMap::new();
+        loaded.insert(Action::FilterMessages, KeyBinding::new("f", false));
+        loaded.insert(Action::Look, KeyBinding::new("l", false));
+        let bindings = KeyBindings::over_defaults(KeyBindings(loaded));
+        assert_eq!(bindings.get(Action::Fire), Some(&KeyBinding::new("f", false)));
+        assert_eq!(bindings.get(Action::FilterMessages), Some(&KeyBinding::new("f", true)));
+        // Bindings that don't clash stay as they were.
+        assert_eq!(bindings.get(Action::Look), Some(&KeyBinding::new("l", false)));
+    }
+}
@@ -7,10 +7,14 @@ extern crate tcod;
 
 use std::ascii::AsciiExt;
 use std::error::Error;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::thread;
+use std::time::Duration;
 
 use rand::Rng;
+use rand::distributions::{IndependentSample, WeightedChoice};
 use tcod::{BackgroundFlag, Console, TextAlignment};
 use tcod::colors::{self, Color};
 use tcod::console::{self, Root, Offscreen};
@@ -18,13 +22,48 @@ use tcod::input::{self, Event, Key, KeyCode, Mouse};
 use tcod::map::{Map as FovMap, FovAlgorithm};
 use tcod::pathfinding::AStar;
 
-use map::Map;
-use message::Messages;
+use artifact::Artifact;
+use boss::PhaseEffect;
+use branch::{Branch, Location};
+use config::{AutoPickup, Config, Layout};
+use equipment::{Equipment, Slot};
+use feature::Feature;
+use highscore::HighScore;
+use keys::{Action, KeyBinding, KeyBindings};
+use map::{Map, Modifier, Terrain, Tile, Transition};
+use message::{MessageKind, Messages};
+use monster::MonsterCatalog;
 use object::*;
-
+use overworld::Overworld;
+
+mod affix;
+mod alchemy;
+mod arena;
+mod artifact;
+mod boss;
+mod branch;
+mod bsp;
+mod cave;
+mod config;
+mod crafting;
+mod descriptions;
+mod equipment;
+mod feature;
+mod highscore;
+mod keys;
 mod map;
+mod maze;
 mod message;
+mod monster;
 mod object;
+mod overworld;
+mod rarity;
+mod ruins;
+mod save;
+mod spawn;
+mod theme;
+mod town;
+mod vault;
 
 const SCREEN_WIDTH: i32 = 80;
 const SCREEN_HEIGHT: i32 = 50;
@@ -35,52 +74,208 @@ const CAMERA_HEIGHT: i32 = 43;
 
 const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
 const FOV_LIGHT_WALLS: bool = true;
-const TORCH_RADIUS: i32 = 10;
-
-const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
-const COLOR_LIGHT_WALL: Color = Color { r: 130, g: 110, b: 50 };
-const COLOR_DARK_GROUND: Color = Color { r: 50, g: 50, b: 150 };
-const COLOR_LIGHT_GROUND: Color = Color { r: 200, g: 180, b: 50 };
+// How far the player sees with no light, or with their light burnt out.
+const DARK_SIGHT_RADIUS: i32 = 2;
+// How far the player sees in magical darkness, whatever light they carry.
+const MAGICAL_DARKNESS_SIGHT_RADIUS: i32 = 1;
+// How much less far the player sees on dim levels, though never blind.
+const DIM_SIGHT_PENALTY: i32 = 3;
+
+// Whether monsters may step diagonally, both when pathfinding and when
+// falling back to moving straight at their target.
+const ENEMIES_MOVE_DIAGONAL: bool = false;
+
+// Monster AI.
+const CHASE_TURNS: i32 = 10;
+const WANDER_CHANCE: u32 = 25;
+const FLEE_TURNS: i32 = 5;
+// How far ranged monsters keep from the player: they back off when closer,
+// and come closer when further.
+const RANGED_MIN_DISTANCE: i32 = 3;
+const RANGED_MAX_DISTANCE: i32 = 6;
+const MONSTER_SIGHT_RADIUS: i32 = 8;
+// How long a way round a pack monster takes to its spot beside the player,
+// before just heading straight at them.
+const FLANK_PATH_LENGTH: i32 = 25;
+// How close the player has to come, in plain sight, to wake a sleeping
+// monster, and how close when sneaking for any monster not after them yet to
+// notice them.
+const WAKE_DISTANCE: i32 = 4;
+const SNEAK_NOTICE_DISTANCE: i32 = 2;
+const INVESTIGATE_TURNS: i32 = 10;
+// How many steps the noise of a fight, of glass shattering and of running
+// carries.
+const FIGHT_NOISE_RADIUS: i32 = 5;
+const SHATTER_NOISE_RADIUS: i32 = 8;
+const RUN_NOISE_RADIUS: i32 = 3;
+// How many turns the player's scent lingers on a tile.
+const SCENT_TURNS: u32 = 30;
+const MONSTER_INVENTORY_SIZE: usize = 3;
+// How far from a boss the monsters it calls show up.
+const SUMMON_RADIUS: i32 = 2;
+const BANNER_WIDTH: i32 = 40;
+
+// What the healer in town charges, in gold per hit point.
+const HEAL_PRICE: u32 = 1;
+// One slot per letter in the inventory menu. A stack takes up a single slot.
+const INVENTORY_SIZE: usize = 26;
+const CARDINAL_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
 
 // Sizes and coordinates relevant for the GUI.
 const BAR_WIDTH: i32 = 20;
 const PANEL_HEIGHT: i32 = 7;
-const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 
-const MSG_X: i32 = BAR_WIDTH + 2;
-const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
 const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 
 const INVENTORY_WIDTH: i32 = 50;
+const SHOP_WIDTH: i32 = 50;
+const CHEST_WIDTH: i32 = 50;
+
+// The minimap squeezes the whole map into a box in the top-right corner.
+const MINIMAP_WIDTH: i32 = 40;
+const MINIMAP_HEIGHT: i32 = 40;
 
 // Item constants.
 const HEAL_AMOUNT: i32 = 40;
+const GREATER_HEAL_AMOUNT: i32 = 100;
+// A botched potion mixture blows up in the player's face.
+const ALCHEMY_EXPLOSION_RADIUS: i32 = 1;
+const ALCHEMY_EXPLOSION_DAMAGE: i32 = 15;
 const LIGHTNING_DAMAGE: i32 = 40;
 const LIGHTNING_RANGE: i32 = 5;
+const LIGHTNING_FLASH_RADIUS: i32 = 4;
 const CONFUSE_RANGE: i32 = 8;
 const CONFUSE_NUM_TURNS: i32 = 10;
+const PLAYER_CONFUSE_NUM_TURNS: i32 = 5;
 const FIREBALL_RADIUS: i32 = 3;
 const FIREBALL_DAMAGE: i32 = 25;
+const DIGGING_RANGE: i32 = 8;
+const SUNSWORD_RADIUS_BONUS: i32 = 3;
+const LAVA_DAMAGE: i32 = 20;
+const FALL_DAMAGE: i32 = 10;
+// Tries to find a spot to land on after a fall, before landing by the stairs.
+const LANDING_TRIES: u32 = 100;
+// Percent chance a troll's blow knocks the player back a step.
+const KNOCKBACK_CHANCE: u32 = 25;
+// Percent chance each turn in deep water that an item slips out of the pack.
+const SINK_CHANCE: u32 = 20;
+const LOW_FUEL_TURNS: u32 = 25;
+const THROW_RANGE: i32 = 6;
+const SPLASH_RADIUS: i32 = 1;
+const DAGGER_THROW_DAMAGE: i32 = 10;
+const FIRE_RANGE: i32 = 8;
+const CHEST_BASH_CHANCE: u32 = 40;
+// Chance, in percent, that a shot arrow or stone breaks instead of landing.
+const AMMO_BREAK_CHANCE: u32 = 30;
+const PROJECTILE_FRAME_MS: u64 = 25;
+
+// Traps.
+const BEAR_TRAP_DAMAGE: i32 = 6;
+const DART_TRAP_DAMAGE: i32 = 10;
+const PIT_DAMAGE: i32 = 8;
+const PIT_TURNS: i32 = 3;
+const ALARM_RADIUS: i32 = 20;
+// Chance to notice a hidden trap next to the player each turn, and to find
+// one within the radius when searching.
+const TRAP_SPOT_CHANCE: u32 = 10;
+const SEARCH_RADIUS: i32 = 2;
+const SEARCH_CHANCE: u32 = 60;
+// Chance that rubble the player searches through has something buried in it.
+const RUBBLE_FIND_CHANCE: u32 = 30;
+
+// Resting and natural regeneration: every `REGEN_INTERVAL` turns, wounded
+// fighters recover a hit point.
+const REST_MAX_TURNS: i32 = 100;
+const REGEN_INTERVAL: u32 = 10;
+const MONSTERS_REGENERATE: bool = true;
+
+// New monsters keep wandering into a level, though only so many at once, and
+// never where the player would see them show up.
+const REPOPULATE_MAX_MONSTERS: usize = 20;
+const REPOPULATE_MIN_DISTANCE: f32 = 15.0;
+const REPOPULATE_TRIES: u32 = 50;
+
+// Longest path the player will follow when walking to a clicked tile.
+const MOUSE_WALK_MAX_PATH: i32 = 50;
 
 // Experience and level-ups.
 const LEVEL_UP_BASE: i32 = 200;
 const LEVEL_UP_FACTOR: i32 = 150;
 const LEVEL_SCREEN_WIDTH: i32 = 40;
-const CHARACTER_SCREEN_WIDTH: i32 = 30;
+const CHARACTER_SCREEN_WIDTH: i32 = 40;
+const QUANTITY_WIDTH: i32 = 40;
+const HIGH_SCORES_WIDTH: i32 = 64;
+const KEY_BINDINGS_WIDTH: i32 = 44;
+const MAX_NAME_LENGTH: usize = 16;
+
+const SAVE_FILE: &str = "savegame";
 
 // Player will always be the first object.
 const PLAYER: usize = 0;
 
+/// Reasons `mut_two` can fail to borrow two elements.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MutTwoError {
+    /// Both indexes point at the same element.
+    SameIndex(usize),
+    /// One of the indexes is past the end of the slice.
+    OutOfBounds { index: usize, len: usize },
+}
+
 /// Mutably borrow two *separate* elements from the given slice.
-/// Panics when the indexes are equal or out of bounds.
-fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert!(first_index != second_index);
+/// Fails when the indexes are equal or out of bounds.
+fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T])
+              -> Result<(&mut T, &mut T), MutTwoError> {
+    if first_index == second_index {
+        return Err(MutTwoError::SameIndex(first_index));
+    }
+    let len = items.len();
+    for &index in &[first_index, second_index] {
+        if index >= len {
+            return Err(MutTwoError::OutOfBounds { index, len });
+        }
+    }
+
     let split_at_index = std::cmp::max(first_index, second_index);
     let (first_slice, second_slice) = items.split_at_mut(split_at_index);
     if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
+        Ok((&mut first_slice[first_index], &mut second_slice[0]))
     } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
+        Ok((&mut second_slice[0], &mut first_slice[second_index]))
+    }
+}
+
+#[cfg(test)]
+mod mut_two_tests {
+    use super::{mut_two, MutTwoError};
+
+    #[test]
+    fn borrows_in_order() {
+        let mut items = [1, 2, 3];
+        assert_eq!(mut_two(0, 2, &mut items), Ok((&mut 1, &mut 3)));
+    }
+
+    #[test]
+    fn swapped_indexes_keep_their_order() {
+        let mut items = [1, 2, 3];
+        let (first, second) = mut_two(2, 0, &mut items).unwrap();
+        assert_eq!((*first, *second), (3, 1));
+        *first = 30;
+        *second = 10;
+        assert_eq!(items, [10, 2, 30]);
+    }
+
+    #[test]
+    fn same_index_fails() {
+        let mut items = [1, 2, 3];
+        assert_eq!(mut_two(1, 1, &mut items), Err(MutTwoError::SameIndex(1)));
+    }
+
+    #[test]
+    fn out_of_bounds_fails() {
+        let mut items = [1, 2, 3];
+        assert_eq!(mut_two(3, 0, &mut items), Err(MutTwoError::OutOfBounds { index: 3, len: 3 }));
+        assert_eq!(mut_two(0, 5, &mut items), Err(MutTwoError::OutOfBounds { index: 5, len: 3 }));
     }
 }
 
@@ -91,8 +286,19 @@ enum PlayerAction {
     Exit,
 }
 
+/// Where the player turns up on another level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Arrival<'a> {
+    /// On the stairs with the given name. With several of them, on the one
+    /// with the given number, paired with the stairs taken.
+    Stairs(&'a str, usize),
+    /// Anywhere open, having fallen from above.
+    Landing,
+}
+
 enum UseResult {
     UsedUp,
+    UsedAndKept,
     Cancelled,
 }
 
@@ -100,38 +306,133 @@ struct Tcod {
     root: Root,
     con: Offscreen,
     panel: Offscreen,
+    minimap: Offscreen,
+    animate_projectiles: bool,
+    auto_pickup: AutoPickup,
 }
 
+impl Tcod {
+    /// Resize the offscreen consoles to match the root console, in case the
+    /// window was resized. Returns the root console's size.
+    fn fit_to_root(&mut self) -> (i32, i32) {
+        let (width, height) = (self.root.get_width(), self.root.get_height());
+        if self.con.get_width() != width || self.con.get_height() != height {
+            self.con = Offscreen::new(width, height);
+            self.panel = Offscreen::new(width, PANEL_HEIGHT);
+        }
+        (width, height)
+    }
+}
+
+fn default_camera_size() -> (i32, i32) {
+    (CAMERA_WIDTH, CAMERA_HEIGHT)
+}
+
+/// A stand-in, until `initialize_fov` sizes the FOV maps to the level.
 fn default_fov_map() -> FovMap {
-    FovMap::new(map::MAP_WIDTH, map::MAP_HEIGHT)
+    FovMap::new(1, 1)
 }
 
+/// Everything about the game in progress.
+///
+/// Methods that don't take a `Tcod` only work on the game state, so they're
+/// safe to call headless, without a window: scripted moves, item effects and
+/// monster turns. The ones that do take it need a window to ask the player
+/// for a target or show a menu.
 #[derive(Serialize, Deserialize)]
 struct GameState {
     // Serialized state.
+    version: u32,
     objects: Vec<Object>,
     map: Map,
     // TODO: Rename to log.
     messages: Messages,
     inventory: Vec<Object>,
-    dungeon_level: u32,
+    /// Where the player is: the town, or which level of which dungeon.
+    location: Location,
+    /// Levels the player has left.
+    levels: Vec<map::Level>,
+    /// The wilderness around the town, with the other dungeons in it.
+    overworld: Overworld,
+    /// Whether the player has found the relic, and is on the way back up.
+    ascending: bool,
+    turn: u32,
+    /// Sight radius gained from leveling up, added to the light's.
+    perception: i32,
+    player_confused_turns: i32,
+    /// Whether the player is sneaking: moving at half speed, but unnoticed
+    /// by monsters not after them yet until close.
+    sneaking: bool,
+    gold: u32,
+    /// Artifacts generated so far this game, which never show up again.
+    artifacts: Vec<Artifact>,
+    /// Every level of the run is generated from it, so that a run can be
+    /// replayed or shared.
+    seed: u64,
+    /// Decides which potions mix well in this run.
+    alchemy_seed: u32,
+    /// Potion mixtures tried so far, by ingredient names, and whether they worked.
+    known_mixtures: Vec<(String, String, bool)>,
+    /// Names of the items bound to the number keys 1 to 9. Going by name
+    /// keeps them bound to the same items as the inventory gets reordered.
+    hotkeys: [Option<String>; 9],
 
     #[serde(skip, default = "default_fov_map")]
     fov_map: FovMap,
+    /// Scratch FOV map, for what monsters and blasts can see.
+    #[serde(skip, default = "default_fov_map")]
+    sight_map: FovMap,
+    /// Walkability of the map tiles, shared by every monster's pathfinding.
+    #[serde(skip, default = "default_fov_map")]
+    path_map: FovMap,
+    /// Where to run from the player, shared by every fleeing monster. Made
+    /// the first time one needs it in a turn.
+    #[serde(skip)]
+    flee_map: Option<Vec<Vec<Option<i32>>>>,
     #[serde(skip)]
     camera_pos: (i32, i32),
+    /// Size of the map view, which follows the window size.
+    #[serde(skip, default = "default_camera_size")]
+    camera_size: (i32, i32),
     #[serde(skip)]
     previous_player_pos: (i32, i32),
     #[serde(skip)]
     mouse: Mouse,
     #[serde(skip)]
     disable_fov: bool,
+    #[serde(skip)]
+    killed_by: Option<String>,
+    #[serde(skip)]
+    look_cursor: Option<(i32, i32)>,
+    #[serde(skip)]
+    show_minimap: bool,
+    /// Where the player is walking to, after clicking on a tile.
+    #[serde(skip)]
+    walk_target: Option<(i32, i32)>,
+    /// Only show warnings and worse in the panel.
+    #[serde(skip)]
+    hide_routine_messages: bool,
+    /// The shopkeeper, healer or chest the player just bumped into, to deal
+    /// with or open.
+    #[serde(skip)]
+    bumped_into: Option<usize>,
+    /// The monster last shot at, for quick shots. It follows the monster as
+    /// objects are removed, and is forgotten on leaving the level. It's still
+    /// checked again before every shot.
+    #[serde(skip)]
+    last_target: Option<usize>,
+    /// Set once the player climbs out of the dungeon with the relic.
+    #[serde(skip)]
+    escaped: bool,
+    /// Set when the player sets out from the town, to pick where to go.
+    #[serde(skip)]
+    travelling: bool,
 }
 
 impl GameState {
-    fn new() -> Self {
+    fn new(player_name: &str, seed: u64) -> Self {
         // Create the player.
-        let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
+        let mut player = Object::new(0, 0, '@', player_name, colors::WHITE, true);
         player.alive = true;
         player.fighter = Some(Fighter {
             max_hp: 100,
@@ -139,29 +440,73 @@ impl GameState {
             defense: 1,
             power: 4,
             xp: 0,
+            flee_below: 0,
             on_death: DeathCallback::Player,
         });
         let mut objects = vec![player];
-        let dungeon_level = 1;
-        let map = map::make_map(&mut objects, dungeon_level);
+        let location = Location::start();
+        let mut artifacts = Vec::new();
+        let map = map::make_map(&mut objects, location, &mut artifacts, seed);
+
+        // Start out with a lit torch.
+        let mut torch = map::make_item(Item::Torch, 0, 0, location.level(), &mut rand::thread_rng());
+        if let Some(ref mut equipment) = torch.equipment {
+            equipment.equipped = true;
+        }
 
-        let mut messages = Messages::new(MSG_HEIGHT);
+        let mut messages = Messages::new();
 
         // A warm welcoming message!
         messages.message("Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.", colors::RED);
+        messages.message("Their crown lies at the very bottom. Bring it back up, and the glory is yours.",
+                         colors::GOLD);
+        messages.message(map.ambience.clone(), map.theme.message_color);
+        for modifier in &map.modifiers {
+            messages.message_kind(modifier.message(), colors::LIGHT_VIOLET, MessageKind::Warning);
+        }
+        if let Some(feeling) = map.feeling {
+            messages.message_kind(feeling.message(), colors::LIGHT_VIOLET, MessageKind::Warning);
+        }
 
         let mut game_state = GameState {
+            version: save::VERSION,
             objects,
             map,
             messages,
-            inventory: Vec::new(),
-            dungeon_level,
+            inventory: vec![torch],
+            location,
+            levels: Vec::new(),
+            overworld: Overworld::new(seed),
+            ascending: false,
+            turn: 0,
+            perception: 0,
+            player_confused_turns: 0,
+            sneaking: false,
+            gold: 0,
+            artifacts,
+            seed,
+            alchemy_seed: seed as u32,
+            known_mixtures: Vec::new(),
+            hotkeys: Default::default(),
 
             fov_map: default_fov_map(),
+            sight_map: default_fov_map(),
+            path_map: default_fov_map(),
+            flee_map: None,
             camera_pos: (0, 0),
+            camera_size: default_camera_size(),
             previous_player_pos: (-1, -1),
             mouse: Default::default(),
             disable_fov: false,
+            killed_by: None,
+            look_cursor: None,
+            show_minimap: false,
+            walk_target: None,
+            hide_routine_messages: false,
+            bumped_into: None,
+            last_target: None,
+            escaped: false,
+            travelling: false,
         };
         game_state.initialize_fov();
         game_state
@@ -169,48 +514,665 @@ impl GameState {
 
     fn from_save() -> Result<Self, Box<Error>> {
         let mut json_save_state = String::new();
-        let mut file = File::open("savegame")?;
+        let mut file = File::open(SAVE_FILE)?;
         file.read_to_string(&mut json_save_state)?;
-        let mut result: Self = json::from_str(&json_save_state)?;
+        // Bring saves from older versions up to date before loading them.
+        let mut save_data: json::Value = json::from_str(&json_save_state)?;
+        save::migrate(&mut save_data)?;
+        let mut result: Self = json::from_value(save_data)?;
         result.initialize_fov();
         Ok(result)
     }
 
     fn save(&self) -> Result<(), Box<Error>> {
         let save_data = json::to_string(self)?;
-        let mut file = File::create("savegame")?;
-        file.write_all(save_data.as_bytes())?;
+        // Write to a temporary file and move it over the real save, so dying
+        // mid-write can't corrupt it.
+        let temp_file = format!("{}.tmp", SAVE_FILE);
+        {
+            let mut file = File::create(&temp_file)?;
+            file.write_all(save_data.as_bytes())?;
+        }
+        fs::rename(&temp_file, SAVE_FILE)?;
         Ok(())
     }
 
+    /// Save the game, reporting how it went in the message log.
+    fn autosave(&mut self) {
+        match self.save() {
+            Ok(()) => self.messages.message("Game saved.", colors::LIGHT_GREY),
+            Err(err) => self.messages.message_kind(format!("Could not save the game: {}", err),
+                                                   colors::RED, MessageKind::Critical),
+        }
+    }
+
+    /// Remove the save file, so a dead character can't be loaded again.
+    fn delete_save() -> Result<(), Box<Error>> {
+        match fs::remove_file(SAVE_FILE) {
+            Err(ref e) if e.kind() != io::ErrorKind::NotFound => Err(e.to_string().into()),
+            _ => Ok(()),
+        }
+    }
+
     fn initialize_fov(&mut self) {
-        // Initialize the FOV map.
-        for y in 0..map::MAP_HEIGHT {
-            for x in 0..map::MAP_WIDTH {
-                self.fov_map.set(x, y,
-                                 !self.map[x as usize][y as usize].block_sight,
-                                 !self.map[x as usize][y as usize].blocked);
+        // Initialize the FOV maps, as big as the level.
+        let (width, height) = (self.map.width, self.map.height);
+        self.fov_map = FovMap::new(width, height);
+        self.sight_map = FovMap::new(width, height);
+        self.path_map = FovMap::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let tile = &self.map[(x, y)];
+                self.fov_map.set(x, y, !tile.block_sight, !tile.blocked);
+                self.sight_map.set(x, y, !tile.block_sight, !tile.blocked);
             }
         }
+        self.update_path_map();
     }
 
-    /// Advance to the next level
-    fn next_level(&mut self) {
-        self.messages.message("You take a moment to rest, and recover your strength.", colors::VIOLET);
-        let heal_hp = self.objects[PLAYER].fighter.map_or(0, |f| f.max_hp / 2);
-        self.objects[PLAYER].heal(heal_hp);
+    /// Go to another level. A level the player has been on before is restored
+    /// just as they left it, and a new one is generated.
+    fn change_level(&mut self, to: Location, arrival: Arrival) {
+        // Take everything but the player off the level being left.
+        let left_objects = self.objects.split_off(PLAYER + 1);
+        self.last_target = None;
+        let left_location = self.location;
+
+        self.location = to;
+        self.overworld.arrive(to);
+
+        let visited = self.levels.iter().position(|level| level.location == to);
+        let map = match visited.map(|id| self.levels.swap_remove(id)) {
+            Some(level) => {
+                let direction = if to.level() > left_location.level() { "down" } else { "up" };
+                self.messages.message(format!("You climb back {} to {}.", direction, to), colors::WHITE);
+                self.objects.extend(level.objects);
+                level.map
+            }
+            None => {
+                self.messages.message("You take a moment to rest, and recover your strength.", colors::VIOLET);
+                let heal_hp = self.max_hp(PLAYER) / 2;
+                let equipped = self.get_all_equipped(PLAYER);
+                self.objects[PLAYER].heal(heal_hp, &equipped);
+
+                self.messages.message("After a rare moment of peace, you descend deeper into \
+                                       the heart of the dungeon...", colors::RED);
+                let map = map::make_map(&mut self.objects, to, &mut self.artifacts, self.seed);
+                for modifier in &map.modifiers {
+                    self.messages.message_kind(modifier.message(), colors::LIGHT_VIOLET, MessageKind::Warning);
+                }
+                if let Some(feeling) = map.feeling {
+                    self.messages.message_kind(feeling.message(), colors::LIGHT_VIOLET, MessageKind::Warning);
+                }
+                map
+            }
+        };
+        if map.theme.name != self.map.theme.name {
+            self.messages.message(format!("You have entered the {}.", map.theme.name), map.theme.message_color);
+        }
+        self.messages.message(map.ambience.clone(), map.theme.message_color);
+        let left_map = mem::replace(&mut self.map, map);
+        self.levels.push(map::Level {
+            location: left_location,
+            map: left_map,
+            objects: left_objects,
+        });
 
-        self.messages.message("After a rare moment of peace, you descend deeper into \
-                               the heart of the dungeon...", colors::RED);
-        self.dungeon_level += 1;
-        self.map = map::make_map(&mut self.objects, self.dungeon_level);
+        let spot = match arrival {
+            // Arrive on the stairs that lead back.
+            Arrival::Stairs(name, number) => {
+                let arrivals = self.stairs_positions(name);
+                arrivals.get(number).or_else(|| arrivals.first()).cloned()
+            }
+            // Land somewhere the stairs up can be walked to from, so not
+            // shut in behind a locked door.
+            Arrival::Landing => {
+                let upstairs = self.stairs_positions("upstairs").first().cloned();
+                (0..LANDING_TRIES)
+                    .map(|_| self.random_open_tile())
+                    .find(|&spot| upstairs.map_or(true, |stairs| map::is_connected(&self.map, stairs, &[spot])))
+                    .or(upstairs)
+            }
+        };
+        if let Some((x, y)) = spot {
+            self.objects[PLAYER].set_pos(x, y);
+        }
         self.initialize_fov();
+        self.autosave();
+    }
+
+    /// Set out from the town to one of the dungeons in the wilderness.
+    fn travel(&mut self, to: Location) {
+        self.messages.message(format!("You follow the road out to the {}.", to.branch.name()), colors::LIGHT_SEPIA);
+        self.change_level(to, Arrival::Stairs("upstairs", 0));
+    }
+
+    /// Where the objects with the given name are, like stairs, in order of
+    /// position. Their order in the object list changes as objects come and
+    /// go, but the stairs stay put, so this numbers them the same every time.
+    fn stairs_positions(&self, name: &str) -> Vec<(i32, i32)> {
+        let mut positions: Vec<(i32, i32)> = self.objects.iter()
+            .filter(|object| object.name == name)
+            .map(Object::pos)
+            .collect();
+        positions.sort();
+        positions
+    }
+
+    /// The number of the stairs with the given name the player stands on.
+    fn stairs_number(&self, name: &str) -> usize {
+        let player_pos = self.objects[PLAYER].pos();
+        self.stairs_positions(name).iter().position(|&pos| pos == player_pos).unwrap_or(0)
+    }
+
+    /// Whether the player stands on an object with the given name, like stairs.
+    fn player_on(&self, name: &str) -> bool {
+        let player_pos = self.objects[PLAYER].pos();
+        self.objects.iter().any(|object| object.pos() == player_pos && object.name == name)
+    }
+
+    /// Summarize the current run for the high-score table.
+    fn high_score(&self) -> HighScore {
+        let player = &self.objects[PLAYER];
+        HighScore {
+            name: player.name.clone(),
+            dungeon_level: self.deepest_level(),
+            xp: player.fighter.map_or(0, |f| f.xp),
+            turns: self.turn,
+            cause_of_death: if self.escaped {
+                "escaped with the relic".into()
+            } else {
+                self.killed_by.clone().unwrap_or_else(|| "unknown causes".into())
+            },
+            won: self.escaped,
+        }
+    }
+
+    /// How deep the player has been in this run.
+    fn deepest_level(&self) -> u32 {
+        self.levels.iter().map(|level| level.location.level()).fold(self.location.level(), std::cmp::max)
     }
 
     fn is_blocked(&self, x: i32, y: i32) -> bool {
         map::is_blocked(x, y, &self.map, &self.objects)
     }
 
+    /// Pick a random tile that nothing is blocking.
+    fn random_open_tile(&self) -> (i32, i32) {
+        let mut rng = rand::thread_rng();
+        loop {
+            let x = rng.gen_range(0, self.map.width);
+            let y = rng.gen_range(0, self.map.height);
+            if !self.is_blocked(x, y) {
+                return (x, y);
+            }
+        }
+    }
+
+    /// Recompute what the player can see from where they stand.
+    fn compute_fov(&mut self) {
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        let radius = self.sight_radius();
+        self.fov_map.compute_fov(player_x, player_y, radius, FOV_LIGHT_WALLS, FOV_ALGO);
+
+        // Remember where items and chests were seen, to keep showing them out of sight.
+        for object in &mut self.objects {
+            if (object.item.is_some() || object.chest.is_some()) && self.fov_map.is_in_fov(object.x, object.y) {
+                object.always_visible = true;
+            }
+        }
+    }
+
+    /// How far the player can see by their light, if it's still burning, and
+    /// the Sunsword's light.
+    fn sight_radius(&self) -> i32 {
+        let light = self.get_equipped_in_slot(Slot::Light)
+            .map(|id| &self.inventory[id])
+            .filter(|light| light.fuel > 0)
+            .and_then(|light| light.item)
+            .and_then(|item| item.light_radius());
+        let mut radius = match light {
+            Some(light_radius) => light_radius + self.perception,
+            None => DARK_SIGHT_RADIUS,
+        };
+        if self.has_artifact(Artifact::Sunsword) {
+            radius += SUNSWORD_RADIUS_BONUS;
+        }
+        if self.map.modifiers.contains(&Modifier::Dim) {
+            radius = std::cmp::max(radius - DIM_SIGHT_PENALTY, 1);
+        }
+        // Magical darkness swallows any light.
+        let (x, y) = self.objects[PLAYER].pos();
+        if self.map[(x, y)].dark {
+            radius = std::cmp::min(radius, MAGICAL_DARKNESS_SIGHT_RADIUS);
+        }
+        radius
+    }
+
+    /// Light up the magical darkness around a spot for good.
+    fn dispel_darkness(&mut self, x: i32, y: i32, radius: i32) {
+        let mut dispelled = false;
+        for tile_x in (x - radius)..(x + radius + 1) {
+            for tile_y in (y - radius)..(y + radius + 1) {
+                let in_reach = (tile_x - x).pow(2) + (tile_y - y).pow(2) <= radius.pow(2);
+                if let Some(tile) = self.map.get_mut(tile_x, tile_y).filter(|tile| in_reach && tile.dark) {
+                    tile.dark = false;
+                    dispelled = true;
+                }
+            }
+        }
+        if dispelled {
+            self.messages.message("The flash burns away the darkness!", colors::LIGHT_YELLOW);
+            self.compute_fov();
+        }
+    }
+
+    /// Whether the player has the given artifact equipped.
+    fn has_artifact(&self, artifact: Artifact) -> bool {
+        self.get_all_equipped(PLAYER).iter().any(|e| e.artifact == Some(artifact))
+    }
+
+    /// Burn down the player's light by one turn.
+    fn burn_light(&mut self) {
+        let id = match self.get_equipped_in_slot(Slot::Light) {
+            Some(id) if self.inventory[id].fuel > 0 => id,
+            _ => return,
+        };
+        self.inventory[id].fuel -= 1;
+        let fuel = self.inventory[id].fuel;
+        let name = self.inventory[id].name.clone();
+        if fuel == LOW_FUEL_TURNS {
+            self.messages.message_kind(format!("Your {} is burning low.", name), colors::LIGHT_GREY,
+                                       MessageKind::Warning);
+        } else if fuel == 0 {
+            self.messages.message_kind(format!("Your {} flickers and goes out.", name), colors::LIGHT_GREY,
+                                       MessageKind::Warning);
+            self.compute_fov();
+        }
+    }
+
+    /// Damage the player with something other than a monster's attack.
+    fn hurt_player(&mut self, damage: i32, cause: &str) {
+        self.objects[PLAYER].take_damage(damage, &mut self.messages);
+        if !self.objects[PLAYER].alive {
+            self.killed_by = Some(cause.into());
+        }
+    }
+
+    /// Spring any undiscovered trap the player is standing on.
+    fn trigger_trap(&mut self) {
+        let floating = self.has_artifact(Artifact::BootsOfTheWisp);
+        let player_pos = self.objects[PLAYER].pos();
+        let trap_id = self.objects.iter().position(|object| {
+            object.pos() == player_pos && object.trap.map_or(false, |trap| !trap.revealed)
+        });
+        let trap = match trap_id.and_then(|id| self.objects[id].trap.as_mut()) {
+            Some(trap) => trap,
+            None => return,
+        };
+        // Now the player knows it's there.
+        trap.revealed = true;
+        if floating {
+            self.messages.message("Your boots carry you lightly over a hidden trap.", colors::ORANGE);
+            return;
+        }
+
+        match trap.kind {
+            TrapKind::BearTrap => {
+                self.messages.message_kind(
+                    format!("A bear trap snaps shut on your leg for {} hit points!", BEAR_TRAP_DAMAGE),
+                    colors::RED,
+                    MessageKind::Warning,
+                );
+                self.hurt_player(BEAR_TRAP_DAMAGE, "a bear trap");
+            }
+            TrapKind::Dart => {
+                self.messages.message_kind(
+                    format!("A dart shoots out of the wall for {} hit points!", DART_TRAP_DAMAGE),
+                    colors::RED,
+                    MessageKind::Warning,
+                );
+                self.hurt_player(DART_TRAP_DAMAGE, "a dart trap");
+            }
+            TrapKind::Teleport => {
+                self.messages.message_kind("The floor flashes, and you find yourself somewhere else!",
+                                           colors::LIGHT_VIOLET, MessageKind::Warning);
+                let (x, y) = self.random_open_tile();
+                self.objects[PLAYER].set_pos(x, y);
+                self.compute_fov();
+            }
+            TrapKind::Confusion => {
+                self.messages.message_kind("A cloud of strange gas bursts from the floor!", colors::LIGHT_GREEN,
+                                           MessageKind::Warning);
+                self.confuse_player(PLAYER_CONFUSE_NUM_TURNS);
+            }
+            TrapKind::Alarm => {
+                self.messages.message_kind("An alarm bell rings out through the dungeon!", colors::LIGHT_YELLOW,
+                                           MessageKind::Warning);
+                self.make_noise(player_pos, ALARM_RADIUS);
+            }
+            TrapKind::Pit => {
+                self.messages.message_kind(
+                    format!("You fall into a pit for {} hit points, and start climbing out.", PIT_DAMAGE),
+                    colors::RED,
+                    MessageKind::Warning,
+                );
+                let player = &mut self.objects[PLAYER];
+                player.slowed_turns = std::cmp::max(player.slowed_turns, PIT_TURNS);
+                self.hurt_player(PIT_DAMAGE, "a pit");
+            }
+        }
+    }
+
+    /// Confuse the player for at least the given number of turns.
+    fn confuse_player(&mut self, num_turns: i32) {
+        if self.has_artifact(Artifact::AmuletOfClarity) {
+            self.messages.message("Your amulet keeps your mind clear.", colors::ORANGE);
+            return;
+        }
+        if self.player_confused_turns == 0 {
+            self.messages.message_kind("You feel dizzy and confused!", colors::LIGHT_GREEN, MessageKind::Warning);
+        }
+        self.player_confused_turns = std::cmp::max(self.player_confused_turns, num_turns);
+    }
+
+    /// Count down the player's confusion, if any.
+    fn recover_from_confusion(&mut self) {
+        if self.player_confused_turns > 0 {
+            self.player_confused_turns -= 1;
+            if self.player_confused_turns == 0 {
+                self.messages.message("Your head clears, you are no longer confused.", colors::LIGHT_GREEN);
+            }
+        }
+    }
+
+    /// Hurt every fighter that's on fire, until it burns out.
+    fn burn(&mut self) {
+        for id in 0..self.objects.len() {
+            if self.objects[id].burning_turns == 0 {
+                continue;
+            }
+            self.objects[id].burning_turns -= 1;
+            if !self.objects[id].alive {
+                continue;
+            }
+            let message = format!("{} burns for {} hit points.", self.objects[id].name, BURN_DAMAGE);
+            self.messages.message_kind(message, colors::ORANGE, MessageKind::Combat);
+            if id == PLAYER {
+                self.hurt_player(BURN_DAMAGE, "flames");
+                if self.objects[PLAYER].alive && self.objects[PLAYER].burning_turns == 0 {
+                    self.messages.message("The flames on you die out.", colors::LIGHT_GREEN);
+                }
+            } else if let Some(xp) = self.objects[id].take_damage(BURN_DAMAGE, &mut self.messages) {
+                // Only the player's weapons set monsters on fire.
+                if let Some(fighter) = self.objects[PLAYER].fighter.as_mut() {
+                    fighter.xp += xp;
+                }
+            }
+        }
+    }
+
+    /// Count down how long fighters stay slowed.
+    fn thaw(&mut self, player_slowed: bool) {
+        for object in &mut self.objects {
+            if object.slowed_turns > 0 {
+                object.slowed_turns -= 1;
+            }
+        }
+        if player_slowed && self.objects[PLAYER].slowed_turns == 0 {
+            self.messages.message("You can move freely again.", colors::LIGHT_GREEN);
+        }
+    }
+
+    /// Give the player a chance to notice each hidden trap within some
+    /// distance of them. Returns whether any was found.
+    fn spot_traps(&mut self, radius: i32, chance: u32) -> bool {
+        let mut rng = rand::thread_rng();
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        let mut found = false;
+        for object in &mut self.objects {
+            let near = (object.x - player_x).abs() <= radius && (object.y - player_y).abs() <= radius;
+            if let Some(ref mut trap) = object.trap {
+                if near && !trap.revealed && rng.gen_range(0, 100) < chance {
+                    trap.revealed = true;
+                    found = true;
+                    self.messages.message_kind(format!("You spot a {}!", object.name), colors::LIGHT_CYAN,
+                                               MessageKind::Warning);
+                }
+            }
+        }
+        found
+    }
+
+    /// Everything that happens once the player has used up their turn.
+    fn end_turn(&mut self) {
+        self.turn += 1;
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        self.map[(player_x, player_y)].scent = Some(self.turn);
+        self.spot_traps(1, TRAP_SPOT_CHANCE);
+        self.burn_light();
+        self.regenerate();
+        self.recover_from_confusion();
+        self.burn();
+        self.terrain_effects();
+        self.update_path_map();
+
+        // Let monsters take their turn. Slowed monsters only act every other
+        // turn, and a slowed or sneaking player gives everyone else two.
+        // Wading through shallow water slows down just the same.
+        let player_slowed = self.objects[PLAYER].slowed_turns > 0;
+        let rounds = if self.is_slowed(PLAYER) || self.sneaking { 2 } else { 1 };
+        for _ in 0..rounds {
+            for id in 0..self.objects.len() {
+                let slowed = self.is_slowed(id) && self.turn % 2 == 0;
+                if self.objects[id].ai.is_some() && !slowed {
+                    self.ai_take_turn(id);
+                }
+            }
+        }
+        self.thaw(player_slowed);
+
+        // Clear away the items monsters picked up, keeping up with where the
+        // last target ends up.
+        let objects = &self.objects;
+        self.last_target = self.last_target
+            .filter(|&target| objects.get(target).map_or(false, |object| object.count > 0))
+            .map(|target| target - objects[..target].iter().filter(|object| object.count == 0).count());
+        self.objects.retain(|object| object.count > 0);
+
+        self.repopulate();
+
+        self.fall_into_chasm();
+    }
+
+    /// Every so often, more often deeper down, have a new monster show up
+    /// somewhere out of sight and away from the player, so that lingering on
+    /// a level isn't free. Not in town, nor once a level is crowded enough.
+    fn repopulate(&mut self) {
+        let level = self.location.level();
+        let interval = map::from_dungeon_level(&[
+            Transition {level: 1, value: 200},
+            Transition {level: 3, value: 150},
+            Transition {level: 5, value: 100},
+            Transition {level: 8, value: 75},
+        ], level);
+        if self.location.is_town() || interval == 0 || self.turn % interval != 0 {
+            return;
+        }
+        let monsters = self.objects.iter()
+            .filter(|object| object.alive && object.ai.as_ref().map_or(false, Ai::is_hostile))
+            .count();
+        if monsters >= REPOPULATE_MAX_MONSTERS {
+            return;
+        }
+
+        let spot = (0..REPOPULATE_TRIES)
+            .map(|_| self.random_open_tile())
+            .find(|&(x, y)| {
+                !self.fov_map.is_in_fov(x, y) && self.objects[PLAYER].distance(x, y) >= REPOPULATE_MIN_DISTANCE
+            });
+        let mut chances = MonsterCatalog::load().monsters(level, self.map.theme.kind);
+        if let Some((x, y)) = spot {
+            let mut rng = rand::thread_rng();
+            let kind = WeightedChoice::new(&mut chances).ind_sample(&mut rng);
+            let monster = map::make_monster(&kind, x, y, level, &mut rng);
+            self.objects.push(monster);
+        }
+    }
+
+    /// Drop the player to the level below if they're over a chasm, having
+    /// jumped or been knocked in. They land hurt, somewhere on it.
+    fn fall_into_chasm(&mut self) {
+        let (x, y) = self.objects[PLAYER].pos();
+        if !self.objects[PLAYER].alive || self.map[(x, y)].terrain != Terrain::Chasm {
+            return;
+        }
+        self.messages.message_kind("You fall through the dark...", colors::LIGHT_GREY, MessageKind::Warning);
+        let below = self.location.offset(1);
+        self.change_level(below, Arrival::Landing);
+        self.messages.message_kind("You land hard!", colors::ORANGE, MessageKind::Warning);
+        self.hurt_player(FALL_DAMAGE, "a fall");
+    }
+
+    /// Whether a fighter is slowed down, by cold or by wading.
+    fn is_slowed(&self, id: usize) -> bool {
+        let (x, y) = self.objects[id].pos();
+        self.objects[id].slowed_turns > 0 || self.map[(x, y)].terrain == Terrain::ShallowWater
+    }
+
+    /// Lava burns whoever stands in it, and things slip out of the pack of a
+    /// player swimming in deep water.
+    fn terrain_effects(&mut self) {
+        for id in 0..self.objects.len() {
+            let (x, y) = self.objects[id].pos();
+            if !self.objects[id].alive || self.map[(x, y)].terrain != Terrain::Lava {
+                continue;
+            }
+            if id == PLAYER {
+                self.messages.message_kind("The lava burns you!", colors::ORANGE, MessageKind::Warning);
+                self.hurt_player(LAVA_DAMAGE, "lava");
+            } else {
+                self.messages.message_kind(format!("The {} burns in the lava.", self.objects[id].name),
+                                           colors::ORANGE, MessageKind::Combat);
+                self.objects[id].take_damage(LAVA_DAMAGE, &mut self.messages);
+            }
+        }
+
+        let (x, y) = self.objects[PLAYER].pos();
+        let mut rng = rand::thread_rng();
+        if self.map[(x, y)].terrain == Terrain::DeepWater && rng.gen_range(0, 100) < SINK_CHANCE {
+            let loose: Vec<usize> = (0..self.inventory.len())
+                .filter(|&id| !self.inventory[id].equipment.map_or(false, |e| e.equipped))
+                .collect();
+            if let Some(&id) = rng.choose(&loose) {
+                let item = self.take_from_inventory(id);
+                self.messages.message_kind(format!("Your {} slips away and sinks.", item.name), colors::LIGHT_BLUE,
+                                           MessageKind::Warning);
+            }
+        }
+    }
+
+    /// Rebuild the pathfinding map from the tiles, once per turn, before the monsters move.
+    fn update_path_map(&mut self) {
+        self.flee_map = None;
+        for y in 0..self.map.height {
+            for x in 0..self.map.width {
+                let tile = &self.map[(x, y)];
+                self.path_map.set(x, y, !tile.block_sight, tile.is_walkable());
+            }
+        }
+    }
+
+    /// Every few turns, let wounded fighters recover a hit point. The Ring of
+    /// the Troll King heals the player every turn.
+    fn regenerate(&mut self) {
+        let troll_ring = self.has_artifact(Artifact::RingOfTheTrollKing);
+        for id in 0..self.objects.len() {
+            let due = self.turn % REGEN_INTERVAL == 0 || (id == PLAYER && troll_ring);
+            if due && self.objects[id].alive && (id == PLAYER || MONSTERS_REGENERATE) {
+                let equipped = self.get_all_equipped(id);
+                self.objects[id].heal(1, &equipped);
+            }
+        }
+    }
+
+    /// Whether any monster is in the player's FOV.
+    fn monster_in_view(&self) -> bool {
+        self.objects.iter().enumerate().any(|(id, object)| {
+            id != PLAYER && object.fighter.is_some() && object.ai.as_ref().map_or(false, Ai::is_hostile) &&
+                self.fov_map.is_in_fov(object.x, object.y)
+        })
+    }
+
+    /// Pass turns until the player is fully healed, gets hurt, or sees a
+    /// monster, up to `REST_MAX_TURNS`.
+    fn rest(&mut self) {
+        let hp = |game_state: &GameState| game_state.objects[PLAYER].fighter.map_or(0, |f| f.hp);
+        let max_hp = self.max_hp(PLAYER);
+        if hp(self) >= max_hp {
+            self.messages.message("You are already fully rested.", colors::WHITE);
+            return;
+        }
+        if self.monster_in_view() {
+            self.messages.message_kind("You cannot rest with enemies in sight!", colors::RED, MessageKind::Warning);
+            return;
+        }
+
+        let mut turns = 0;
+        while turns < REST_MAX_TURNS {
+            let hp_before = hp(self);
+            self.end_turn();
+            turns += 1;
+
+            if !self.objects[PLAYER].alive || hp(self) < hp_before {
+                self.messages.message_kind("Your rest is interrupted!", colors::RED, MessageKind::Warning);
+                break;
+            }
+            if self.monster_in_view() {
+                self.messages.message_kind("You spot an enemy and stop resting.", colors::RED, MessageKind::Warning);
+                break;
+            }
+            if hp(self) >= max_hp {
+                break;
+            }
+        }
+        self.messages.message(format!("You rest for {} turns.", turns), colors::LIGHT_GREY);
+    }
+
+    /// Ids of the fighters within `radius` of a tile that have a clear line of
+    /// sight to it, so walls shield whatever is behind them.
+    fn fighters_in_blast(&mut self, x: i32, y: i32, radius: i32) -> Vec<usize> {
+        self.sight_map.compute_fov(x, y, radius, FOV_LIGHT_WALLS, FOV_ALGO);
+        self.objects.iter()
+            .enumerate()
+            .filter(|&(_, obj)| {
+                obj.fighter.is_some() && obj.distance(x, y) <= radius as f32 &&
+                    self.sight_map.is_in_fov(obj.x, obj.y)
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Whether a monster not after the player yet notices them, within some
+    /// distance in plain sight. A sneaking player has to come closer.
+    fn monster_notices_player(&mut self, monster_id: usize, distance: i32) -> bool {
+        let distance = if self.sneaking { std::cmp::min(distance, SNEAK_NOTICE_DISTANCE) } else { distance };
+        self.objects[monster_id].distance_to(&self.objects[PLAYER]) <= distance as f32 &&
+            self.monster_sees_player(monster_id)
+    }
+
+    /// Whether a monster can see the player from where it stands. Monsters
+    /// have their own sight, so the player's torch doesn't give them away.
+    fn monster_sees_player(&mut self, monster_id: usize) -> bool {
+        let (monster_x, monster_y) = self.objects[monster_id].pos();
+        if self.objects[monster_id].distance_to(&self.objects[PLAYER]) > MONSTER_SIGHT_RADIUS as f32 {
+            return false;
+        }
+        self.sight_map.compute_fov(monster_x, monster_y, MONSTER_SIGHT_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        self.sight_map.is_in_fov(player_x, player_y)
+    }
+
     /// Find closest enemy, up to a maximum range, and in the player's FOV.
     fn closest_monster(&self, max_range: i32) -> Option<usize> {
         let mut closest_enemy = None;
@@ -218,7 +1180,7 @@ impl GameState {
         let mut closest_dist = (max_range + 1) as f32;
 
         for (id, object) in self.objects.iter().enumerate() {
-            if (id != PLAYER) && object.fighter.is_some() && object.ai.is_some() &&
+            if (id != PLAYER) && object.fighter.is_some() && object.ai.as_ref().map_or(false, Ai::is_hostile) &&
                 self.fov_map.is_in_fov(object.x, object.y) {
                 // Calculate distance between this object and the player.
                 let dist = self.objects[PLAYER].distance_to(object);
@@ -255,7 +1217,8 @@ impl GameState {
                     "Level up! Choose a stat to raise:\n",
                     &[format!("Constitution (+20 HP, from {})", fighter.max_hp),
                       format!("Strength (+1 attack, from {})", fighter.power),
-                      format!("Agility (+1 defense, from {})", fighter.defense)],
+                      format!("Agility (+1 defense, from {})", fighter.defense),
+                      format!("Perception (+1 sight radius, from {:+})", self.perception)],
                     LEVEL_SCREEN_WIDTH, &mut tcod.root,
                 );
             };
@@ -271,6 +1234,9 @@ impl GameState {
                 2 => {
                     fighter.defense += 1;
                 }
+                3 => {
+                    self.perception += 1;
+                }
                 _ => unreachable!(),
             }
         }
@@ -279,79 +1245,144 @@ impl GameState {
     /// Move an object by the given amount, if the destination is not blocked.
     fn move_object_by(&mut self, id: usize, dx: i32, dy: i32) {
         let (x, y) = self.objects[id].pos();
-        if !self.is_blocked(x + dx, y + dy) {
+        if self.can_enter(id, x + dx, y + dy) {
             self.objects[id].set_pos(x + dx, y + dy);
         }
     }
 
+    /// Whether an object can move onto a tile. Only the player goes into deep
+    /// water, lava or chasms, and flying monsters over chasms.
+    fn can_enter(&self, id: usize, x: i32, y: i32) -> bool {
+        let flies_over = self.objects[id].flying && self.map[(x, y)].terrain == Terrain::Chasm;
+        if id == PLAYER || flies_over {
+            !map::is_obstructed(x, y, &self.map, &self.objects)
+        } else {
+            !self.is_blocked(x, y)
+        }
+    }
+
     fn move_towards(&mut self, id: usize, target_x: i32, target_y: i32) {
         // Vector from this object to the target, and distance.
         let dx = target_x - self.objects[id].x;
         let dy = target_y - self.objects[id].y;
-        let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
 
-        // Normalize it to length 1 (preserving direction), then round it and
-        // convert to integer so the movement is restricted to the map grid.
-        let dx = (dx as f32 / distance).round() as i32;
-        let dy = (dy as f32 / distance).round() as i32;
+        if ENEMIES_MOVE_DIAGONAL {
+            let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
 
-        // TODO: Prevent moving diagonally.
+            // Normalize it to length 1 (preserving direction), then round it and
+            // convert to integer so the movement is restricted to the map grid.
+            let dx = (dx as f32 / distance).round() as i32;
+            let dy = (dy as f32 / distance).round() as i32;
 
-        self.move_object_by(id, dx, dy);
-    }
-
-    fn move_astar(&mut self, id: usize, target_id: usize) {
-        // Create a FOV map that has the dimensions of the map.
-        let mut fov_map = FovMap::new(map::MAP_WIDTH, map::MAP_HEIGHT);
+            self.move_object_by(id, dx, dy);
+        } else {
+            // Step along the axis with the larger distance, or along the other
+            // one if that tile is blocked.
+            let horizontal = (dx.signum(), 0);
+            let vertical = (0, dy.signum());
+            let steps = if dx.abs() >= dy.abs() {
+                [horizontal, vertical]
+            } else {
+                [vertical, horizontal]
+            };
 
-        // Scan the current map each turn and set all the walls as unwalkable.
-        for y in 0..map::MAP_HEIGHT {
-            for x in 0..map::MAP_WIDTH {
-                fov_map.set(x, y,
-                            !self.map[x as usize][y as usize].block_sight,
-                            !self.map[x as usize][y as usize].blocked);
+            let (x, y) = self.objects[id].pos();
+            for &(step_x, step_y) in &steps {
+                if (step_x, step_y) != (0, 0) && self.can_enter(id, x + step_x, y + step_y) {
+                    self.objects[id].set_pos(x + step_x, y + step_y);
+                    break;
+                }
             }
         }
+    }
+
+    /// Step directly away from the given position. Return whether the object
+    /// managed to move.
+    fn move_away(&mut self, id: usize, from_x: i32, from_y: i32) -> bool {
+        let (x, y) = self.objects[id].pos();
+        self.move_towards(id, 2 * x - from_x, 2 * y - from_y);
+        self.objects[id].pos() != (x, y)
+    }
 
+    /// The next step on the shortest path from an object to a tile, going
+    /// around blocking objects other than whatever is on that tile. None if
+    /// there's no path, or it's not shorter than `max_length`.
+    fn path_step(&mut self, id: usize, target_x: i32, target_y: i32, max_length: i32) -> Option<(i32, i32)> {
         // Scan all the objects to see if there are objects that must be navigated around
-        // Check also that the object isn't self or the target (so that the start and the end points are free)
-        // The AI class handles the situation if self is next to the target so it will not use this A* function anyway
-        for (i, object) in self.objects.iter().enumerate() {
-            if object.blocks && i != id && i != target_id {
-                // Set the tile as a wall so it must be navigated around.
-                fov_map.set(object.x, object.y, true, false);
-            }
+        // Check also that the object isn't self or on the target (so that the start and the end points are free)
+        let blockers: Vec<(i32, i32)> = self.objects.iter().enumerate()
+            .filter(|&(i, object)| object.blocks && i != id && object.pos() != (target_x, target_y))
+            .map(|(_, object)| object.pos())
+            .collect();
+        for &(x, y) in &blockers {
+            // Set the tile as a wall so it must be navigated around.
+            self.path_map.set(x, y, true, false);
         }
 
-        // Allocate a A* path
-        // The 1.41 is the normal diagonal cost of moving, it can be set as 0.0 if diagonal moves are prohibited
-        let ENEMIES_MOVE_DIAGONAL = false;
-        let cost = if ENEMIES_MOVE_DIAGONAL {
-            1.41
-        } else {
-            0.0
+        let (object_x, object_y) = (self.objects[id].x, self.objects[id].y);
+        let next_step = {
+            // Allocate a A* path over the shared walkability map
+            // The 1.41 is the normal diagonal cost of moving, it can be set as 0.0 if diagonal moves are prohibited
+            let cost = if ENEMIES_MOVE_DIAGONAL {
+                1.41
+            } else {
+                0.0
+            };
+            let path_map = &self.path_map;
+            let mut my_path = AStar::new_from_callback(
+                self.map.width, self.map.height,
+                |_from: (i32, i32), (x, y): (i32, i32)| if path_map.is_walkable(x, y) { 1.0 } else { 0.0 },
+                cost);
+
+            // Compute the path between self's coordinates and the target's coordinates
+            my_path.find((object_x, object_y), (target_x, target_y));
+
+            // Check if the path exists, and in this case, also the path is short enough
+            if !my_path.is_empty() && my_path.len() < max_length {
+                // Find the next coordinates in the computed full path.
+                my_path.walk_one_step(true)
+            } else {
+                None
+            }
         };
-        let mut my_path = AStar::new_from_map(fov_map, cost);
 
-        // Compute the path between self's coordinates and the target's coordinates
-        let (object_x, object_y) = (self.objects[id].x, self.objects[id].y);
-        let (target_x, target_y) = (self.objects[target_id].x, self.objects[target_id].y);
-        my_path.find((object_x, object_y), (target_x, target_y));
+        // Put the blocked tiles back the way the map has them for the next search.
+        for &(x, y) in &blockers {
+            let tile = &self.map[(x, y)];
+            self.path_map.set(x, y, !tile.block_sight, tile.is_walkable());
+        }
+
+        next_step
+    }
+
+    /// Take a step along the shortest path to a tile, unless it's not shorter
+    /// than `max_length`. Return whether the object managed to move.
+    fn step_towards(&mut self, id: usize, (x, y): (i32, i32), max_length: i32) -> bool {
+        match self.path_step(id, x, y, max_length) {
+            Some((step_x, step_y)) if !self.is_blocked(step_x, step_y) => {
+                self.objects[id].set_pos(step_x, step_y);
+                true
+            }
+            _ => false,
+        }
+    }
 
-        // Check if the path exists, and in this case, also the path is shorter than 25 tiles
+    fn move_astar(&mut self, id: usize, target_id: usize) {
+        // The AI class handles the situation if self is next to the target so it will not use this A* function anyway
         // The path size matters if you want the monster to use alternative longer paths (for example through other rooms) if for example the player is in a corridor
         // It makes sense to keep path size relatively low to keep the monsters from running around the map if there's an alternative path really far away
-        if !my_path.is_empty() && my_path.len() < 25 {
-            // Find the next coordinates in the computed full path.
-            if let Some((x, y)) = my_path.walk_one_step(true) {
+        let (target_x, target_y) = (self.objects[target_id].x, self.objects[target_id].y);
+        match self.path_step(id, target_x, target_y, 25) {
+            Some((x, y)) => {
                 // Set object's coordinates to the next path tile.
                 self.objects[id].x = x;
                 self.objects[id].y = y;
             }
-        } else {
-            // Keep the old move function as a backup so that if there are no paths (for example another monster blocks a corridor)
-            // it will still try to move towards the player (closer to the corridor opening)
-            self.move_towards(id, target_x, target_y)
+            None => {
+                // Keep the old move function as a backup so that if there are no paths (for example another monster blocks a corridor)
+                // it will still try to move towards the player (closer to the corridor opening)
+                self.move_towards(id, target_x, target_y)
+            }
         }
     }
 
@@ -359,33 +1390,433 @@ impl GameState {
         if let Some(ai) = self.objects[monster_id].ai.take() {
             let new_ai = match ai {
                 Ai::Basic => self.ai_basic(monster_id),
+                Ai::Chasing { last_seen, num_turns } =>
+                    self.ai_chasing(monster_id, last_seen, num_turns),
                 Ai::Confused { previous_ai, num_turns } =>
                     self.ai_confused(monster_id, previous_ai, num_turns),
+                Ai::Fleeing { previous_ai, num_turns } =>
+                    self.ai_fleeing(monster_id, previous_ai, num_turns),
+                Ai::Shopkeeper => Ai::Shopkeeper,
+                Ai::Healer => Ai::Healer,
+                Ai::Boss { phase } => self.ai_boss(monster_id, phase),
+                Ai::Ranged => self.ai_ranged(monster_id),
+                Ai::Investigating { noise, num_turns } =>
+                    self.ai_investigating(monster_id, noise, num_turns),
+                Ai::Patrolling { route, next, last_seen } =>
+                    self.ai_patrolling(monster_id, route, next, last_seen),
+                Ai::Tracking => self.ai_tracking(monster_id),
+                Ai::Sleeping { previous_ai } => self.ai_sleeping(monster_id, previous_ai),
             };
             self.objects[monster_id].ai = Some(new_ai);
         }
     }
 
     fn ai_basic(&mut self, monster_id: usize) -> Ai {
-        // A basic monster takes its turn. If you can see it, it can see you.
-        let (monster_x, monster_y) = self.objects[monster_id].pos();
-        if self.fov_map.is_in_fov(monster_x, monster_y) {
-            if self.objects[monster_id].distance_to(&self.objects[PLAYER]) > 1.0 {
-                // Move towards player if not adjacent.
-                self.move_astar(monster_id, PLAYER);
-            } else if self.objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-                // Close enough, attack! (if the player is still alive.)
-                let (monster, player) = mut_two(monster_id, PLAYER, &mut self.objects);
-                monster.attack(player, &mut self.messages);
-            }
+        // A basic monster takes its turn, going after the player if it notices them.
+        if self.monster_notices_player(monster_id, MONSTER_SIGHT_RADIUS) {
+            self.go_after_player(monster_id)
+        } else {
+            self.monster_idle(monster_id);
+            Ai::Basic
         }
-        Ai::Basic
     }
 
-    fn ai_confused(&mut self, monster_id: usize, previous_ai: Box<Ai>, num_turns: i32) -> Ai {
-        if num_turns >= 0 {
-            // Still confused, so move in a random direction, and decrease the number of turns confused.
-            let possible_movements = [
+    /// Go after the player in sight: close in and attack, bringing the rest
+    /// of the pack along, or run away if afraid.
+    fn go_after_player(&mut self, monster_id: usize) -> Ai {
+        if self.objects[monster_id].fighter.map_or(false, |f| f.is_afraid()) {
+            return self.start_fleeing(monster_id, Ai::Basic);
+        }
+        self.close_in(monster_id);
+
+        // Remember where the player is, to go looking once they're out of sight.
+        Ai::Chasing {
+            last_seen: self.objects[PLAYER].pos(),
+            num_turns: CHASE_TURNS,
+        }
+    }
+
+    /// Close in on the player in sight, around them along with the rest of
+    /// its pack, and attack once next to them.
+    fn close_in(&mut self, monster_id: usize) {
+        self.alert_pack(monster_id);
+        if self.objects[monster_id].distance_to(&self.objects[PLAYER]) > 1.0 {
+            match self.flank_spot(monster_id) {
+                Some(spot) if self.step_towards(monster_id, spot, FLANK_PATH_LENGTH) => {}
+                _ => self.move_astar(monster_id, PLAYER),
+            }
+        } else {
+            self.monster_attack(monster_id);
+        }
+    }
+
+    /// Let the rest of a monster's pack know where the player is, waking any
+    /// that sleep.
+    fn alert_pack(&mut self, monster_id: usize) {
+        let pack = match self.objects[monster_id].pack {
+            Some(pack) => pack,
+            None => return,
+        };
+        let last_seen = self.objects[PLAYER].pos();
+        for object in self.objects.iter_mut().filter(|object| object.pack == Some(pack)) {
+            object.wake_if_seen(&self.fov_map, &mut self.messages);
+            if let Some(Ai::Basic) | Some(Ai::Investigating { .. }) = object.ai {
+                object.ai = Some(Ai::Chasing {
+                    last_seen,
+                    num_turns: CHASE_TURNS,
+                });
+            }
+        }
+    }
+
+    /// Where a pack monster should head to get at the player: the free tile
+    /// next to them nearest to it that no packmate nearer the player already
+    /// went for. That way, the pack surrounds the player instead of queueing
+    /// up behind each other. None if it's not in a pack, or every tile is
+    /// taken.
+    fn flank_spot(&self, monster_id: usize) -> Option<(i32, i32)> {
+        let pack = self.objects[monster_id].pack?;
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        let distance = |(x, y): (i32, i32), (to_x, to_y): (i32, i32)| (x - to_x).pow(2) + (y - to_y).pow(2);
+
+        let mut spots: Vec<(i32, i32)> = CARDINAL_DIRECTIONS.iter()
+            .map(|&(dx, dy)| (player_x + dx, player_y + dy))
+            .filter(|&(x, y)| !self.is_blocked(x, y))
+            .collect();
+        let mut hunters: Vec<usize> = (0..self.objects.len())
+            .filter(|&id| id == monster_id || (self.objects[id].alive && self.objects[id].pack == Some(pack)))
+            .filter(|&id| distance(self.objects[id].pos(), (player_x, player_y)) > 1)
+            .collect();
+        hunters.sort_by_key(|&id| distance(self.objects[id].pos(), (player_x, player_y)));
+        for id in hunters {
+            let pos = self.objects[id].pos();
+            let nearest = (0..spots.len()).min_by_key(|&i| distance(spots[i], pos))?;
+            let spot = spots.remove(nearest);
+            if id == monster_id {
+                return Some(spot);
+            }
+        }
+        None
+    }
+
+    fn ai_patrolling(&mut self, monster_id: usize, route: Vec<(i32, i32)>, next: usize,
+                     last_seen: Option<(i32, i32)>) -> Ai {
+        let sees_player = if last_seen.is_some() {
+            self.monster_sees_player(monster_id)
+        } else {
+            self.monster_notices_player(monster_id, MONSTER_SIGHT_RADIUS)
+        };
+        if sees_player {
+            if self.objects[monster_id].fighter.map_or(false, |f| f.is_afraid()) {
+                return self.start_fleeing(monster_id, Ai::Patrolling { route, next, last_seen: None });
+            }
+            self.close_in(monster_id);
+            let last_seen = Some(self.objects[PLAYER].pos());
+            return Ai::Patrolling { route, next, last_seen };
+        }
+
+        let pos = self.objects[monster_id].pos();
+        let max_length = self.map.width + self.map.height;
+        if let Some(last_seen) = last_seen {
+            if pos != last_seen && self.step_towards(monster_id, last_seen, max_length) {
+                return Ai::Patrolling { route, next, last_seen: Some(last_seen) };
+            }
+        }
+
+        // Back on its rounds. Once at a waypoint, or when it can't get any
+        // closer to it, it's on to the next one.
+        let mut next = next;
+        if pos == route[next] || !self.step_towards(monster_id, route[next], max_length) {
+            next = (next + 1) % route.len();
+        }
+        Ai::Patrolling { route, next, last_seen: None }
+    }
+
+    /// A tracker goes after the player in sight, and otherwise follows their
+    /// scent, so there's no losing it just by getting out of its sight.
+    fn ai_tracking(&mut self, monster_id: usize) -> Ai {
+        if self.monster_sees_player(monster_id) {
+            if self.objects[monster_id].fighter.map_or(false, |f| f.is_afraid()) {
+                return self.start_fleeing(monster_id, Ai::Tracking);
+            }
+            self.close_in(monster_id);
+        } else if !self.follow_scent(monster_id) {
+            self.monster_idle(monster_id);
+        }
+        Ai::Tracking
+    }
+
+    /// Step to the neighboring tile with the freshest scent on it, if it's
+    /// fresher than where the monster stands. Return whether it moved.
+    fn follow_scent(&mut self, monster_id: usize) -> bool {
+        let turn = self.turn;
+        let fresh = |tile: &Tile| tile.scent.filter(|&scent| turn - scent < SCENT_TURNS);
+        let (x, y) = self.objects[monster_id].pos();
+        let here = fresh(&self.map[(x, y)]);
+        let best = CARDINAL_DIRECTIONS.iter()
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .filter(|&(next_x, next_y)| self.can_enter(monster_id, next_x, next_y))
+            .filter_map(|(next_x, next_y)| fresh(&self.map[(next_x, next_y)]).map(|scent| (scent, next_x, next_y)))
+            .max();
+        match best {
+            Some((scent, next_x, next_y)) if Some(scent) > here => {
+                self.objects[monster_id].set_pos(next_x, next_y);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// A sleeping monster does nothing until the player comes close, in plain
+    /// sight.
+    fn ai_sleeping(&mut self, monster_id: usize, previous_ai: Box<Ai>) -> Ai {
+        if self.monster_notices_player(monster_id, WAKE_DISTANCE) {
+            self.messages.message_kind(format!("The {} wakes up!", self.objects[monster_id].name), colors::ORANGE,
+                                       MessageKind::Warning);
+            return *previous_ai;
+        }
+        Ai::Sleeping { previous_ai }
+    }
+
+    /// An investigating monster heads for the noise it heard, going after the
+    /// player if it notices them on the way. Once there, it looks around.
+    fn ai_investigating(&mut self, monster_id: usize, noise: (i32, i32), num_turns: i32) -> Ai {
+        if self.monster_notices_player(monster_id, MONSTER_SIGHT_RADIUS) {
+            return self.go_after_player(monster_id);
+        }
+        let max_length = self.map.width + self.map.height;
+        let there = self.objects[monster_id].pos() == noise;
+        if num_turns <= 0 || there || !self.step_towards(monster_id, noise, max_length) {
+            self.monster_idle(monster_id);
+            return Ai::Basic;
+        }
+        Ai::Investigating {
+            noise,
+            num_turns: num_turns - 1,
+        }
+    }
+
+    /// Make a noise on a tile, carrying some steps around walls. Whoever hears
+    /// it wakes up, and those not after the player yet come to see what it was.
+    fn make_noise(&mut self, (x, y): (i32, i32), radius: i32) {
+        let heard = map::earshot(&self.map, (x, y), radius);
+        for (id, object) in self.objects.iter_mut().enumerate() {
+            if id == PLAYER || !object.alive || !heard[object.x as usize][object.y as usize] {
+                continue;
+            }
+            object.wake_if_seen(&self.fov_map, &mut self.messages);
+            object.ai = match object.ai.take() {
+                Some(Ai::Basic) | Some(Ai::Investigating { .. }) => Some(Ai::Investigating {
+                    noise: (x, y),
+                    num_turns: INVESTIGATE_TURNS,
+                }),
+                Some(Ai::Patrolling { route, next, last_seen: None }) => Some(Ai::Patrolling {
+                    route,
+                    next,
+                    last_seen: Some((x, y)),
+                }),
+                ai => ai,
+            };
+        }
+    }
+
+    /// Nothing to do, so pick up what's lying around, or every now and then
+    /// wander around.
+    fn monster_idle(&mut self, monster_id: usize) {
+        let mut rng = rand::thread_rng();
+        if !self.monster_pick_up(monster_id) && rng.gen_range(0, 100) < WANDER_CHANCE {
+            let &(dx, dy) = rng.choose(&CARDINAL_DIRECTIONS)
+                .expect("Wandering monster could not get a movement direction.");
+            self.move_object_by(monster_id, dx, dy);
+        }
+    }
+
+    /// A ranged monster keeps the player at a distance, backing off when they
+    /// come close, and shoots whenever it has a clear shot. Cornered, it
+    /// fights back up close.
+    fn ai_ranged(&mut self, monster_id: usize) -> Ai {
+        if !self.monster_sees_player(monster_id) {
+            self.monster_idle(monster_id);
+            return Ai::Ranged;
+        }
+        if self.objects[monster_id].fighter.map_or(false, |f| f.is_afraid()) {
+            return self.start_fleeing(monster_id, Ai::Ranged);
+        }
+
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        let distance = self.objects[monster_id].distance_to(&self.objects[PLAYER]);
+        if distance < RANGED_MIN_DISTANCE as f32 && self.move_away(monster_id, player_x, player_y) {
+            return Ai::Ranged;
+        }
+        if distance <= 1.0 {
+            self.monster_attack(monster_id);
+        } else if distance <= RANGED_MAX_DISTANCE as f32 &&
+            self.clear_shot(self.objects[monster_id].pos(), (player_x, player_y)) {
+            self.monster_shoot(monster_id);
+        } else {
+            self.move_astar(monster_id, PLAYER);
+        }
+        Ai::Ranged
+    }
+
+    /// Shoot at the player from afar. Unlike a blow, a shot can't be met with
+    /// thorns.
+    fn monster_shoot(&mut self, monster_id: usize) {
+        if self.objects[PLAYER].fighter.map_or(true, |f| f.hp <= 0) {
+            return;
+        }
+        let power = self.objects[monster_id].fighter.map_or(0, |f| f.power(&self.get_all_equipped(monster_id)));
+        let defense = self.objects[PLAYER].fighter.map_or(0, |f| f.defense(&self.get_all_equipped(PLAYER)));
+        let damage = power - defense;
+        let name = self.objects[monster_id].name.clone();
+        if damage > 0 {
+            self.messages.message_kind(format!("The {} shoots you for {} hit points.", name, damage),
+                                       colors::WHITE, MessageKind::Combat);
+            self.objects[PLAYER].take_damage(damage, &mut self.messages);
+            if !self.objects[PLAYER].alive {
+                self.killed_by = Some(name);
+            }
+        } else {
+            self.messages.message_kind(format!("The {} shoots you, but it has no effect!", name),
+                                       colors::WHITE, MessageKind::Combat);
+        }
+    }
+
+    /// A boss holds its ground by the stairs until it sees the player, then
+    /// fights to the death. Whenever it's hurt badly enough to enter a new
+    /// phase of the fight, it does something about it.
+    fn ai_boss(&mut self, monster_id: usize, phase: usize) -> Ai {
+        let boss = match self.objects[monster_id].boss {
+            Some(boss) => boss,
+            None => return Ai::Basic,
+        };
+        // Healing doesn't take it back to an earlier phase.
+        let new_phase = self.objects[monster_id].fighter.map_or(phase, |f| {
+            std::cmp::max(phase, boss.phase(f.hp, f.max_hp))
+        });
+        for &effect in &boss.phase_effects()[phase..new_phase] {
+            self.boss_phase(monster_id, effect);
+        }
+
+        if self.monster_sees_player(monster_id) {
+            if self.objects[monster_id].distance_to(&self.objects[PLAYER]) > 1.0 {
+                self.move_astar(monster_id, PLAYER);
+            } else {
+                self.monster_attack(monster_id);
+            }
+        }
+        Ai::Boss { phase: new_phase }
+    }
+
+    /// Have a boss do what it does on entering a new phase of the fight.
+    fn boss_phase(&mut self, monster_id: usize, effect: PhaseEffect) {
+        let name = self.objects[monster_id].name.clone();
+        match effect {
+            PhaseEffect::Summon(kind, count) => {
+                self.messages.message_kind(format!("{} calls for help!", name), colors::ORANGE,
+                                           MessageKind::Warning);
+                let (x, y) = self.objects[monster_id].pos();
+                let mut spots = vec![];
+                for spot_x in (x - SUMMON_RADIUS)..(x + SUMMON_RADIUS + 1) {
+                    for spot_y in (y - SUMMON_RADIUS)..(y + SUMMON_RADIUS + 1) {
+                        if self.map.in_bounds(spot_x, spot_y) && !self.is_blocked(spot_x, spot_y) {
+                            spots.push((spot_x, spot_y));
+                        }
+                    }
+                }
+                let mut rng = rand::thread_rng();
+                rng.shuffle(&mut spots);
+                for &(spot_x, spot_y) in spots.iter().take(count as usize) {
+                    let mut monster = map::make_monster(kind, spot_x, spot_y, self.location.level(), &mut rng);
+                    monster.ai = Some(Ai::Chasing {
+                        last_seen: self.objects[PLAYER].pos(),
+                        num_turns: CHASE_TURNS,
+                    });
+                    self.objects.push(monster);
+                }
+            }
+            PhaseEffect::Enrage => {
+                self.messages.message_kind(format!("{} flies into a rage!", name), colors::RED,
+                                           MessageKind::Warning);
+                if let Some(fighter) = self.objects[monster_id].fighter.as_mut() {
+                    fighter.power += fighter.power / 2;
+                }
+            }
+            PhaseEffect::Regenerate(percent) => {
+                self.messages.message_kind(format!("The wounds of {} close up!", name), colors::ORANGE,
+                                           MessageKind::Warning);
+                let equipped = self.get_all_equipped(monster_id);
+                let amount = self.objects[monster_id].fighter.map_or(0, |f| f.max_hp(&equipped) * percent / 100);
+                self.objects[monster_id].heal(amount, &equipped);
+            }
+        }
+    }
+
+    /// Attack the player, if they're still alive.
+    fn monster_attack(&mut self, monster_id: usize) {
+        if self.objects[PLAYER].fighter.map_or(true, |f| f.hp <= 0) {
+            return;
+        }
+        let player_pos = self.objects[PLAYER].pos();
+        self.make_noise(player_pos, FIGHT_NOISE_RADIUS);
+        let monster_equipped = self.get_all_equipped(monster_id);
+        let player_equipped = self.get_all_equipped(PLAYER);
+        match mut_two(monster_id, PLAYER, &mut self.objects) {
+            Ok((monster, player)) => {
+                monster.attack(player, &monster_equipped, &player_equipped, &mut self.messages);
+                if !player.alive {
+                    self.killed_by = Some(monster.name.clone());
+                }
+            },
+            Err(err) => eprintln!("Monster {} skipped its attack: {:?}", monster_id, err),
+        }
+
+        // Trolls hit hard enough to send the player staggering back.
+        let mut rng = rand::thread_rng();
+        if self.objects[monster_id].name == "troll" && self.objects[PLAYER].alive &&
+            rng.gen_range(0, 100) < KNOCKBACK_CHANCE {
+            self.knock_back(monster_id);
+        }
+    }
+
+    /// Knock the player a step back, away from a monster, unless something is
+    /// in the way. It may well be into a chasm.
+    fn knock_back(&mut self, monster_id: usize) {
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        let (monster_x, monster_y) = self.objects[monster_id].pos();
+        let x = player_x + (player_x - monster_x).signum();
+        let y = player_y + (player_y - monster_y).signum();
+        if map::is_obstructed(x, y, &self.map, &self.objects) {
+            return;
+        }
+        self.objects[PLAYER].set_pos(x, y);
+        self.messages.message_kind(format!("The {} knocks you back!", self.objects[monster_id].name),
+                                   colors::ORANGE, MessageKind::Combat);
+    }
+
+    fn ai_chasing(&mut self, monster_id: usize, last_seen: (i32, i32), num_turns: i32) -> Ai {
+        // Back after them as soon as the player is in sight again.
+        if self.monster_sees_player(monster_id) {
+            return self.go_after_player(monster_id);
+        }
+        let (monster_x, monster_y) = self.objects[monster_id].pos();
+
+        // Lost track of the player, give up.
+        if num_turns <= 0 || (monster_x, monster_y) == last_seen {
+            return Ai::Basic;
+        }
+
+        self.move_towards(monster_id, last_seen.0, last_seen.1);
+        Ai::Chasing {
+            last_seen,
+            num_turns: num_turns - 1,
+        }
+    }
+
+    fn ai_confused(&mut self, monster_id: usize, previous_ai: Box<Ai>, num_turns: i32) -> Ai {
+        if num_turns >= 0 {
+            // Still confused, so move in a random direction, and decrease the number of turns confused.
+            let possible_movements = [
                 (0, 0),
                 (1, 0),
                 (-1, 0),
@@ -395,109 +1826,1230 @@ impl GameState {
             let (x, y) = *rand::thread_rng().choose(&possible_movements)
                 .expect("Confused enemy could not get a movement direction.");
 
-            self.move_object_by(monster_id, x, y);
-            Ai::Confused {
-                previous_ai: previous_ai,
-                num_turns: num_turns - 1,
+            self.move_object_by(monster_id, x, y);
+            Ai::Confused {
+                previous_ai: previous_ai,
+                num_turns: num_turns - 1,
+            }
+        } else {
+            // Restore the previous AI (this one will be deleted).
+            self.messages.message_kind(
+                format!("The {} is no longer confused!", self.objects[monster_id].name),
+                colors::RED,
+                MessageKind::Combat,
+            );
+            *previous_ai
+        }
+    }
+
+    fn start_fleeing(&mut self, monster_id: usize, previous_ai: Ai) -> Ai {
+        self.messages.message_kind(
+            format!("The {} turns to flee!", self.objects[monster_id].name),
+            colors::LIGHT_GREY,
+            MessageKind::Combat,
+        );
+        self.ai_fleeing(monster_id, Box::new(previous_ai), FLEE_TURNS)
+    }
+
+    fn ai_fleeing(&mut self, monster_id: usize, previous_ai: Box<Ai>, num_turns: i32) -> Ai {
+        // Calm down after a while, or as soon as it has healed enough to fight.
+        let afraid = self.objects[monster_id].fighter.map_or(false, |f| f.is_afraid());
+        if num_turns <= 0 || !afraid {
+            return *previous_ai;
+        }
+
+        let moved = self.flee_step(monster_id);
+        if !moved && self.objects[monster_id].distance_to(&self.objects[PLAYER]) <= 1.0 {
+            // Cornered, so it fights back.
+            self.monster_attack(monster_id);
+        }
+        Ai::Fleeing {
+            previous_ai,
+            num_turns: num_turns - 1,
+        }
+    }
+
+    /// Take a step down the flee map, away from the player and towards open
+    /// space. Return whether there was anywhere better to go.
+    fn flee_step(&mut self, monster_id: usize) -> bool {
+        let (x, y) = self.objects[monster_id].pos();
+        let enterable: Vec<_> = CARDINAL_DIRECTIONS.iter()
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .filter(|&(next_x, next_y)| self.can_enter(monster_id, next_x, next_y))
+            .collect();
+        let (map, player_pos) = (&self.map, self.objects[PLAYER].pos());
+        let flee_map = self.flee_map.get_or_insert_with(|| map::flee_map(map, player_pos));
+        // A monster somewhere it can't walk, like over a chasm, takes any way out.
+        let here = flee_map[x as usize][y as usize].unwrap_or(i32::max_value());
+        let best = enterable.into_iter()
+            .filter_map(|(next_x, next_y)| {
+                flee_map[next_x as usize][next_y as usize].map(|value| (value, next_x, next_y))
+            })
+            .min();
+        match best {
+            Some((value, next_x, next_y)) if value < here => {
+                self.objects[monster_id].set_pos(next_x, next_y);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move or attack in the given direction, stumbling in a random one
+    /// instead while confused.
+    fn player_step(&mut self, dx: i32, dy: i32) -> PlayerAction {
+        let (dx, dy) = if self.player_confused_turns > 0 {
+            *rand::thread_rng().choose(&CARDINAL_DIRECTIONS)
+                .expect("Confused player could not get a movement direction.")
+        } else {
+            (dx, dy)
+        };
+        self.player_move_or_attack(dx, dy);
+        PlayerAction::TookTurn
+    }
+
+    /// Take one step along the shortest path to a tile, attacking whatever
+    /// stands there once next to it.
+    fn player_step_towards(&mut self, x: i32, y: i32) -> PlayerAction {
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        match self.path_step(PLAYER, x, y, MOUSE_WALK_MAX_PATH) {
+            Some((step_x, step_y)) => self.player_step(step_x - player_x, step_y - player_y),
+            None => PlayerAction::DidntTakeTurn,
+        }
+    }
+
+    /// Left-clicking a floor tile in view starts walking there, and clicking
+    /// a monster steps towards it to attack. Right-clicking stops walking.
+    fn handle_click(&mut self, mouse: Mouse) -> PlayerAction {
+        if mouse.rbutton_pressed {
+            self.walk_target = None;
+        }
+        if !mouse.lbutton_pressed || !self.objects[PLAYER].alive {
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        let (x, y) = self.to_world_coordinates(mouse.cx as i32, mouse.cy as i32);
+        let open = self.map.get(x, y).map_or(false, |tile| !tile.blocked);
+        if !open || !self.fov_map.is_in_fov(x, y) {
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        let monster_there = self.objects.iter().enumerate().any(|(id, object)| {
+            id != PLAYER && object.fighter.is_some() && object.pos() == (x, y)
+        });
+        if monster_there {
+            // Approach it one click at a time, instead of walking into it.
+            self.walk_target = None;
+            return self.player_step_towards(x, y);
+        }
+
+        self.walk_target = Some((x, y));
+        let action = self.player_step_towards(x, y);
+        if action == PlayerAction::DidntTakeTurn {
+            // Can't get there.
+            self.walk_target = None;
+        }
+        action
+    }
+
+    /// Keep walking to the clicked tile, one step per turn, until arriving or
+    /// a monster shows up.
+    fn auto_walk(&mut self) -> PlayerAction {
+        let (x, y) = match self.walk_target {
+            Some(target) => target,
+            None => return PlayerAction::DidntTakeTurn,
+        };
+        if self.objects[PLAYER].pos() == (x, y) || self.monster_in_view() {
+            self.walk_target = None;
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        let action = self.player_step_towards(x, y);
+        if action == PlayerAction::DidntTakeTurn {
+            // The way got blocked.
+            self.walk_target = None;
+        } else if !self.sneaking {
+            // Hurrying along like that is noisy.
+            let player_pos = self.objects[PLAYER].pos();
+            self.make_noise(player_pos, RUN_NOISE_RADIUS);
+        }
+        action
+    }
+
+    fn player_move_or_attack(&mut self, dx: i32, dy: i32) {
+        // the coordinates the player is moving to/attacking
+        let x = self.objects[PLAYER].x + dx;
+        let y = self.objects[PLAYER].y + dy;
+
+        // Try to find an attackable object there, or a chest.
+        let target_id = self.objects.iter().position(|object| {
+            object.fighter.is_some() && object.pos() == (x, y)
+        });
+        let chest_id = self.objects.iter().position(|object| object.chest.is_some() && object.pos() == (x, y));
+
+        // Attack if target found, move otherwise. Shopkeepers and healers
+        // deal with the player instead.
+        if let Some(target_id) = target_id {
+            if self.objects[target_id].ai.as_ref().map_or(false, |ai| !ai.is_hostile()) {
+                self.bumped_into = Some(target_id);
+            } else {
+                self.player_attack(target_id);
+            }
+        } else if chest_id.is_some() {
+            self.bumped_into = chest_id;
+        } else if let Some(lock) = self.map[(x, y)].lock {
+            self.try_door(x, y, lock);
+        } else if self.map[(x, y)].blocked && self.wields(Item::Pickaxe) {
+            if self.map[(x, y)].is_diggable() {
+                self.dig(x, y);
+                self.compute_fov();
+                self.messages.message("You dig through the wall.", colors::LIGHT_SEPIA);
+            } else {
+                self.messages.message("This wall is too hard to dig through.", colors::WHITE);
+            }
+        } else {
+            if self.map[(x, y)].feature == Some(Feature::Statue) && !self.push_statue(x, y, dx, dy) {
+                return;
+            }
+            let before = self.map[(x - dx, y - dy)].terrain;
+            self.move_object_by(PLAYER, dx, dy);
+            self.trigger_trap();
+            let (player_x, player_y) = self.objects[PLAYER].pos();
+            let after = self.map[(player_x, player_y)].terrain;
+            if let Some(message) = after.entering_message().filter(|_| after != before) {
+                self.messages.message_kind(message, colors::LIGHT_BLUE, MessageKind::Warning);
+            }
+            if self.map[(player_x, player_y)].dark && !self.map[(x - dx, y - dy)].dark {
+                self.messages.message_kind("A magical darkness swallows your light.", colors::LIGHT_GREY,
+                                           MessageKind::Warning);
+            }
+        }
+    }
+
+    /// Push a statue a tile further along, out of the player's way, if there's
+    /// room for it. Into water, lava or a chasm, it's gone for good.
+    fn push_statue(&mut self, x: i32, y: i32, dx: i32, dy: i32) -> bool {
+        let (to_x, to_y) = (x + dx, y + dy);
+        let room = self.map.get(to_x, to_y).map_or(false, |tile| tile.feature.is_none()) &&
+            !map::is_obstructed(to_x, to_y, &self.map, &self.objects);
+        if !room {
+            self.messages.message("The statue won't budge.", colors::WHITE);
+            return false;
+        }
+        self.map[(x, y)].feature = None;
+        match self.map[(to_x, to_y)].terrain {
+            Terrain::Chasm => self.messages.message("The statue tumbles down the chasm.", colors::WHITE),
+            terrain if terrain.is_hazard() => self.messages.message("The statue sinks out of sight.", colors::WHITE),
+            _ => {
+                self.map[(to_x, to_y)].feature = Some(Feature::Statue);
+                self.messages.message("You push the statue.", colors::WHITE);
+            },
+        }
+        true
+    }
+
+    /// Dig through any rubble on or next to the player, clearing it away.
+    /// Some of it has something buried in it. Returns whether there was any.
+    fn search_rubble(&mut self) -> bool {
+        let mut rng = rand::thread_rng();
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        let mut found = false;
+        for x in (player_x - 1)..(player_x + 2) {
+            for y in (player_y - 1)..(player_y + 2) {
+                if self.map.get(x, y).and_then(|tile| tile.feature) != Some(Feature::Rubble) {
+                    continue;
+                }
+                self.map[(x, y)].feature = None;
+                found = true;
+                if rng.gen_range(0, 100) < RUBBLE_FIND_CHANCE {
+                    let buried = map::make_buried(x, y, self.location.level(), &self.map.theme, &mut rng);
+                    self.messages.message(format!("You dig through the rubble and find a {}!", buried.name),
+                                          colors::LIGHT_GREEN);
+                    self.objects.push(buried);
+                } else {
+                    self.messages.message("You dig through the rubble, but find nothing.", colors::WHITE);
+                }
+            }
+        }
+        found
+    }
+
+    /// Whether the player has a kind of item equipped.
+    fn wields(&self, item: Item) -> bool {
+        self.inventory.iter().any(|object| {
+            object.item == Some(item) && object.equipment.map_or(false, |e| e.equipped)
+        })
+    }
+
+    /// Turn a wall into floor, letting sight and paths through it.
+    fn dig(&mut self, x: i32, y: i32) {
+        let tile = &mut self.map[(x, y)];
+        tile.blocked = false;
+        tile.block_sight = false;
+        self.fov_map.set(x, y, true, true);
+        self.sight_map.set(x, y, true, true);
+        self.path_map.set(x, y, true, tile.is_walkable());
+        self.flee_map = None;
+    }
+
+    /// Unlock a locked door if the player has its key.
+    fn try_door(&mut self, x: i32, y: i32, lock: u32) {
+        if !self.inventory.iter().any(|item| item.item == Some(Item::DoorKey(lock))) {
+            self.messages.message("The door is locked.", colors::WHITE);
+            return;
+        }
+        let tile = &mut self.map[(x, y)];
+        tile.lock = None;
+        tile.blocked = false;
+        tile.block_sight = false;
+        self.fov_map.set(x, y, true, true);
+        self.sight_map.set(x, y, true, true);
+        self.compute_fov();
+        self.messages.message("You unlock the door.", colors::LIGHT_GREEN);
+    }
+
+    fn player_attack(&mut self, target_id: usize) {
+        let player_equipped = self.get_all_equipped(PLAYER);
+        let target_equipped = self.get_all_equipped(target_id);
+        match mut_two(PLAYER, target_id, &mut self.objects) {
+            Ok((player, target)) => {
+                player.attack(target, &player_equipped, &target_equipped, &mut self.messages);
+                if !player.alive {
+                    self.killed_by = Some(format!("the thorns of a {}", target.name));
+                }
+            }
+            Err(err) => eprintln!("Player skipped attacking {}: {:?}", target_id, err),
+        }
+
+        // The racket draws whoever is nearby, after the fact.
+        let target_pos = self.objects[target_id].pos();
+        self.make_noise(target_pos, FIGHT_NOISE_RADIUS);
+    }
+
+    /// Buy one of a shopkeeper's items, if the player has the gold and room for it.
+    fn buy(&mut self, shopkeeper_id: usize, stock_id: usize) {
+        let price = self.objects[shopkeeper_id].carried[stock_id].item.map_or(0, |i| i.value());
+        let name = self.objects[shopkeeper_id].carried[stock_id].name.clone();
+        if self.gold < price {
+            self.messages.message(format!("You can't afford the {}.", name), colors::RED);
+            return;
+        }
+        let stack_id = self.find_stack(&self.objects[shopkeeper_id].carried[stock_id]);
+        if stack_id.is_none() && self.inventory.len() >= INVENTORY_SIZE {
+            self.messages.message(format!("Your inventory is full, cannot buy the {}.", name), colors::RED);
+            return;
+        }
+
+        let stock = &mut self.objects[shopkeeper_id].carried;
+        let item = if stock[stock_id].count > 1 {
+            stock[stock_id].count -= 1;
+            let mut item = stock[stock_id].clone();
+            item.count = 1;
+            item
+        } else {
+            stock.remove(stock_id)
+        };
+        self.gold -= price;
+        self.messages.message(format!("You buy a {} for {} gold.", name, price), colors::GOLD);
+        match stack_id {
+            Some(stack_id) => self.inventory[stack_id].count += 1,
+            None => self.inventory.push(item),
+        }
+    }
+
+    /// Sell one of the player's items to a shopkeeper, for half its value.
+    fn sell(&mut self, shopkeeper_id: usize, inventory_id: usize) {
+        if self.inventory[inventory_id].item == Some(Item::Relic) {
+            self.messages.message("You came all this way for the crown. It's not for sale.", colors::RED);
+            return;
+        }
+        // The shop only has room for as many kinds of wares as fit on its menu.
+        let stack_id = {
+            let selling = &self.inventory[inventory_id];
+            let stackable = selling.item.map_or(false, |i| i.is_stackable());
+            self.objects[shopkeeper_id].carried.iter().position(|other| {
+                stackable && other.item == selling.item && other.name == selling.name
+            })
+        };
+        if stack_id.is_none() && self.objects[shopkeeper_id].carried.len() >= INVENTORY_SIZE {
+            let text = format!("The shop has no room left for your {}.", self.inventory[inventory_id].name);
+            self.messages.message(text, colors::RED);
+            return;
+        }
+        if !self.inventory[inventory_id].unequip(&mut self.messages) {
+            return;
+        }
+        let item = self.take_from_inventory(inventory_id);
+        let price = item.item.map_or(0, |i| i.value()) / 2;
+        self.gold += price;
+        self.messages.message(format!("You sell a {} for {} gold.", item.name, price), colors::GOLD);
+
+        let stock = &mut self.objects[shopkeeper_id].carried;
+        match stack_id {
+            Some(stack_id) => stock[stack_id].count += 1,
+            None => stock.push(item),
+        }
+    }
+
+    /// Pick up everything the player walked onto that auto-pickup wants,
+    /// with a single message for all of it.
+    fn auto_pick_up(&mut self, settings: &AutoPickup) {
+        let player_pos = self.objects[PLAYER].pos();
+        let ids: Vec<usize> = (0..self.objects.len())
+            .rev()
+            .filter(|&id| {
+                let object = &self.objects[id];
+                object.pos() == player_pos && object.count > 0 && object.item.map_or(false, |i| settings.wants(i))
+            })
+            .collect();
+
+        // Going from the end, removing an object doesn't move the ones left to pick up.
+        let mut picked_up = vec![];
+        for id in ids {
+            if self.objects[id].item == Some(Item::Gold) {
+                let gold = self.remove_object(id);
+                self.gold += gold.count;
+                picked_up.push(format!("{} gold pieces", gold.count));
+                continue;
+            }
+            let stack_id = self.find_stack(&self.objects[id]);
+            if stack_id.is_none() && self.inventory.len() >= INVENTORY_SIZE {
+                self.messages.message(format!("Your inventory is full, cannot pick up {}.", self.objects[id].name),
+                                      colors::RED);
+                continue;
+            }
+            let item = self.remove_object(id);
+            if item.count > 1 {
+                picked_up.push(format!("{} {}", item.count, item.name));
+            } else {
+                picked_up.push(format!("a {}", item.name));
+            }
+            match stack_id {
+                Some(stack_id) => self.inventory[stack_id].count += item.count,
+                None => self.inventory.push(item),
+            }
+        }
+        if !picked_up.is_empty() {
+            self.messages.message(format!("You pick up {}.", picked_up.join(", ")), colors::GREEN);
+        }
+    }
+
+    /// Scoop up any gold on the player's tile into the wallet.
+    fn pick_up_gold(&mut self) {
+        let player_pos = self.objects[PLAYER].pos();
+        while let Some(id) = self.objects.iter().position(|object| {
+            object.pos() == player_pos && object.item == Some(Item::Gold) && object.count > 0
+        }) {
+            let gold = self.remove_object(id);
+            self.gold += gold.count;
+            self.messages.message(format!("You pick up {} gold pieces.", gold.count), colors::GOLD);
+        }
+    }
+
+    /// Add to the player's inventory and remove from the map.
+    fn pick_item_up(&mut self, object_id: usize) {
+        // Gold goes in the wallet, never the inventory.
+        if self.objects[object_id].item == Some(Item::Gold) {
+            self.pick_up_gold();
+            return;
+        }
+        // Stacks count as a single slot, so a matching stack always has room.
+        let stack_id = self.find_stack(&self.objects[object_id]);
+        if stack_id.is_none() && self.inventory.len() >= INVENTORY_SIZE {
+            self.messages.message(
+                format!("Your inventory is full, cannot pick up {}.", self.objects[object_id].name),
+                colors::RED,
+            );
+        } else {
+            let item = self.remove_object(object_id);
+            if item.count > 1 {
+                self.messages.message(format!("You picked up {} {}!", item.count, item.name), colors::GREEN);
+            } else {
+                self.messages.message(format!("You picked up a {}!", item.name), colors::GREEN);
+            }
+            if item.item == Some(Item::Relic) && !self.ascending {
+                self.ascending = true;
+                self.messages.message_kind("You have the crown! Now climb back out of the dungeon, alive.",
+                                           colors::GOLD, MessageKind::Critical);
+            }
+            match stack_id {
+                Some(stack_id) => self.inventory[stack_id].count += item.count,
+                None => self.inventory.push(item),
+            }
+        }
+    }
+
+    /// Find the inventory stack the given item would join, if it stacks.
+    fn find_stack(&self, item: &Object) -> Option<usize> {
+        if !item.item.map_or(false, |i| i.is_stackable()) {
+            return None;
+        }
+        self.inventory.iter().position(|other| other.item == item.item && other.name == item.name)
+    }
+
+    /// Take a single item out of the inventory, splitting it off its stack.
+    fn take_from_inventory(&mut self, inventory_id: usize) -> Object {
+        self.take_many_from_inventory(inventory_id, 1)
+    }
+
+    /// Take some items out of a stack, or the whole of it if there aren't
+    /// more than that.
+    fn take_many_from_inventory(&mut self, inventory_id: usize, count: u32) -> Object {
+        if self.inventory[inventory_id].count > count {
+            self.inventory[inventory_id].count -= count;
+            let mut item = self.inventory[inventory_id].clone();
+            item.count = count;
+            item
+        } else {
+            self.inventory.remove(inventory_id)
+        }
+    }
+
+    /// Combine two inventory items following a recipe. Both can be from the
+    /// same stack. Returns whether anything was made.
+    fn craft(&mut self, first_id: usize, second_id: usize) -> bool {
+        let (first, second) = (&self.inventory[first_id], &self.inventory[second_id]);
+        if first_id == second_id && first.count < 2 {
+            self.messages.message(format!("You need another {} to combine it with.", first.name), colors::WHITE);
+            return false;
+        }
+        let recipe = match (first.item, second.item) {
+            (Some(first_item), Some(second_item)) => crafting::find_recipe(first_item, second_item),
+            _ => None,
+        };
+        let recipe = match recipe {
+            Some(recipe) => recipe,
+            None => {
+                self.messages.message(format!("You can't combine the {} and the {}.", first.name, second.name),
+                                      colors::WHITE);
+                return false;
+            }
+        };
+
+        // Take out the later item first, so the other one keeps its place.
+        let first = self.take_from_inventory(std::cmp::max(first_id, second_id));
+        let second = self.take_from_inventory(std::cmp::min(first_id, second_id));
+        let (x, y) = self.objects[PLAYER].pos();
+        let result = map::make_item(recipe.result, x, y, self.location.level(), &mut rand::thread_rng());
+        self.messages.message(format!("You combine the {} and the {} into a {}.", first.name, second.name,
+                                      result.name),
+                              colors::LIGHT_GREEN);
+        self.add_to_inventory(result);
+        true
+    }
+
+    /// Put a freshly made item in the inventory, or at the player's feet if
+    /// there's no room for it.
+    fn add_to_inventory(&mut self, item: Object) {
+        match self.find_stack(&item) {
+            Some(stack_id) => self.inventory[stack_id].count += item.count,
+            None if self.inventory.len() < INVENTORY_SIZE => self.inventory.push(item),
+            None => {
+                self.messages.message(format!("Your inventory is full, the {} falls to the floor.", item.name),
+                                      colors::RED);
+                let (x, y) = self.objects[PLAYER].pos();
+                self.place_on_floor(item, x, y);
+            }
+        }
+    }
+
+    /// Mix two potions. Whether it makes a pair of greater healing potions or
+    /// blows up depends on the ingredients, and the player remembers which.
+    fn mix_potions(&mut self, first_id: usize, second_id: usize) -> UseResult {
+        let (first, second) = (&self.inventory[first_id], &self.inventory[second_id]);
+        let potions = match (first.item, second.item) {
+            (Some(first_item), Some(second_item)) if first_item.is_potion() && second_item.is_potion() =>
+                (first_item, second_item),
+            _ => {
+                self.messages.message("Only potions can be mixed.", colors::WHITE);
+                return UseResult::Cancelled;
+            }
+        };
+        if first_id == second_id && first.count < 2 {
+            self.messages.message(format!("You need another {} to mix it with.", first.name), colors::WHITE);
+            return UseResult::Cancelled;
+        }
+
+        let first = self.take_from_inventory(std::cmp::max(first_id, second_id));
+        let second = self.take_from_inventory(std::cmp::min(first_id, second_id));
+        let succeeds = alchemy::mixture_succeeds(self.alchemy_seed, potions.0, potions.1);
+        let mut names = [first.name.clone(), second.name.clone()];
+        names.sort();
+        let [first_name, second_name] = names;
+        if !self.known_mixtures.iter().any(|&(ref a, ref b, _)| *a == first_name && *b == second_name) {
+            self.known_mixtures.push((first_name, second_name, succeeds));
+        }
+
+        if succeeds {
+            self.messages.message("The potions blend into a shimmering brew.", colors::LIGHT_VIOLET);
+            let (x, y) = self.objects[PLAYER].pos();
+            let mut brew = map::make_item(Item::GreaterHeal, x, y, self.location.level(), &mut rand::thread_rng());
+            brew.count = 2;
+            self.add_to_inventory(brew);
+        } else {
+            self.messages.message_kind("The mixture boils over and explodes!", colors::ORANGE,
+                                       MessageKind::Warning);
+            let (x, y) = self.objects[PLAYER].pos();
+            for id in self.fighters_in_blast(x, y, ALCHEMY_EXPLOSION_RADIUS) {
+                self.messages.message_kind(
+                    format!("The {} gets burned for {} hit points.", self.objects[id].name,
+                            ALCHEMY_EXPLOSION_DAMAGE),
+                    colors::ORANGE,
+                    MessageKind::Combat,
+                );
+                if id == PLAYER {
+                    self.hurt_player(ALCHEMY_EXPLOSION_DAMAGE, "a botched potion");
+                } else {
+                    self.objects[id].take_damage(ALCHEMY_EXPLOSION_DAMAGE, &mut self.messages);
+                }
+            }
+        }
+        UseResult::UsedAndKept
+    }
+
+    /// Drop some of an inventory stack, the rest staying in the inventory.
+    fn drop_item(&mut self, inventory_id: usize, count: u32) {
+        // Take it off first, so it stops counting as worn, unless some of the
+        // stack stays in the quiver.
+        if count >= self.inventory[inventory_id].count && !self.inventory[inventory_id].unequip(&mut self.messages) {
+            return;
+        }
+        let item = self.take_many_from_inventory(inventory_id, count);
+        if item.count > 1 {
+            self.messages.message(format!("You dropped {} {}.", item.count, item.name), colors::YELLOW);
+        } else {
+            self.messages.message(format!("You dropped a {}.", item.name), colors::YELLOW);
+        }
+        let (x, y) = self.objects[PLAYER].pos();
+        self.place_on_floor(item, x, y);
+    }
+
+    /// Take an object off the level. The last one takes its place, so the last
+    /// target is moved along with it.
+    fn remove_object(&mut self, id: usize) -> Object {
+        let last = self.objects.len() - 1;
+        self.last_target = match self.last_target {
+            Some(target) if target == id => None,
+            Some(target) if target == last => Some(id),
+            target => target,
+        };
+        self.objects.swap_remove(id)
+    }
+
+    /// Put an item that was carried down on the map.
+    fn place_on_floor(&mut self, mut item: Object, x: i32, y: i32) {
+        if let Some(ref mut equipment) = item.equipment {
+            equipment.equipped = false;
+        }
+        item.set_pos(x, y);
+        self.objects.push(item);
+    }
+
+    /// Have dead monsters drop whatever they were carrying.
+    fn drop_carried_items(&mut self) {
+        for id in 0..self.objects.len() {
+            if id == PLAYER || self.objects[id].alive || self.objects[id].chest.is_some() ||
+                self.objects[id].carried.is_empty() {
+                continue;
+            }
+            let (x, y) = self.objects[id].pos();
+            let carried = std::mem::replace(&mut self.objects[id].carried, Vec::new());
+            for item in carried {
+                self.place_on_floor(item, x, y);
+            }
+        }
+    }
+
+    /// Celebrate any boss killed this turn. Returns the banner to show for it.
+    fn slain_boss_banner(&mut self) -> Option<String> {
+        let mut banner = None;
+        for object in self.objects.iter_mut().filter(|object| !object.alive) {
+            if let Some(boss) = object.boss.take() {
+                let text = format!("You have slain {}!", boss.name());
+                self.messages.message_kind(text.clone(), colors::GOLD, MessageKind::Critical);
+                banner = Some(text);
+            }
+        }
+        banner
+    }
+
+    /// Let a monster that collects items pick up one lying next to it,
+    /// equipping it if it can. Returns whether it did, using up its turn.
+    fn monster_pick_up(&mut self, monster_id: usize) -> bool {
+        let monster = &self.objects[monster_id];
+        if !monster.collects_items || monster.carried.len() >= MONSTER_INVENTORY_SIZE {
+            return false;
+        }
+        // Equipment is worth more than anything else.
+        let (monster_x, monster_y) = monster.pos();
+        let item_id = self.objects.iter()
+            .enumerate()
+            .filter(|&(_, object)| {
+                object.item.is_some() && object.count > 0 &&
+                    (object.x - monster_x).abs() <= 1 && (object.y - monster_y).abs() <= 1
+            })
+            .max_by_key(|&(_, object)| object.equipment.is_some())
+            .map(|(id, _)| id);
+        let item_id = match item_id {
+            Some(item_id) => item_id,
+            None => return false,
+        };
+
+        if self.fov_map.is_in_fov(monster_x, monster_y) {
+            self.messages.message(
+                format!("The {} picks up a {}.", self.objects[monster_id].name, self.objects[item_id].name),
+                colors::LIGHT_GREY,
+            );
+        }
+        // Leave an empty stack behind rather than removing the item, so the ids
+        // of the monsters still to move don't change. `end_turn` clears it away.
+        let mut item = self.objects[item_id].clone();
+        self.objects[item_id].count = 0;
+
+        let monster = &mut self.objects[monster_id];
+        if let Some(ref mut equipment) = item.equipment {
+            let slots_free = !monster.carried.iter().any(|other| {
+                other.equipment.map_or(false, |e| {
+                    e.equipped && Slot::ALL.iter().any(|&slot| e.occupies(slot) && equipment.occupies(slot))
+                })
+            });
+            equipment.equipped = slots_free;
+        }
+        monster.carried.push(item);
+        true
+    }
+
+    fn use_item(&mut self, inventory_id: usize, tcod: &mut Tcod) {
+        use Item::*;
+        // Just call the "use_function" if it is defined.
+        if let Some(item) = self.inventory[inventory_id].item.filter(|item| item.is_usable()) {
+            let result = match item {
+                Heal => self.cast_heal(HEAL_AMOUNT),
+                GreaterHeal => self.cast_heal(GREATER_HEAL_AMOUNT),
+                Lightning => self.zap_lightning(tcod),
+                Confuse => self.cast_confuse(tcod),
+                Fireball => self.cast_fireball(tcod),
+                OilFlask => self.cast_refuel(),
+                MagicMapping => self.cast_magic_mapping(),
+                RemoveCurse => self.cast_remove_curse(),
+                EnchantWeapon => self.cast_enchant(true),
+                EnchantArmor => self.cast_enchant(false),
+                Dagger | Sword | Greatsword | Shield | Armor | Boots | Ring | Amulet | Bow | Sling | Arrow |
+                Stone | Torch | Lantern => self.toggle_equipment(inventory_id),
+                WandLightning | WandConfusion => self.zap_wand(inventory_id, tcod),
+                Mortar => self.use_mortar(tcod),
+                Pickaxe => self.toggle_equipment(inventory_id),
+                Digging => self.cast_digging(tcod),
+                Key | DoorKey(_) | Gold | Relic => unreachable!(),
+            };
+            self.finish_using(inventory_id, result);
+        } else {
+            self.messages.message(
+                format!("The {} cannot be used.", self.inventory[inventory_id].name),
+                colors::WHITE,
+            );
+        }
+    }
+
+    /// The equipment a fighter has on: the inventory's for the player, and
+    /// the items it carries for a monster.
+    fn get_all_equipped(&self, id: usize) -> Vec<Equipment> {
+        if id == PLAYER {
+            equipment::get_all_equipped(&self.inventory)
+        } else {
+            equipment::get_all_equipped(&self.objects[id].carried)
+        }
+    }
+
+    /// Max HP of a fighter, with its equipment bonuses.
+    fn max_hp(&self, id: usize) -> i32 {
+        let equipped = self.get_all_equipped(id);
+        self.objects[id].fighter.map_or(0, |f| f.max_hp(&equipped))
+    }
+
+    /// Find the equipped inventory item taking up the given slot, if any.
+    fn get_equipped_in_slot(&self, slot: Slot) -> Option<usize> {
+        self.inventory.iter().position(|item| {
+            item.equipment.map_or(false, |e| e.equipped && e.occupies(slot))
+        })
+    }
+
+    /// Equip an inventory item, taking off whatever is in its way.
+    fn equip_item(&mut self, inventory_id: usize) {
+        let mut equipment = match self.inventory[inventory_id].equipment {
+            Some(equipment) => equipment,
+            None => return,
+        };
+
+        // A ring goes on whichever hand doesn't have one yet.
+        if let Some(other_ring) = equipment.slot.other_ring() {
+            if self.get_equipped_in_slot(equipment.slot).is_some() && self.get_equipped_in_slot(other_ring).is_none() {
+                equipment.slot = other_ring;
+                self.inventory[inventory_id].equipment = Some(equipment);
+            }
+        }
+
+        // Two-handed weapons won't push a shield out of the off-hand.
+        if equipment.two_handed {
+            let shield_id = self.get_equipped_in_slot(Slot::LeftHand).filter(|&id| {
+                self.inventory[id].equipment.map_or(false, |e| e.slot == Slot::LeftHand)
+            });
+            if let Some(shield_id) = shield_id {
+                self.messages.message(
+                    format!("You need to take off the {} before wielding the {}.",
+                            self.inventory[shield_id].name, self.inventory[inventory_id].name),
+                    colors::WHITE,
+                );
+                return;
+            }
+        }
+
+        for &slot in Slot::ALL.iter().filter(|&&slot| equipment.occupies(slot)) {
+            if let Some(old_id) = self.get_equipped_in_slot(slot) {
+                if !self.inventory[old_id].unequip(&mut self.messages) {
+                    return;
+                }
+            }
+        }
+        self.inventory[inventory_id].equip(&mut self.messages);
+    }
+
+    /// Show a projectile flying from one tile to another, a tile per frame,
+    /// unless animations are turned off.
+    fn animate_projectile(&mut self, tcod: &mut Tcod, from: (i32, i32), to: (i32, i32), glyph: char, color: Color) {
+        if !tcod.animate_projectiles {
+            return;
+        }
+        for (x, y) in map::line(from, to) {
+            self.render_all(tcod);
+            if let Some((x, y)) = self.to_camera_coordinates(x, y) {
+                tcod.root.set_default_foreground(color);
+                tcod.root.put_char(x, y, glyph, BackgroundFlag::None);
+            }
+            tcod.root.flush();
+            thread::sleep(Duration::from_millis(PROJECTILE_FRAME_MS));
+        }
+    }
+
+    /// Deal with an inventory item once its effect has been applied.
+    fn finish_using(&mut self, inventory_id: usize, result: UseResult) {
+        match result {
+            UseResult::UsedUp => {
+                // Destroy after use, unless it was cancelled for some reason.
+                self.take_from_inventory(inventory_id);
+            },
+            UseResult::UsedAndKept => {},
+            UseResult::Cancelled => self.messages.message("Cancelled", colors::WHITE),
+        }
+    }
+
+    /// Throw an item at a target tile, applying its effect there.
+    fn throw_item(&mut self, inventory_id: usize, tcod: &mut Tcod) {
+        use Item::*;
+        if let Some(item) = self.inventory[inventory_id].item.filter(|item| item.is_throwable()) {
+            self.messages.message(
+                format!("Left-click a target tile to throw the {} at, or right-click to cancel.",
+                        self.inventory[inventory_id].name),
+                colors::LIGHT_CYAN,
+            );
+            let target = match self.target_tile(tcod, Some(THROW_RANGE as f32)) {
+                Some(tile_pos) => tile_pos,
+                None => {
+                    self.messages.message("Cancelled", colors::WHITE);
+                    return;
+                }
+            };
+            // Let go of it first, if it's in hand.
+            if !self.inventory[inventory_id].unequip(&mut self.messages) {
+                return;
+            }
+            let from = self.objects[PLAYER].pos();
+            let (x, y) = match self.projectile_landing(from, target) {
+                Some(tile_pos) => tile_pos,
+                None => {
+                    self.messages.message("There's no room to throw it there.", colors::WHITE);
+                    return;
+                }
+            };
+            let glyph = self.inventory[inventory_id].char;
+            let color = self.inventory[inventory_id].color;
+            self.animate_projectile(tcod, from, (x, y), glyph, color);
+
+            let on_throw = match item {
+                Heal => Self::throw_heal,
+                Dagger => Self::throw_dagger,
+                GreaterHeal | Lightning | Confuse | Fireball | Torch | Lantern | OilFlask | MagicMapping |
+                RemoveCurse | Sword | Greatsword | Shield | Armor | Boots | Ring | Amulet | Bow | Sling | Arrow |
+                Stone | WandLightning | WandConfusion | Key | EnchantWeapon | EnchantArmor | DoorKey(_) | Mortar |
+                Pickaxe | Digging | Gold | Relic => unreachable!(),
+            };
+            match on_throw(self, x, y) {
+                UseResult::UsedUp if item.lands_when_thrown() => {
+                    let thrown = self.take_from_inventory(inventory_id);
+                    self.place_on_floor(thrown, x, y);
+                },
+                result => self.finish_using(inventory_id, result),
+            }
+        } else {
+            self.messages.message(
+                format!("The {} cannot be thrown.", self.inventory[inventory_id].name),
+                colors::WHITE,
+            );
+        }
+    }
+
+    /// Where something thrown from one tile at another comes down: short of
+    /// the first wall in the way, or on the first fighter it hits. None when
+    /// a wall is right in front.
+    fn projectile_landing(&self, from: (i32, i32), to: (i32, i32)) -> Option<(i32, i32)> {
+        let path = map::projectile_path(&self.map, from, to);
+        path.iter()
+            .cloned()
+            .find(|&pos| self.objects.iter().any(|object| object.fighter.is_some() && object.pos() == pos))
+            .or_else(|| path.last().cloned())
+    }
+
+    /// Whether something shot from one tile would reach another, with no
+    /// wall or fighter in the way.
+    fn clear_shot(&self, from: (i32, i32), to: (i32, i32)) -> bool {
+        self.projectile_landing(from, to) == Some(to)
+    }
+
+    /// Shoot the launcher in hand at a monster, using up a piece of its
+    /// ammo, from the quiver first. A quick shot goes at the last target if
+    /// it's still in sight, or else the closest monster, instead of asking.
+    /// Returns whether a shot was taken.
+    fn fire(&mut self, tcod: &mut Tcod, quick: bool) -> bool {
+        let launcher = self.get_equipped_in_slot(Slot::RightHand)
+            .and_then(|id| self.inventory[id].item)
+            .filter(|item| item.ammo().is_some());
+        let (launcher, ammo) = match launcher.and_then(|launcher| launcher.ammo().map(|ammo| (launcher, ammo))) {
+            Some(launcher_and_ammo) => launcher_and_ammo,
+            None => {
+                self.messages.message("You have nothing to shoot with.", colors::WHITE);
+                return false;
+            }
+        };
+        let quivered = self.get_equipped_in_slot(Slot::Quiver).filter(|&id| self.inventory[id].item == Some(ammo));
+        let ammo_id = match quivered.or_else(|| self.inventory.iter().position(|item| item.item == Some(ammo))) {
+            Some(ammo_id) => ammo_id,
+            None => {
+                self.messages.message("You are out of ammunition.", colors::RED);
+                return false;
+            }
+        };
+
+        let target_id = if quick {
+            let last_target = self.last_target.filter(|&id| self.can_shoot_at(id));
+            match last_target.or_else(|| self.closest_monster(FIRE_RANGE)) {
+                Some(target_id) => target_id,
+                None => {
+                    self.messages.message("There is no enemy in range.", colors::WHITE);
+                    return false;
+                }
+            }
+        } else {
+            self.messages.message("Left-click an enemy to shoot at, or right-click to cancel.", colors::LIGHT_CYAN);
+            match self.target_monster(tcod, Some(FIRE_RANGE as f32)) {
+                Some(target_id) => target_id,
+                None => {
+                    self.messages.message("Cancelled", colors::WHITE);
+                    return false;
+                }
+            }
+        };
+        self.last_target = Some(target_id);
+        let from = self.objects[PLAYER].pos();
+        let (x, y) = match self.projectile_landing(from, self.objects[target_id].pos()) {
+            Some(tile_pos) => tile_pos,
+            None => {
+                self.messages.message("There's no room to shoot there.", colors::WHITE);
+                return false;
+            }
+        };
+        let glyph = self.inventory[ammo_id].char;
+        let color = self.inventory[ammo_id].color;
+        self.animate_projectile(tcod, from, (x, y), glyph, color);
+        let shot = self.take_from_inventory(ammo_id);
+
+        let hit_id = self.objects.iter().position(|object| object.fighter.is_some() && object.pos() == (x, y));
+        match hit_id {
+            Some(hit_id) => {
+                let equipped = self.get_all_equipped(hit_id);
+                let defense = self.objects[hit_id].fighter.map_or(0, |f| f.defense(&equipped));
+                let enchantment = self.get_equipped_in_slot(Slot::RightHand)
+                    .and_then(|id| self.inventory[id].equipment)
+                    .map_or(0, |e| e.enchantment);
+                let mut damage = launcher.ranged_damage() + enchantment - defense;
+                // Hard to take aim with enemies breathing down your neck.
+                if self.hostile_adjacent() {
+                    damage /= 2;
+                    self.messages.message("With enemies this close, you can't aim well.", colors::LIGHT_GREY);
+                }
+                if damage > 0 {
+                    self.messages.message_kind(
+                        format!("The {} hits the {} for {} hit points.", shot.name, self.objects[hit_id].name, damage),
+                        colors::WHITE,
+                        MessageKind::Combat,
+                    );
+                    if let Some(xp) = self.objects[hit_id].take_damage(damage, &mut self.messages) {
+                        self.objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+                    }
+                } else {
+                    self.messages.message_kind(
+                        format!("The {} bounces off the {}.", shot.name, self.objects[hit_id].name),
+                        colors::WHITE,
+                        MessageKind::Combat,
+                    );
+                }
+            },
+            None => self.messages.message(format!("The {} misses.", shot.name), colors::WHITE),
+        }
+
+        if rand::thread_rng().gen_range(0, 100) >= AMMO_BREAK_CHANCE {
+            self.place_on_floor(shot, x, y);
+        }
+        true
+    }
+
+    /// Whether a monster is one the player can see, and in range to shoot.
+    fn can_shoot_at(&self, id: usize) -> bool {
+        self.objects.get(id).map_or(false, |object| {
+            id != PLAYER && object.fighter.is_some() && object.ai.as_ref().map_or(false, Ai::is_hostile) &&
+                self.fov_map.is_in_fov(object.x, object.y) &&
+                self.objects[PLAYER].distance_to(object) <= FIRE_RANGE as f32 &&
+                self.clear_shot(self.objects[PLAYER].pos(), object.pos())
+        })
+    }
+
+    /// Unlock a chest with a key from the inventory, using it up.
+    fn unlock_chest(&mut self, chest_id: usize) {
+        let key_id = match self.inventory.iter().position(|item| item.item == Some(Item::Key)) {
+            Some(key_id) => key_id,
+            None => {
+                self.messages.message("You have no key.", colors::WHITE);
+                return;
             }
-        } else {
-            // Restore the previous AI (this one will be deleted).
+        };
+        self.take_from_inventory(key_id);
+        if let Some(ref mut chest) = self.objects[chest_id].chest {
+            chest.locked = false;
+        }
+        self.messages.message("The key turns, and the lock clicks open.", colors::LIGHT_GREEN);
+    }
+
+    /// Try to break a chest's lock. Whatever is fragile inside might break too.
+    fn bash_chest(&mut self, chest_id: usize) {
+        let mut rng = rand::thread_rng();
+        if rng.gen_range(0, 100) >= CHEST_BASH_CHANCE {
+            self.messages.message("You bash the chest, but the lock holds.", colors::WHITE);
+            return;
+        }
+        self.messages.message("You bash the lock off the chest!", colors::LIGHT_GREEN);
+        let chest = &mut self.objects[chest_id];
+        if let Some(ref mut lock) = chest.chest {
+            lock.locked = false;
+        }
+        let before = chest.carried.len();
+        chest.carried.retain(|item| !item.item.map_or(false, |i| i.is_fragile()) || rng.gen());
+        if chest.carried.len() < before {
+            let pos = chest.pos();
+            self.messages.message("You hear something shatter inside.", colors::LIGHT_RED);
+            self.make_noise(pos, SHATTER_NOISE_RADIUS);
+        }
+    }
+
+    /// Take an item out of a chest, if there's room for it.
+    fn take_from_chest(&mut self, chest_id: usize, item_id: usize) {
+        let stack_id = self.find_stack(&self.objects[chest_id].carried[item_id]);
+        let is_gold = self.objects[chest_id].carried[item_id].item == Some(Item::Gold);
+        if !is_gold && stack_id.is_none() && self.inventory.len() >= INVENTORY_SIZE {
             self.messages.message(
-                format!("The {} is no longer confused!", self.objects[monster_id].name),
+                format!("Your inventory is full, cannot take the {}.", self.objects[chest_id].carried[item_id].name),
                 colors::RED,
             );
-            *previous_ai
+            return;
+        }
+        let item = self.objects[chest_id].carried.remove(item_id);
+        if is_gold {
+            self.gold += item.count;
+            self.messages.message(format!("You take {} gold pieces.", item.count), colors::GOLD);
+            return;
+        }
+        self.messages.message(format!("You take the {}.", item.name), colors::GREEN);
+        match stack_id {
+            Some(stack_id) => self.inventory[stack_id].count += item.count,
+            None => self.inventory.push(item),
         }
     }
 
-    fn player_move_or_attack(&mut self, dx: i32, dy: i32) {
-        // the coordinates the player is moving to/attacking
-        let x = self.objects[PLAYER].x + dx;
-        let y = self.objects[PLAYER].y + dy;
+    /// Put an item, or a whole stack of them, in the player's stash.
+    fn put_in_stash(&mut self, chest_id: usize, inventory_id: usize) {
+        if !self.inventory[inventory_id].unequip(&mut self.messages) {
+            return;
+        }
+        let count = self.inventory[inventory_id].count;
+        let item = self.take_many_from_inventory(inventory_id, count);
+        self.messages.message(format!("You put the {} in your stash.", item.name), colors::GREEN);
+
+        let stash = &mut self.objects[chest_id].carried;
+        let stackable = item.item.map_or(false, |i| i.is_stackable());
+        match stash.iter_mut().find(|other| stackable && other.item == item.item && other.name == item.name) {
+            Some(stack) => stack.count += item.count,
+            None => stash.push(item),
+        }
+    }
 
-        // Try to find an attackable object there.
-        let target_id = self.objects.iter().position(|object| {
-            object.fighter.is_some() && object.pos() == (x, y)
-        });
+    /// What the healer charges to heal the player fully.
+    fn healing_price(&self) -> u32 {
+        let hp = self.objects[PLAYER].fighter.map_or(0, |f| f.hp);
+        (self.max_hp(PLAYER) - hp).max(0) as u32 * HEAL_PRICE
+    }
 
-        // Attack if target found, move otherwise.
-        if let Some(target_id) = target_id {
-            let (player, target) = mut_two(PLAYER, target_id, &mut self.objects);
-            player.attack(target, &mut self.messages);
-        } else {
-            self.move_object_by(PLAYER, dx, dy);
+    /// Pay the healer to heal the player fully.
+    fn buy_healing(&mut self) {
+        let price = self.healing_price();
+        if self.gold < price {
+            self.messages.message(format!("You can't afford the {} gold for healing.", price), colors::RED);
+            return;
+        }
+        self.gold -= price;
+        let max_hp = self.max_hp(PLAYER);
+        if let Some(fighter) = self.objects[PLAYER].fighter.as_mut() {
+            fighter.hp = max_hp;
         }
+        self.messages.message(format!("The healer tends to your wounds for {} gold.", price), colors::LIGHT_VIOLET);
     }
 
-    /// Add to the player's inventory and remove from the map.
-    fn pick_item_up(&mut self, object_id: usize) {
-        if self.inventory.len() >= 26 {
-            self.messages.message(
-                format!("Your inventory is full, cannot pick up {}.", self.objects[object_id].name),
-                colors::RED,
-            );
-        } else {
-            let item = self.objects.swap_remove(object_id);
-            self.messages.message(format!("You picked up a {}!", item.name), colors::GREEN);
-            self.inventory.push(item);
-        }
+    /// Whether a hostile monster stands next to the player.
+    fn hostile_adjacent(&self) -> bool {
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        self.objects.iter().enumerate().any(|(id, object)| {
+            id != PLAYER && object.fighter.is_some() && object.ai.as_ref().map_or(false, Ai::is_hostile) &&
+                (object.x - player_x).abs() <= 1 && (object.y - player_y).abs() <= 1
+        })
     }
 
-    fn drop_item(&mut self, inventory_id: usize) {
-        let mut item = self.inventory.remove(inventory_id);
-        item.set_pos(self.objects[PLAYER].x, self.objects[PLAYER].y);
-        self.messages.message(format!("You dropped a {}.", item.name), colors::YELLOW);
-        self.objects.push(item);
+    fn throw_dagger(&mut self, x: i32, y: i32) -> UseResult {
+        // Stab whatever it hits, then drop to the floor.
+        let target_id = self.objects.iter().position(|object| object.fighter.is_some() && object.pos() == (x, y));
+        match target_id {
+            Some(target_id) => {
+                self.messages.message_kind(
+                    format!("The dagger hits the {} for {} hit points.", self.objects[target_id].name,
+                            DAGGER_THROW_DAMAGE),
+                    colors::WHITE,
+                    MessageKind::Combat,
+                );
+                if let Some(xp) = self.objects[target_id].take_damage(DAGGER_THROW_DAMAGE, &mut self.messages) {
+                    self.objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+                }
+            },
+            None => self.messages.message("The dagger clatters to the floor.", colors::WHITE),
+        }
+        UseResult::UsedUp
     }
 
-    fn use_item(&mut self, inventory_id: usize, tcod: &mut Tcod) {
-        use Item::*;
-        // Just call the "use_function" if it is defined.
-        if let Some(item) = self.inventory[inventory_id].item {
-            let on_use = match item {
-                Heal => Self::cast_heal,
-                Lightning => Self::cast_lightning,
-                Confuse => Self::cast_confuse,
-                Fireball => Self::cast_fireball,
-            };
-            match on_use(self, inventory_id, tcod) {
-                UseResult::UsedUp => {
-                    // Destroy after use, unless it was cancelled for some reason.
-                    self.inventory.remove(inventory_id);
-                },
-                UseResult::Cancelled => self.messages.message("Cancelled", colors::WHITE),
-            }
-        } else {
-            self.messages.message(
-                format!("The {} cannot be used.", self.inventory[inventory_id].name),
-                colors::WHITE,
-            );
+    fn throw_heal(&mut self, x: i32, y: i32) -> UseResult {
+        // Heal everything caught in the splash.
+        self.messages.message("The potion shatters in a cloud of soothing mist!", colors::LIGHT_VIOLET);
+        self.make_noise((x, y), SHATTER_NOISE_RADIUS);
+        for id in self.fighters_in_blast(x, y, SPLASH_RADIUS) {
+            let equipped = self.get_all_equipped(id);
+            let obj = &mut self.objects[id];
+            self.messages.message(format!("The {} looks healthier.", obj.name), colors::LIGHT_VIOLET);
+            obj.heal(HEAL_AMOUNT, &equipped);
         }
+        UseResult::UsedUp
     }
 
-    fn cast_heal(&mut self, _inventory_id: usize, _tcod: &mut Tcod) -> UseResult {
+    fn cast_heal(&mut self, amount: i32) -> UseResult {
         // Heal the player.
+        let equipped = self.get_all_equipped(PLAYER);
         if let Some(fighter) = self.objects[PLAYER].fighter {
-            if fighter.hp == fighter.max_hp {
+            if fighter.hp >= fighter.max_hp(&equipped) {
                 self.messages.message("You are already at full health.", colors::RED);
                 return UseResult::Cancelled;
             }
             self.messages.message("Your wounds start to feel better!", colors::LIGHT_VIOLET);
-            self.objects[PLAYER].heal(HEAL_AMOUNT);
+            self.objects[PLAYER].heal(amount, &equipped);
             return UseResult::UsedUp;
         }
         UseResult::Cancelled
     }
 
-    fn cast_lightning(&mut self, _inventory_id: usize, _tcod: &mut Tcod) -> UseResult {
+    /// Pick two potions to mix with a mortar.
+    fn use_mortar(&mut self, tcod: &mut Tcod) -> UseResult {
+        let mut header = String::from("Press the key next to a potion to mix, or any other to cancel.\n");
+        for &(ref first, ref second, succeeds) in &self.known_mixtures {
+            let outcome = if succeeds { "a greater brew" } else { "an explosion" };
+            header.push_str(&format!("\n{} + {}: {}", first, second, outcome));
+        }
+        let first_id = match inventory_menu(&self.inventory, &header, &mut tcod.root) {
+            Some(first_id) => first_id,
+            None => return UseResult::Cancelled,
+        };
+        let header = format!("Press the key next to a potion to mix with the {}, or any other to cancel.\n",
+                             self.inventory[first_id].name);
+        match inventory_menu(&self.inventory, &header, &mut tcod.root) {
+            Some(second_id) => self.mix_potions(first_id, second_id),
+            None => UseResult::Cancelled,
+        }
+    }
+
+    /// Use up a charge of a wand for its effect. An empty wand does nothing.
+    fn zap_wand(&mut self, inventory_id: usize, tcod: &mut Tcod) -> UseResult {
+        if self.inventory[inventory_id].charges == 0 {
+            self.messages.message("The wand fizzles.", colors::LIGHT_GREY);
+            return UseResult::UsedAndKept;
+        }
+        let result = match self.inventory[inventory_id].item {
+            Some(Item::WandLightning) => self.zap_lightning(tcod),
+            Some(Item::WandConfusion) => self.cast_confuse(tcod),
+            _ => UseResult::Cancelled,
+        };
+        match result {
+            UseResult::UsedUp => {
+                self.inventory[inventory_id].charges -= 1;
+                UseResult::UsedAndKept
+            },
+            result => result,
+        }
+    }
+
+    /// Show the bolt flying at the closest monster, then strike it.
+    fn zap_lightning(&mut self, tcod: &mut Tcod) -> UseResult {
+        if let Some(monster_id) = self.closest_monster(LIGHTNING_RANGE) {
+            let (from, to) = (self.objects[PLAYER].pos(), self.objects[monster_id].pos());
+            self.animate_projectile(tcod, from, to, '*', colors::LIGHT_BLUE);
+        }
+        self.cast_lightning()
+    }
+
+    fn cast_lightning(&mut self) -> UseResult {
         // Find closest enemy (inside a maximum range) and damage it.
         if let Some(monster_id) = self.closest_monster(LIGHTNING_RANGE) {
+            // Its flash lights up the darkness around it.
+            let (x, y) = self.objects[monster_id].pos();
+            self.dispel_darkness(x, y, LIGHTNING_FLASH_RADIUS);
+            // Armor of reflection sends the bolt right back.
+            if self.get_all_equipped(monster_id).iter().any(|e| e.reflection) {
+                self.messages.message_kind(
+                    format!("A lightning bolt bounces off the {} and strikes you! The damage is {} hit points.",
+                            self.objects[monster_id].name, LIGHTNING_DAMAGE),
+                    colors::LIGHT_BLUE,
+                    MessageKind::Combat,
+                );
+                self.hurt_player(LIGHTNING_DAMAGE, "their own lightning bolt");
+                return UseResult::UsedUp;
+            }
             // Zap it!
-            self.messages.message(
+            self.messages.message_kind(
                 format!("A lightning bolt strikes the {} with a loud thunder! \
                          The damage is {} hit points.",
                         self.objects[monster_id].name, LIGHTNING_DAMAGE),
                 colors::LIGHT_BLUE,
+                MessageKind::Combat,
             );
             if let Some(xp) = self.objects[monster_id].take_damage(LIGHTNING_DAMAGE, &mut self.messages) {
                 self.objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
@@ -510,13 +3062,15 @@ impl GameState {
         }
     }
 
-    fn cast_confuse(&mut self, _inventory_id: usize, tcod: &mut Tcod) -> UseResult {
+    fn cast_confuse(&mut self, tcod: &mut Tcod) -> UseResult {
         // Ask the player for a target to confuse.
         self.messages.message(
             "Left-click an enemy to confuse it, or right-click to cancel.",
             colors::LIGHT_CYAN,
         );
         if let Some(monster_id) = self.target_monster(tcod, Some(CONFUSE_RANGE as f32)) {
+            // No one stumbles around in their sleep.
+            self.objects[monster_id].wake(&mut self.messages);
             let old_ai = self.objects[monster_id].ai.take()
                 .unwrap_or(Ai::Basic);
             // Replace the monster's AI with a "confused" one.
@@ -535,7 +3089,7 @@ impl GameState {
         }
     }
 
-    fn cast_fireball(&mut self, _inventory_id: usize, tcod: &mut Tcod) -> UseResult {
+    fn cast_fireball(&mut self, tcod: &mut Tcod) -> UseResult {
         // Ask the player for a target tile to throw a fireball at.
         self.messages.message(
             "Left-click a target tile for the fireball, or right-click to cancel.",
@@ -545,23 +3099,27 @@ impl GameState {
             Some(tile_pos) => tile_pos,
             None => return UseResult::Cancelled,
         };
+        let from = self.objects[PLAYER].pos();
+        self.animate_projectile(tcod, from, (x, y), '*', colors::ORANGE);
         self.messages.message(
             format!("The fireball explodes, burning everything within {} tiles!", FIREBALL_RADIUS),
             colors::ORANGE,
         );
 
         let mut xp_to_gain = 0;
-        for (id, obj) in self.objects.iter_mut().enumerate() {
-            if obj.distance(x, y) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
-                self.messages.message(
-                    format!("The {} gets burned for {} hit points.", obj.name, FIREBALL_DAMAGE),
-                    colors::ORANGE,
-                );
-                if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, &mut self.messages) {
-                    // Don't reward the player for burning themself!
-                    if id != PLAYER {
-                        xp_to_gain += xp;
-                    }
+        for id in self.fighters_in_blast(x, y, FIREBALL_RADIUS) {
+            let obj = &mut self.objects[id];
+            self.messages.message_kind(
+                format!("The {} gets burned for {} hit points.", obj.name, FIREBALL_DAMAGE),
+                colors::ORANGE,
+                MessageKind::Combat,
+            );
+            if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, &mut self.messages) {
+                // Don't reward the player for burning themself!
+                if id != PLAYER {
+                    xp_to_gain += xp;
+                } else {
+                    self.killed_by = Some("their own fireball".into());
                 }
             }
         }
@@ -570,16 +3128,197 @@ impl GameState {
         UseResult::UsedUp
     }
 
-    fn handle_keys(&mut self, key: Key, tcod: &mut Tcod) -> PlayerAction {
+    fn cast_digging(&mut self, tcod: &mut Tcod) -> UseResult {
+        // Ask the player which way to dig.
+        self.messages.message(
+            "Left-click a tile to dig towards, or right-click to cancel.",
+            colors::LIGHT_CYAN,
+        );
+        let from = self.objects[PLAYER].pos();
+        let target = match self.target_tile(tcod, None) {
+            Some(tile_pos) if tile_pos != from => tile_pos,
+            _ => return UseResult::Cancelled,
+        };
+
+        // Stretch the line out to the full range of the scroll. The map's
+        // border can't be dug, so it never goes past it.
+        let (dx, dy) = ((target.0 - from.0) as f32, (target.1 - from.1) as f32);
+        let scale = DIGGING_RANGE as f32 / (dx * dx + dy * dy).sqrt();
+        let to = (from.0 + (dx * scale).round() as i32, from.1 + (dy * scale).round() as i32);
+        let mut dug = false;
+        for (x, y) in map::line(from, to) {
+            let tile = self.map[(x, y)];
+            if tile.is_diggable() {
+                self.dig(x, y);
+                dug = true;
+            } else if tile.blocked {
+                break;
+            }
+        }
+
+        if dug {
+            self.compute_fov();
+            self.messages.message("The rock crumbles away before you!", colors::LIGHT_SEPIA);
+        } else {
+            self.messages.message("The scroll's power fades against the stone.", colors::WHITE);
+        }
+        UseResult::UsedUp
+    }
+
+    fn cast_magic_mapping(&mut self) -> UseResult {
+        // Reveal the layout of the level: every open tile, and the walls around
+        // them. What's on the tiles stays hidden until it's seen.
+        self.messages.message("The scroll reveals the layout of this level!", colors::LIGHT_CYAN);
+        for x in 0..self.map.width {
+            for y in 0..self.map.height {
+                let near_open = (x - 1..x + 2).any(|nx| (y - 1..y + 2).any(|ny| {
+                    self.map.get(nx, ny).map_or(false, |tile| !tile.blocked)
+                }));
+                if near_open {
+                    self.map[(x, y)].explored = true;
+                }
+            }
+        }
+        UseResult::UsedUp
+    }
+
+    fn cast_remove_curse(&mut self) -> UseResult {
+        // Lift the curse off everything the player is wearing.
+        let mut lifted = false;
+        for item in &mut self.inventory {
+            if let Some(ref mut equipment) = item.equipment {
+                if equipment.equipped && equipment.cursed {
+                    equipment.cursed = false;
+                    lifted = true;
+                }
+            }
+        }
+        if lifted {
+            self.messages.message("You feel as if someone is watching over you.", colors::LIGHT_CYAN);
+        } else {
+            self.messages.message("You feel a fleeting sense of relief.", colors::LIGHT_CYAN);
+        }
+        UseResult::UsedUp
+    }
+
+    /// Enchant the weapon in hand, or a random piece of armor being worn.
+    fn cast_enchant(&mut self, weapon: bool) -> UseResult {
+        let candidates = self.inventory.iter()
+            .enumerate()
+            .filter(|&(_, item)| item.equipment.map_or(false, |e| e.equipped && e.is_weapon() == weapon))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        let id = match rand::thread_rng().choose(&candidates) {
+            Some(&id) => id,
+            None => {
+                let kind = if weapon { "weapon" } else { "armor" };
+                self.messages.message(format!("You have no {} on to enchant.", kind), colors::RED);
+                return UseResult::Cancelled;
+            }
+        };
+        if let Some(ref mut equipment) = self.inventory[id].equipment {
+            equipment.enchantment += 1;
+        }
+        self.messages.message(
+            format!("Your {} glows blue for a moment.", self.inventory[id].name),
+            colors::LIGHT_BLUE,
+        );
+        UseResult::UsedUp
+    }
+
+    fn toggle_equipment(&mut self, inventory_id: usize) -> UseResult {
+        match self.inventory[inventory_id].equipment {
+            Some(equipment) if equipment.equipped => {
+                if !self.inventory[inventory_id].unequip(&mut self.messages) {
+                    return UseResult::Cancelled;
+                }
+            },
+            Some(_) => self.equip_item(inventory_id),
+            None => return UseResult::Cancelled,
+        }
+        // Taking off a max HP bonus takes the extra HP with it.
+        let max_hp = self.max_hp(PLAYER);
+        if let Some(ref mut fighter) = self.objects[PLAYER].fighter {
+            fighter.hp = std::cmp::min(fighter.hp, max_hp);
+        }
+        // Some artifacts light up the surroundings.
+        self.compute_fov();
+        UseResult::UsedAndKept
+    }
+
+    fn cast_refuel(&mut self) -> UseResult {
+        // Fill up the player's light, relighting it if it went out.
+        let (id, max_fuel) = match self.get_equipped_in_slot(Slot::Light) {
+            Some(id) if self.inventory[id].item.map_or(0, |i| i.max_fuel()) > 0 => {
+                (id, self.inventory[id].item.map_or(0, |i| i.max_fuel()))
+            }
+            _ => {
+                self.messages.message("You have no torch or lantern equipped to refuel.", colors::RED);
+                return UseResult::Cancelled;
+            }
+        };
+        let light = &mut self.inventory[id];
+        if light.fuel == 0 {
+            self.messages.message(format!("You refuel your {}, and the darkness recedes.", light.name),
+                                  colors::YELLOW);
+        } else {
+            self.messages.message(format!("You top up your {}.", light.name), colors::YELLOW);
+        }
+        light.fuel = max_fuel;
+        self.compute_fov();
+        UseResult::UsedUp
+    }
+
+    /// Bind an inventory item, and any other with the same name, to a number key.
+    fn bind_hotkey(&mut self, slot: usize, inventory_id: usize) {
+        let name = self.inventory[inventory_id].name.clone();
+        self.messages.message(format!("Bound the {} to {}.", name, slot + 1), colors::WHITE);
+        self.hotkeys[slot] = Some(name);
+    }
+
+    /// Use the item bound to a number key, if the player still has one.
+    fn use_hotkey(&mut self, slot: usize, tcod: &mut Tcod) {
+        let name = match self.hotkeys[slot] {
+            Some(ref name) => name.clone(),
+            None => {
+                self.messages.message(format!("Nothing is bound to {}.", slot + 1), colors::WHITE);
+                return;
+            }
+        };
+        match self.inventory.iter().position(|item| item.name == name) {
+            Some(inventory_id) => self.use_item(inventory_id, tcod),
+            None => self.messages.message(format!("You have no {} left.", name), colors::WHITE),
+        }
+    }
+
+    fn handle_keys(&mut self, key: Key, keys: &KeyBindings, tcod: &mut Tcod) -> PlayerAction {
         // Don't move if the player is dead.
         if !self.objects[PLAYER].alive {
             return PlayerAction::DidntTakeTurn;
         }
 
         self.previous_player_pos = self.objects[PLAYER].pos();
-        match key {
-            Key { printable: 'c', .. } => {
+        let action = match keys.action_for(key) {
+            Some(action) => action,
+            None => {
+                if let Some(slot) = hotkey_slot(key) {
+                    self.use_hotkey(slot, tcod);
+                }
+                return PlayerAction::DidntTakeTurn;
+            }
+        };
+        match action {
+            Action::Character => {
                 // Show character information.
+                let equipped = self.get_all_equipped(PLAYER);
+                let slots = Slot::ALL.iter()
+                    .map(|&slot| {
+                        let name = self.get_equipped_in_slot(slot)
+                            .map_or("nothing".into(), |id| self.inventory[id].name.clone());
+                        format!("{}: {}", slot, name)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
                 let player = &self.objects[PLAYER];
                 let level = player.level;
                 let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
@@ -593,14 +3332,40 @@ Experience to level up: {}
 
 Maximum HP: {}
 Attack: {}
-Defense: {}",
-                        level, fighter.xp, level_up_xp, fighter.max_hp, fighter.power, fighter.defense);
+Defense: {}
+
+Equipment:
+{}
+
+Seed: {}",
+                        level, fighter.xp, level_up_xp, fighter.max_hp(&equipped), fighter.power(&equipped),
+                        fighter.defense(&equipped), slots, self.seed);
                     msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
                 }
 
                 PlayerAction::DidntTakeTurn
             }
-            Key { printable: 'g', .. } => {
+            Action::Minimap => {
+                // Toggle the minimap.
+                self.show_minimap = !self.show_minimap;
+                PlayerAction::DidntTakeTurn
+            }
+            Action::MessageLog => {
+                // Show the full message log.
+                message_log(&self.messages, &mut tcod.root);
+                PlayerAction::DidntTakeTurn
+            }
+            Action::FilterMessages => {
+                // Toggle hiding routine messages in the panel.
+                self.hide_routine_messages = !self.hide_routine_messages;
+                PlayerAction::DidntTakeTurn
+            }
+            Action::Look => {
+                // Look around with a cursor.
+                self.look(tcod);
+                PlayerAction::DidntTakeTurn
+            }
+            Action::PickUp => {
                 // Pick up an item.
                 let item_id = self.objects.iter().position(|object| {
                     object.pos() == self.objects[PLAYER].pos() && object.item.is_some()
@@ -610,59 +3375,185 @@ Defense: {}",
                 }
                 PlayerAction::DidntTakeTurn
             },
-            Key { printable: 'i', .. } => {
-                // Show the inventory.
+            Action::Inventory => {
+                // Show the inventory. A number key binds an item to it instead.
+                let mut header = String::from("Press the key next to an item to use it, a number to bind an item \
+                                               to it, or any other to cancel.\n");
+                for (slot, name) in self.hotkeys.iter().enumerate() {
+                    if let Some(ref name) = *name {
+                        header.push_str(&format!("{}: {}\n", slot + 1, name));
+                    }
+                }
+                let key = show_menu(&header, &inventory_options(&self.inventory), INVENTORY_WIDTH, &mut tcod.root);
+                if let Some(slot) = hotkey_slot(key) {
+                    let header = format!("Press the key next to an item to bind to {}, or any other to cancel.\n",
+                                         slot + 1);
+                    if let Some(inventory_index) = inventory_menu(&self.inventory, &header, &mut tcod.root) {
+                        self.bind_hotkey(slot, inventory_index);
+                    }
+                } else if let Some(inventory_index) = menu_index(key, self.inventory.len()) {
+                    self.use_item(inventory_index, tcod);
+                }
+                PlayerAction::DidntTakeTurn
+            },
+            Action::Examine => {
+                // Show the inventory. If an item is selected, describe it.
                 let inventory_index = inventory_menu(
                     &self.inventory,
-                    "Press the key next to an item to use it, or any other to cancel.\n",
+                    "Press the key next to an item to examine it, or any other to cancel.\n",
                     &mut tcod.root);
                 if let Some(inventory_index) = inventory_index {
-                    self.use_item(inventory_index, tcod);
+                    examine_screen(&self.inventory[inventory_index], &mut tcod.root);
                 }
                 PlayerAction::DidntTakeTurn
             },
-            Key { printable: 'd', .. } => {
+            Action::Drop => {
                 // Show the inventory. If an item is selected, drop it.
                 let inventory_index = inventory_menu(
                     &self.inventory,
                     "Press the key next to an item to drop it, or any other to cancel.\n'",
                     &mut tcod.root);
                 if let Some(inventory_index) = inventory_index {
-                    self.drop_item(inventory_index);
+                    let item = &self.inventory[inventory_index];
+                    let count = if item.count > 1 {
+                        let header = format!("Drop how many {}? (1-{}, Enter for all)", item.name, item.count);
+                        quantity_input(&header, item.count, &mut tcod.root)
+                    } else {
+                        Some(1)
+                    };
+                    if let Some(count) = count {
+                        self.drop_item(inventory_index, count);
+                    }
                 }
                 PlayerAction::DidntTakeTurn
             },
-            Key { printable: '.', shift: true, .. } => {
-                // Go down stairs, if the player is on them.
-                let player_on_stairs = self.objects.iter().any(|object|
-                    object.pos() == self.objects[PLAYER].pos() && object.name == "stairs"
-                );
-                if player_on_stairs {
-                    self.next_level();
+            Action::Craft => {
+                // Pick two items from the inventory, and combine them.
+                let first_id = match inventory_menu(
+                    &self.inventory,
+                    "Press the key next to an item to combine, or any other to cancel.\n",
+                    &mut tcod.root) {
+                    Some(first_id) => first_id,
+                    None => return PlayerAction::DidntTakeTurn,
+                };
+                let header = format!("Press the key next to an item to combine with the {}, or any other to \
+                                      cancel.\n", self.inventory[first_id].name);
+                if let Some(second_id) = inventory_menu(&self.inventory, &header, &mut tcod.root) {
+                    if self.craft(first_id, second_id) {
+                        return PlayerAction::TookTurn;
+                    }
                 }
                 PlayerAction::DidntTakeTurn
             },
-            Key { printable: '.', .. } => {
-                // Simply wait a turn.
-                PlayerAction::TookTurn
-            }
-            Key { code: KeyCode::Left, .. } => {
-                self.player_move_or_attack(-1, 0);
-                PlayerAction::TookTurn
+            Action::Throw => {
+                // Show the inventory. If an item is selected, throw it.
+                let inventory_index = inventory_menu(
+                    &self.inventory,
+                    "Press the key next to an item to throw it, or any other to cancel.\n",
+                    &mut tcod.root);
+                if let Some(inventory_index) = inventory_index {
+                    self.throw_item(inventory_index, tcod);
+                }
+                PlayerAction::DidntTakeTurn
             },
-            Key { code: KeyCode::Right, .. } => {
-                self.player_move_or_attack(1, 0);
-                PlayerAction::TookTurn
+            Action::Fire | Action::QuickFire => {
+                // Shoot the equipped launcher at a monster.
+                if self.fire(tcod, action == Action::QuickFire) {
+                    PlayerAction::TookTurn
+                } else {
+                    PlayerAction::DidntTakeTurn
+                }
             },
-            Key { code: KeyCode::Up, .. } => {
-                self.player_move_or_attack(0, -1);
-                PlayerAction::TookTurn
+            Action::Open => {
+                // Open a chest next to the player.
+                let (player_x, player_y) = self.objects[PLAYER].pos();
+                let chest_id = self.objects.iter().position(|object| {
+                    object.chest.is_some() && (object.x - player_x).abs() <= 1 && (object.y - player_y).abs() <= 1
+                });
+                match chest_id {
+                    Some(chest_id) => {
+                        self.bumped_into = Some(chest_id);
+                        PlayerAction::TookTurn
+                    },
+                    None => {
+                        self.messages.message("There's nothing here to open.", colors::WHITE);
+                        PlayerAction::DidntTakeTurn
+                    },
+                }
             },
-            Key { code: KeyCode::Down, .. } => {
-                self.player_move_or_attack(0, 1);
-                PlayerAction::TookTurn
+            Action::Descend => {
+                // Go down stairs, if the player is on them: further down this
+                // branch, or into another one. The road out of town leads to
+                // the wilderness instead.
+                if self.player_on("road") {
+                    self.travelling = true;
+                } else if self.player_on("stairs") {
+                    let below = self.location.offset(1);
+                    let number = self.stairs_number("stairs");
+                    self.change_level(below, Arrival::Stairs("upstairs", number));
+                } else if let Some(&branch) = Branch::ALL.iter().find(|b| self.player_on(&b.stairs_name())) {
+                    self.change_level(Location { branch, depth: 1 }, Arrival::Stairs("upstairs", 0));
+                }
+                PlayerAction::DidntTakeTurn
+            },
+            Action::Ascend => {
+                // Go up stairs, if the player is on them. From the top of a
+                // branch, they lead back out of it, and from the first level,
+                // up to the town. From a dungeon in the wilderness, the player
+                // walks back to town along the road.
+                if !self.player_on("upstairs") {
+                    return PlayerAction::DidntTakeTurn;
+                }
+                if let Some(above) = self.location.above() {
+                    let arrival = if above.branch == self.location.branch {
+                        "stairs".into()
+                    } else if above.is_town() {
+                        "road".into()
+                    } else {
+                        self.location.branch.stairs_name()
+                    };
+                    let number = self.stairs_number("upstairs");
+                    self.change_level(above, Arrival::Stairs(&arrival, number));
+                    if above.is_town() && self.inventory.iter().any(|item| item.item == Some(Item::Relic)) {
+                        self.escaped = true;
+                        self.messages.message_kind("You climb out into the daylight, the crown in your hands!",
+                                                   colors::GOLD, MessageKind::Critical);
+                    }
+                }
+                PlayerAction::DidntTakeTurn
             },
-            _ => PlayerAction::DidntTakeTurn,
+            Action::Wait => {
+                // Simply wait a turn.
+                PlayerAction::TookTurn
+            }
+            Action::Rest => {
+                // Rest until healed or disturbed. The turns pass inside `rest`.
+                self.rest();
+                PlayerAction::DidntTakeTurn
+            }
+            Action::Search => {
+                // Look around carefully for traps, and dig through any rubble
+                // at hand, which takes a turn.
+                let found_traps = self.spot_traps(SEARCH_RADIUS, SEARCH_CHANCE);
+                if !self.search_rubble() && !found_traps {
+                    self.messages.message("You search around, but find nothing.", colors::WHITE);
+                }
+                PlayerAction::TookTurn
+            }
+            Action::Sneak => {
+                self.sneaking = !self.sneaking;
+                let message = if self.sneaking {
+                    "You start sneaking, slow and quiet."
+                } else {
+                    "You stop sneaking."
+                };
+                self.messages.message(message, colors::LIGHT_GREY);
+                PlayerAction::DidntTakeTurn
+            }
+            Action::MoveLeft => self.player_step(-1, 0),
+            Action::MoveRight => self.player_step(1, 0),
+            Action::MoveUp => self.player_step(0, -1),
+            Action::MoveDown => self.player_step(0, 1),
         }
     }
 
@@ -687,7 +3578,7 @@ Defense: {}",
 
             // Accept the target if the player clicked in FOV, and in case a range
             // is specified, if it's in that range.
-            let in_fov = (x < map::MAP_WIDTH) && (y < map::MAP_HEIGHT) && self.fov_map.is_in_fov(x, y);
+            let in_fov = self.map.in_bounds(x, y) && self.fov_map.is_in_fov(x, y);
             let in_range = max_range.map_or(
                 true, |range| self.objects[PLAYER].distance(x, y) <= range);
             if self.mouse.lbutton_pressed && in_fov && in_range {
@@ -724,19 +3615,20 @@ Defense: {}",
 
         // New camera coordinates (top-left corner of the screen relative to the map).
         // Coordinates so that the target is at the center of the screen.
-        let mut x = target_x - CAMERA_WIDTH / 2;
-        let mut y = target_y - CAMERA_HEIGHT / 2;
+        let (camera_width, camera_height) = self.camera_size;
+        let mut x = target_x - camera_width / 2;
+        let mut y = target_y - camera_height / 2;
 
         // Clamp the viewport to the map edges.
         if x < 0 {
             x = 0;
-        } else if x > map::MAP_WIDTH - CAMERA_WIDTH - 1 {
-            x = map::MAP_WIDTH - CAMERA_WIDTH - 1;
+        } else if x > self.map.width - camera_width - 1 {
+            x = self.map.width - camera_width - 1;
         }
         if y < 0 {
             y = 0;
-        } else if y > map::MAP_HEIGHT - CAMERA_HEIGHT - 1 {
-            y = map::MAP_HEIGHT - CAMERA_HEIGHT - 1;
+        } else if y > self.map.height - camera_height - 1 {
+            y = self.map.height - camera_height - 1;
         }
 
         if x != self.camera_pos.0 || y != self.camera_pos.1 {
@@ -753,7 +3645,7 @@ Defense: {}",
         let (x, y) = (x - self.camera_pos.0, y - self.camera_pos.1);
 
         // Check that the coordinates are inside the view.
-        if x < 0 || y < 0 || x >= CAMERA_WIDTH || y >= CAMERA_HEIGHT {
+        if x < 0 || y < 0 || x >= self.camera_size.0 || y >= self.camera_size.1 {
             None
         } else {
             Some((x, y))
@@ -765,15 +3657,22 @@ Defense: {}",
         (x + self.camera_pos.0, y + self.camera_pos.1)
     }
 
+    /// Whether the player can currently see an object: it's in FOV, or it's
+    /// always visible on an explored tile. Hidden traps are never visible.
+    fn is_visible(&self, object: &Object) -> bool {
+        let known = object.trap.map_or(true, |trap| trap.revealed);
+        self.disable_fov || (known &&
+            (self.fov_map.is_in_fov(object.x, object.y) ||
+             (object.always_visible && self.map[(object.x, object.y)].explored)))
+    }
+
     /// Return a string with the names of all objects under the mouse.
     fn get_names_under_mouse(&self) -> String {
         let (x, y) = self.to_world_coordinates(self.mouse.cx as i32, self.mouse.cy as i32);
 
         // Create a list with the names of all objects at the mouse's coordinates and in FOV.
         let names = self.objects.iter()
-            .filter(|obj| obj.pos() == (x, y) &&
-                    (self.disable_fov || self.fov_map.is_in_fov(obj.x, obj.y) ||
-                     (obj.always_visible && self.map[obj.x as usize][obj.y as usize].explored)))
+            .filter(|obj| obj.pos() == (x, y) && self.is_visible(obj))
             .map(|obj| obj.name.as_ref())
             .collect::<Vec<_>>();
 
@@ -781,56 +3680,189 @@ Defense: {}",
         names.join(", ")
     }
 
+    /// Describe what the player can see on a tile, including the health and
+    /// state of any monsters there.
+    fn describe_tile(&self, x: i32, y: i32) -> String {
+        let tile = &self.map[(x, y)];
+        if !(self.disable_fov || tile.explored) {
+            return "You don't know what's there.".into();
+        }
+
+        let descriptions = self.objects.iter()
+            .enumerate()
+            .filter(|&(_, obj)| obj.pos() == (x, y) && self.is_visible(obj))
+            .map(|(id, obj)| match (obj.fighter, obj.ai.as_ref()) {
+                (Some(fighter), Some(ai)) =>
+                    format!("{} ({}/{} HP, {})", obj.name, fighter.hp, self.max_hp(id), ai.describe()),
+                (Some(fighter), None) =>
+                    format!("{} ({}/{} HP)", obj.name, fighter.hp, self.max_hp(id)),
+                _ => obj.name.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        if !descriptions.is_empty() {
+            descriptions.join(", ")
+        } else if tile.blocked {
+            "A wall.".into()
+        } else if let Some(feature) = tile.feature {
+            feature.description().into()
+        } else {
+            "The floor.".into()
+        }
+    }
+
+    /// Let the player move a cursor around with the arrow keys, describing
+    /// whatever is under it. Doesn't take a turn.
+    fn look(&mut self, tcod: &mut Tcod) {
+        self.look_cursor = Some(self.objects[PLAYER].pos());
+
+        while let Some((x, y)) = self.look_cursor {
+            if tcod.root.window_closed() {
+                break;
+            }
+            self.render_all(tcod);
+            tcod.root.flush();
+
+            let (dx, dy) = match tcod.root.wait_for_keypress(true) {
+                Key { code: KeyCode::Left, .. } => (-1, 0),
+                Key { code: KeyCode::Right, .. } => (1, 0),
+                Key { code: KeyCode::Up, .. } => (0, -1),
+                Key { code: KeyCode::Down, .. } => (0, 1),
+                Key { code: KeyCode::Escape, .. } | Key { code: KeyCode::Enter, .. } |
+                Key { printable: 'x', .. } => break,
+                _ => continue,
+            };
+
+            // Keep the cursor on the map and inside the camera view.
+            let (x, y) = (x + dx, y + dy);
+            if self.map.in_bounds(x, y) && self.to_camera_coordinates(x, y).is_some() {
+                self.look_cursor = Some((x, y));
+            }
+        }
+
+        self.look_cursor = None;
+    }
+
+    /// Draw the explored parts of the map, scaled down to fit the minimap box,
+    /// in the top-right corner of the screen.
+    fn render_minimap(&self, tcod: &mut Tcod) {
+        let (width, height) = (self.map.width, self.map.height);
+        for cell_y in 0..MINIMAP_HEIGHT {
+            for cell_x in 0..MINIMAP_WIDTH {
+                // Each cell stands for a block of map tiles. It shows as floor if
+                // any explored tile in the block is open.
+                let mut explored = false;
+                let mut open = false;
+                for x in (cell_x * width / MINIMAP_WIDTH)..((cell_x + 1) * width / MINIMAP_WIDTH) {
+                    for y in (cell_y * height / MINIMAP_HEIGHT)..((cell_y + 1) * height / MINIMAP_HEIGHT) {
+                        let tile = &self.map[(x, y)];
+                        if self.disable_fov || tile.explored {
+                            explored = true;
+                            open = open || !tile.blocked;
+                        }
+                    }
+                }
+                let color = match (explored, open) {
+                    (false, _) => colors::BLACK,
+                    (true, false) => self.map.theme.dark_wall,
+                    (true, true) => self.map.theme.dark_ground,
+                };
+                tcod.minimap.set_char_background(cell_x, cell_y, color, BackgroundFlag::Set);
+                tcod.minimap.put_char(cell_x, cell_y, ' ', BackgroundFlag::None);
+            }
+        }
+
+        // Mark the stairs, once discovered, and the player on top.
+        let to_cell = |x: i32, y: i32| (x * MINIMAP_WIDTH / width, y * MINIMAP_HEIGHT / height);
+        let is_stairs = |obj: &Object| obj.name.contains("stairs");
+        for object in self.objects.iter().filter(|obj| is_stairs(obj) && self.is_visible(obj)) {
+            let (cell_x, cell_y) = to_cell(object.x, object.y);
+            tcod.minimap.set_char_background(cell_x, cell_y, colors::YELLOW, BackgroundFlag::Set);
+        }
+        let (cell_x, cell_y) = to_cell(self.objects[PLAYER].x, self.objects[PLAYER].y);
+        tcod.minimap.set_char_background(cell_x, cell_y, colors::WHITE, BackgroundFlag::Set);
+
+        console::blit(&tcod.minimap, (0, 0), (MINIMAP_WIDTH, MINIMAP_HEIGHT),
+                      &mut tcod.root, (self.camera_size.0 - MINIMAP_WIDTH, 0), 1.0, 0.9);
+    }
+
     fn render_all(&mut self, tcod: &mut Tcod) {
+        // Fit the view to the window, which may have been resized. The camera
+        // takes what the panel leaves, but can't show more than the map.
+        let (screen_width, screen_height) = tcod.fit_to_root();
+        self.camera_size = (std::cmp::min(screen_width, self.map.width - 1),
+                            std::cmp::min(screen_height - PANEL_HEIGHT, self.map.height - 1));
+
         let (player_x, player_y) = (self.objects[PLAYER].x, self.objects[PLAYER].y);
         let fov_recompute = self.move_camera(player_x, player_y) ||
             self.previous_player_pos != (player_x, player_y);
 
         if fov_recompute {
             // Recompute FOV if needed (the player moved or something).
-            self.fov_map.compute_fov(player_x, player_y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+            self.compute_fov();
         }
 
-        // Go through all tiles, and update their background color.
-        for y in 0..CAMERA_HEIGHT {
-            for x in 0..CAMERA_WIDTH {
+        // Go through all tiles, and update their background color and glyph.
+        let theme = self.map.theme.clone();
+        tcod.con.set_default_background(colors::BLACK);
+        tcod.con.clear();
+        for y in 0..self.camera_size.1 {
+            for x in 0..self.camera_size.0 {
                 let (map_x, map_y) = (self.camera_pos.0 + x, self.camera_pos.1 + y);
                 let visible = self.fov_map.is_in_fov(map_x, map_y);
-                let wall = self.map[map_x as usize][map_y as usize].block_sight;
+                let tile = self.map[(map_x, map_y)];
+                let wall = tile.block_sight;
                 let color = match (visible, wall) {
+                    // Water, lava and chasms have colors of their own.
+                    (_, false) if tile.terrain != Terrain::Ground => tile.terrain.color(visible).unwrap(),
                     // Outside of field of view:
-                    (false, true) => COLOR_DARK_WALL,
-                    (false, false) => COLOR_DARK_GROUND,
+                    (false, true) => theme.dark_wall,
+                    (false, false) => theme.dark_ground,
                     // Inside fov:
-                    (true, true) => COLOR_LIGHT_WALL,
-                    (true, false) => COLOR_LIGHT_GROUND,
+                    (true, true) => theme.light_wall,
+                    (true, false) => theme.light_ground,
+                };
+                let feature = tile.feature.filter(|_| !wall).map(Feature::glyph);
+                let glyph = if tile.lock.is_some() {
+                    '+'
+                } else if wall {
+                    theme.wall_char
+                } else if let Some((glyph, _)) = feature {
+                    glyph
+                } else {
+                    tile.terrain.glyph()
                 };
 
-                let explored = &mut self.map[map_x as usize][map_y as usize].explored;
+                let explored = &mut self.map[(map_x, map_y)].explored;
                 if visible {
                     // Since it's visible, explore it.
                     *explored = true;
                 }
                 if self.disable_fov || *explored {
                     // Show explored tiles only (any visible tile is explored already).
-                    tcod.con.set_char_background(x, y, color, BackgroundFlag::Set);
+                    // Features keep their own colors, dimmed out of sight.
+                    let glyph_color = match feature {
+                        Some((_, feature_color)) if visible => feature_color,
+                        Some((_, feature_color)) => colors::lerp(feature_color, colors::BLACK, 0.5),
+                        None => colors::lerp(color, colors::BLACK, 0.4),
+                    };
+                    tcod.con.put_char_ex(x, y, glyph, glyph_color, color);
                 } else {
                     // Clear the tile.
-                    tcod.con.set_char_background(x, y, colors::BLACK, BackgroundFlag::Set);
+                    tcod.con.put_char_ex(x, y, ' ', colors::BLACK, colors::BLACK);
                 }
             }
         }
 
+        // Highlight the tile being looked at.
+        if let Some((x, y)) = self.look_cursor.and_then(|(x, y)| self.to_camera_coordinates(x, y)) {
+            tcod.con.set_char_background(x, y, colors::LIGHT_GREY, BackgroundFlag::Set);
+        }
+
         // Filter out visible objects and sort them so that non-blocking ones come first.
-        let mut to_draw: Vec<_> = if self.disable_fov {
-            self.objects.iter()
-                .collect()
-        } else {
-            self.objects.iter()
-                .filter(|obj| self.fov_map.is_in_fov(obj.x, obj.y) ||
-                        (obj.always_visible && self.map[obj.x as usize][obj.y as usize].explored))
-                .collect()
-        };
+        let mut to_draw: Vec<_> = self.objects.iter()
+            .filter(|obj| self.is_visible(obj))
+            .collect();
         to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
 
         // Draw all objects.
@@ -841,51 +3873,106 @@ Defense: {}",
             }
         }
 
-        console::blit(&tcod.con, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), &mut tcod.root, (0, 0), 1.0, 1.0);
+        console::blit(&tcod.con, (0, 0), (screen_width, screen_height), &mut tcod.root, (0, 0), 1.0, 1.0);
+
+        if self.show_minimap {
+            self.render_minimap(tcod);
+        }
 
         // Prepare to render the GUI panel.
         tcod.panel.set_default_background(colors::BLACK);
         tcod.panel.clear();
 
-        // Print the game messages, one line at a time.
+        // The bars grow with the window, and the messages get the rest of the width.
+        let bar_width = std::cmp::max(BAR_WIDTH, screen_width / 4);
+        let msg_x = bar_width + 2;
+        let msg_width = screen_width - msg_x;
+
+        // Print the game messages, one line at a time, leaving out the routine
+        // ones if they're hidden.
+        let hide_routine_messages = self.hide_routine_messages;
+        let shown_messages = self.messages.iter().rev()
+            .filter(|&&(_, _, kind)| !hide_routine_messages || kind.is_important());
         let mut y = MSG_HEIGHT as i32;
-        for &(ref msg, color) in self.messages.iter().rev() {
-            let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+        for &(ref msg, color, kind) in shown_messages {
+            let msg_height = tcod.panel.get_height_rect(msg_x, y, msg_width, 0, msg);
             y -= msg_height;
             if y < 0 {
                 break;
             }
             tcod.panel.set_default_foreground(color);
-            tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+            if kind == MessageKind::Critical {
+                // Make critical messages stand out.
+                tcod.panel.set_default_background(colors::DARKER_RED);
+                tcod.panel.print_rect_ex(msg_x, y, msg_width, 0, BackgroundFlag::Set, TextAlignment::Left, msg);
+                tcod.panel.set_default_background(colors::BLACK);
+            } else {
+                tcod.panel.print_rect(msg_x, y, msg_width, 0, msg);
+            }
         }
 
         // Show the player's stats.
         let hp = self.objects[PLAYER].fighter.map_or(0, |f| f.hp);
-        let max_hp = self.objects[PLAYER].fighter.map_or(0, |f| f.max_hp);
-        render_bar(&mut tcod.panel, 1, 1, BAR_WIDTH, "HP", hp, max_hp, colors::LIGHT_RED, colors::DARKER_RED);
+        let max_hp = self.max_hp(PLAYER);
+        render_bar(&mut tcod.panel, 1, 1, bar_width, "HP", hp, max_hp, colors::LIGHT_RED, colors::DARKER_RED);
 
         tcod.panel.print_ex(1, 3, BackgroundFlag::None, TextAlignment::Left,
-                            format!("Dungeon level: {}", self.dungeon_level));
+                            self.location.to_string());
+        tcod.panel.print_ex(1, 4, BackgroundFlag::None, TextAlignment::Left,
+                            format!("Gold: {}", self.gold));
+        if self.sneaking {
+            tcod.panel.print_ex(bar_width, 4, BackgroundFlag::None, TextAlignment::Right, "Sneaking");
+        }
+        if self.ascending {
+            tcod.panel.set_default_foreground(colors::GOLD);
+            tcod.panel.print_ex(1, 5, BackgroundFlag::None, TextAlignment::Left, "Escape with the crown!");
+        }
+
+        // The name of the level goes above where it is, and what sets it apart below.
+        tcod.panel.set_default_foreground(self.map.theme.message_color);
+        tcod.panel.print_rect(1, 2, bar_width, 1, &self.map.name);
+        if !self.map.modifiers.is_empty() {
+            let modifiers: Vec<&str> = self.map.modifiers.iter().map(|modifier| modifier.name()).collect();
+            tcod.panel.set_default_foreground(colors::LIGHT_VIOLET);
+            tcod.panel.print_rect(1, 6, bar_width, 1, modifiers.join(", "));
+        }
 
-        // Display names of objects under the mouse.
+        // Display names of objects under the mouse, or what's under the look cursor.
+        let names = match self.look_cursor {
+            Some((x, y)) => self.describe_tile(x, y),
+            None => self.get_names_under_mouse(),
+        };
         tcod.panel.set_default_foreground(colors::LIGHT_GREY);
-        tcod.panel.print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left,
-                            self.get_names_under_mouse());
+        tcod.panel.print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left, names);
 
         // Blit the contents of `panel` to the root console.
-        console::blit(&tcod.panel, (0, 0), (SCREEN_WIDTH, PANEL_HEIGHT), &mut tcod.root, (0, PANEL_Y), 1.0, 1.0);
+        console::blit(&tcod.panel, (0, 0), (screen_width, PANEL_HEIGHT), &mut tcod.root,
+                      (0, screen_height - PANEL_HEIGHT), 1.0, 1.0);
     }
 }
 
 fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32,
                        root: &mut Root) -> Option<usize> {
+    let options = options.iter().map(|option| (option.as_ref(), colors::WHITE)).collect::<Vec<_>>();
+    colored_menu(header, &options, width, root)
+}
+
+/// A menu with each option in its own color.
+fn colored_menu<T: AsRef<str>>(header: &str, options: &[(T, Color)], width: i32,
+                               root: &mut Root) -> Option<usize> {
+    let key = show_menu(header, options, width, root);
+    menu_index(key, options.len())
+}
+
+/// Show a menu and wait for a key press, returning the key.
+fn show_menu<T: AsRef<str>>(header: &str, options: &[(T, Color)], width: i32, root: &mut Root) -> Key {
     assert!(options.len() <= 26, "Cannot have a menu with more than 26 options.");
 
     // Calculate total height for the header (after auto-wrap) and one line per option.
     let header_height = if header.is_empty() {
         0
     } else {
-        root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header)
+        root.get_height_rect(0, 0, width, root.get_height(), header)
     };
     let height = options.len() as i32 + header_height;
 
@@ -897,27 +3984,31 @@ fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32,
     window.print_rect_ex(0, 0, width, height, BackgroundFlag::None, TextAlignment::Left, header);
 
     // Print all the options.
-    for (index, option_text) in options.iter().enumerate() {
+    for (index, &(ref option_text, color)) in options.iter().enumerate() {
         let menu_letter = (b'a' + index as u8) as char;
         let text = format!("({}) {}", menu_letter, option_text.as_ref());
+        window.set_default_foreground(color);
         window.print_ex(0, header_height + index as i32,
                         BackgroundFlag::None, TextAlignment::Left, text);
     }
 
     // Blit the contents of "window" to the root console.
-    let x = SCREEN_WIDTH / 2 - width / 2;
-    let y = SCREEN_HEIGHT / 2 - height / 2;
+    let x = root.get_width() / 2 - width / 2;
+    let y = root.get_height() / 2 - height / 2;
     console::blit(&mut window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
 
     // Present the root console to the player and wait for a key-press.
     root.flush();
     // TODO: Include this in the main loop!
-    let key = root.wait_for_keypress(true);
+    root.wait_for_keypress(true)
+}
 
+/// The menu option a key picks, if any.
+fn menu_index(key: Key, num_options: usize) -> Option<usize> {
     // Convert the ASCII code to an index; if it corresponds to an option, return it.
     if key.printable.is_alphabetic() {
         let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
-        if index < options.len() {
+        if index < num_options {
             Some(index)
         } else {
             None
@@ -927,15 +4018,24 @@ fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32,
     }
 }
 
-fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
-    // How a menu with each item of the inventory as an option.
-    let options = if inventory.len() == 0 {
-        vec!["Inventory is empty."]
+/// The hotkey slot of a number key from 1 to 9.
+fn hotkey_slot(key: Key) -> Option<usize> {
+    key.printable.to_digit(10).filter(|&digit| digit >= 1).map(|digit| digit as usize - 1)
+}
+
+/// Each item of the inventory as a menu option, in its rarity's color.
+fn inventory_options(inventory: &[Object]) -> Vec<(String, Color)> {
+    if inventory.len() == 0 {
+        vec![("Inventory is empty.".into(), colors::WHITE)]
     } else {
-        inventory.iter().map(|item| item.name.as_ref()).collect()
-    };
+        inventory.iter().map(|item| (item.inventory_name(), item.rarity.color())).collect::<Vec<_>>()
+    }
+}
 
-    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
+fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
+    // How a menu with each item of the inventory as an option.
+    let options = inventory_options(inventory);
+    let inventory_index = colored_menu(header, &options, INVENTORY_WIDTH, root);
 
     // If an item was chosen, return it.
     if inventory.len() > 0 {
@@ -974,12 +4074,18 @@ fn render_bar(panel: &mut Offscreen,
                    &format!("{}: {}/{}", name, value, maximum));
 }
 
-fn play_game(game_state: &mut GameState, tcod: &mut Tcod) {
+fn play_game(game_state: &mut GameState, keys: &KeyBindings, tcod: &mut Tcod) {
     while !tcod.root.window_closed() {
         let mut key = None;
+        let mut click = None;
 
         match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
-           Some((_, Event::Mouse(m))) => game_state.mouse = m,
+           Some((_, Event::Mouse(m))) => {
+               game_state.mouse = m;
+               if m.lbutton_pressed || m.rbutton_pressed {
+                   click = Some(m);
+               }
+           },
            Some((_, Event::Key(k))) => key = Some(k),
            _ => {},
         }
@@ -997,9 +4103,12 @@ fn play_game(game_state: &mut GameState, tcod: &mut Tcod) {
             }
         }
 
+        let player_pos = game_state.objects[PLAYER].pos();
         let player_action = if let Some(key) = key {
+            // Any key stops walking to a clicked tile.
+            game_state.walk_target = None;
             match key {
-                Key { code: KeyCode::Escape, .. } => PlayerAction::Exit,
+                Key { code: KeyCode::Escape, .. } => quit_menu(game_state, &mut tcod.root),
                 Key { code: KeyCode::Enter, left_alt: true, .. } => {
                     let fullscreen = !tcod.root.is_fullscreen();
                     tcod.root.set_fullscreen(fullscreen);
@@ -1009,27 +4118,412 @@ fn play_game(game_state: &mut GameState, tcod: &mut Tcod) {
                     game_state.disable_fov = !game_state.disable_fov;
                     PlayerAction::DidntTakeTurn
                 },
-                key => game_state.handle_keys(key, tcod),
+                key => game_state.handle_keys(key, keys, tcod),
             }
+        } else if let Some(mouse) = click {
+            game_state.handle_click(mouse)
         } else {
-            PlayerAction::DidntTakeTurn
+            game_state.auto_walk()
         };
 
         if player_action == PlayerAction::Exit {
-            game_state.save()
-                .expect("Failed to save the game.");
             break;
         }
 
+        // Walking over items picks up the kinds the player asked for.
+        if game_state.objects[PLAYER].pos() != player_pos {
+            game_state.auto_pick_up(&tcod.auto_pickup);
+        }
+
+        // Bumping into a shopkeeper opens their shop, into a healer asks for
+        // healing, and into a chest opens it.
+        if let Some(id) = game_state.bumped_into.take() {
+            if game_state.objects[id].chest.is_some() {
+                chest_screen(game_state, id, &mut tcod.root);
+            } else if game_state.objects[id].ai == Some(Ai::Healer) {
+                healer_screen(game_state, id, &mut tcod.root);
+            } else {
+                shop_screen(game_state, id, &mut tcod.root);
+            }
+        }
+
+        // Taking the road out of town shows the wilderness, to pick where to go.
+        if mem::replace(&mut game_state.travelling, false) {
+            if let Some(to) = overworld_screen(game_state, &mut tcod.root) {
+                game_state.travel(to);
+            }
+        }
+
+        // Monsters killed this turn drop their loot.
+        game_state.drop_carried_items();
+        if let Some(banner) = game_state.slain_boss_banner() {
+            game_state.render_all(tcod);
+            msgbox(&format!("\n{}\n", banner), BANNER_WIDTH, &mut tcod.root);
+        }
+
         // Let monsters take their turn.
         if game_state.objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            // Skip the first object, which should be the player.
-            for id in 0..game_state.objects.len() {
-                if game_state.objects[id].ai.is_some() {
-                    game_state.ai_take_turn(id);
+            game_state.end_turn();
+        }
+
+        // Game over, lost or won: show what happened and let the player start
+        // over or leave.
+        if !game_state.objects[PLAYER].alive || game_state.escaped {
+            game_state.render_all(tcod);
+            if let Err(err) = GameState::delete_save() {
+                game_state.messages.message(format!("Could not delete the save: {}", err), colors::RED);
+            }
+            match highscore::record(game_state.high_score()) {
+                Ok(scores) => show_high_scores(&scores, &mut tcod.root),
+                Err(err) => msgbox(&format!("\nCould not save the high scores: {}\n", err),
+                                   HIGH_SCORES_WIDTH, &mut tcod.root),
+            }
+            let play_again = if game_state.escaped {
+                victory_screen(game_state, &mut tcod.root)
+            } else {
+                death_screen(game_state, &mut tcod.root)
+            };
+            let restart = if play_again {
+                new_game(None, &mut tcod.root)
+            } else {
+                None
+            };
+            match restart {
+                Some(new_game_state) => *game_state = new_game_state,
+                None => break,
+            }
+        }
+    }
+
+    // Closing the window shouldn't lose the run.
+    if tcod.root.window_closed() && game_state.objects[PLAYER].alive {
+        if let Err(err) = game_state.save() {
+            eprintln!("Could not save the game: {}", err);
+        }
+    }
+}
+
+/// Ask whether to save before quitting to the main menu. Returns `Exit` to
+/// quit, or `DidntTakeTurn` to go back to the game.
+fn quit_menu(game_state: &GameState, root: &mut Root) -> PlayerAction {
+    let choices = &["Save and quit to menu", "Quit without saving", "Cancel"];
+    match menu("", choices, 24, root) {
+        Some(0) => match game_state.save() {
+            Ok(()) => PlayerAction::Exit,
+            Err(err) => {
+                // Stay in the game, so the run isn't lost.
+                msgbox(&format!("\nCould not save the game: {}\n", err), 50, root);
+                PlayerAction::DidntTakeTurn
+            }
+        },
+        Some(1) => PlayerAction::Exit,
+        _ => PlayerAction::DidntTakeTurn,
+    }
+}
+
+/// Let the player buy from and sell to a shopkeeper, until they leave or
+/// attack.
+fn shop_screen(game_state: &mut GameState, shopkeeper_id: usize, root: &mut Root) {
+    loop {
+        let header = format!("\"Welcome, traveller!\" You have {} gold.\n", game_state.gold);
+        let choices = &["Buy", "Sell", "Attack the shopkeeper", "Leave"];
+        match menu(&header, choices, SHOP_WIDTH, root) {
+            Some(0) => {
+                let stock = &game_state.objects[shopkeeper_id].carried;
+                if stock.is_empty() {
+                    msgbox("\nThe shelves are bare.\n", SHOP_WIDTH, root);
+                    continue;
+                }
+                let options = stock.iter()
+                    .map(|item| {
+                        let price = item.item.map_or(0, |i| i.value());
+                        (format!("{} - {} gold", item.inventory_name(), price), item.rarity.color())
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(stock_id) = colored_menu("Buy which item?\n", &options, SHOP_WIDTH, root) {
+                    game_state.buy(shopkeeper_id, stock_id);
+                }
+            },
+            Some(1) => {
+                if game_state.inventory.is_empty() {
+                    msgbox("\nYou have nothing to sell.\n", SHOP_WIDTH, root);
+                    continue;
+                }
+                let options = game_state.inventory.iter()
+                    .map(|item| {
+                        let price = item.item.map_or(0, |i| i.value()) / 2;
+                        (format!("{} - {} gold", item.inventory_name(), price), item.rarity.color())
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(inventory_id) = colored_menu("Sell which item?\n", &options, SHOP_WIDTH, root) {
+                    game_state.sell(shopkeeper_id, inventory_id);
+                }
+            },
+            Some(2) => {
+                game_state.objects[shopkeeper_id].anger(&mut game_state.messages);
+                game_state.player_attack(shopkeeper_id);
+                return;
+            },
+            _ => return,
+        }
+    }
+}
+
+/// Show the wilderness around the town, with the dungeons out there, and let
+/// the player pick one to travel to.
+fn overworld_screen(game_state: &GameState, root: &mut Root) -> Option<Location> {
+    let land = Overworld::land(game_state.seed);
+    let sites = &game_state.overworld.sites;
+    let destinations: Vec<_> = sites.iter().filter(|site| !site.location.is_town()).collect();
+    let options: Vec<(String, Color)> = destinations.iter().map(|site| {
+        let branch = site.location.branch;
+        let bottom = Location { branch, depth: branch.bottom().unwrap_or(1) };
+        let progress = if site.cleared {
+            ", cleared"
+        } else if site.visited {
+            ", visited"
+        } else {
+            ""
+        };
+        let text = format!("The {} (levels {} to {}{})", branch.name(), site.location.level(), bottom.level(),
+                           progress);
+        (text, if site.cleared { colors::LIGHT_GREY } else { colors::WHITE })
+    }).collect();
+
+    let (width, height) = (overworld::WIDTH, overworld::HEIGHT + 2 + options.len() as i32);
+    let mut window = Offscreen::new(width, height);
+    for x in 0..overworld::WIDTH {
+        for y in 0..overworld::HEIGHT {
+            let (glyph, color) = land[x as usize][y as usize].glyph();
+            window.put_char_ex(x, y, glyph, color, colors::BLACK);
+        }
+    }
+    // The town, where the player is, and each dungeon marked by its letter.
+    for site in sites.iter().filter(|site| site.location.is_town()) {
+        window.put_char_ex(site.pos.0, site.pos.1, '@', colors::WHITE, colors::BLACK);
+    }
+    for (index, site) in destinations.iter().enumerate() {
+        let letter = (b'a' + index as u8) as char;
+        window.put_char_ex(site.pos.0, site.pos.1, letter, options[index].1, colors::BLACK);
+    }
+
+    window.set_default_foreground(colors::WHITE);
+    window.print_ex(0, overworld::HEIGHT + 1, BackgroundFlag::None, TextAlignment::Left,
+                    "Travel where? The town is at the @.");
+    for (index, &(ref text, color)) in options.iter().enumerate() {
+        let letter = (b'a' + index as u8) as char;
+        window.set_default_foreground(color);
+        window.print_ex(0, overworld::HEIGHT + 2 + index as i32, BackgroundFlag::None, TextAlignment::Left,
+                        format!("({}) {}", letter, text));
+    }
+
+    let x = root.get_width() / 2 - width / 2;
+    let y = root.get_height() / 2 - height / 2;
+    console::blit(&mut window, (0, 0), (width, height), root, (x, y), 1.0, 0.9);
+    root.flush();
+    let key = root.wait_for_keypress(true);
+    menu_index(key, options.len()).map(|index| destinations[index].location)
+}
+
+/// Talk to a healer, who heals the player fully for gold.
+fn healer_screen(game_state: &mut GameState, healer_id: usize, root: &mut Root) {
+    let price = game_state.healing_price();
+    if price == 0 {
+        msgbox("\n\"You look well enough to me.\"\n", SHOP_WIDTH, root);
+        return;
+    }
+    let header = format!("\"Let me see those wounds.\" You have {} gold.\n", game_state.gold);
+    let heal = format!("Heal fully for {} gold", price);
+    match menu(&header, &[&heal, "Attack the healer", "Leave"], SHOP_WIDTH, root) {
+        Some(0) => game_state.buy_healing(),
+        Some(1) => {
+            game_state.objects[healer_id].anger(&mut game_state.messages);
+            game_state.player_attack(healer_id);
+        }
+        _ => {}
+    }
+}
+
+/// Open a chest, unlocking or bashing it first if needed, and let the player
+/// take what's inside, or put things in their stash.
+fn chest_screen(game_state: &mut GameState, chest_id: usize, root: &mut Root) {
+    loop {
+        if game_state.objects[chest_id].chest.map_or(false, |chest| chest.locked) {
+            let choices = &["Unlock it with a key", "Bash it open", "Leave it"];
+            match menu("The chest is locked.\n", choices, CHEST_WIDTH, root) {
+                Some(0) => game_state.unlock_chest(chest_id),
+                Some(1) => {
+                    // Bashing takes a turn, so show one attempt at a time.
+                    game_state.bash_chest(chest_id);
+                    return;
+                },
+                _ => return,
+            }
+            if game_state.objects[chest_id].chest.map_or(false, |chest| chest.locked) {
+                return;
+            }
+        }
+
+        // The stash also takes items in.
+        if game_state.objects[chest_id].chest.map_or(false, |chest| chest.stash) {
+            let header = format!("Your stash holds {} items.\n", game_state.objects[chest_id].carried.len());
+            match menu(&header, &["Take an item out", "Put an item in", "Leave it"], CHEST_WIDTH, root) {
+                Some(0) => {}
+                Some(1) => {
+                    if game_state.inventory.is_empty() {
+                        msgbox("\nYou have nothing to put in.\n", CHEST_WIDTH, root);
+                        continue;
+                    }
+                    let options = game_state.inventory.iter()
+                        .map(|item| (item.inventory_name(), item.rarity.color()))
+                        .collect::<Vec<_>>();
+                    if let Some(inventory_id) = colored_menu("Put which item in?\n", &options, CHEST_WIDTH, root) {
+                        game_state.put_in_stash(chest_id, inventory_id);
+                    }
+                    continue;
                 }
+                _ => return,
             }
         }
+
+        let contents = &game_state.objects[chest_id].carried;
+        if contents.is_empty() {
+            msgbox("\nThe chest is empty.\n", CHEST_WIDTH, root);
+            return;
+        }
+        let options = contents.iter().map(|item| (item.inventory_name(), item.rarity.color())).collect::<Vec<_>>();
+        match colored_menu("Take which item?\n", &options, CHEST_WIDTH, root) {
+            Some(item_id) => game_state.take_from_chest(chest_id, item_id),
+            None => return,
+        }
+    }
+}
+
+/// Show the death screen with a summary of the run. Returns whether the
+/// player wants to start a new game.
+fn death_screen(game_state: &GameState, root: &mut Root) -> bool {
+    let player = &game_state.objects[PLAYER];
+    let msg = format!(
+"You died!
+
+Location: {}
+Character level: {}
+Experience: {}
+Turns survived: {}
+Seed: {}
+",
+        game_state.location, player.level, player.fighter.map_or(0, |f| f.xp), game_state.turn,
+        game_state.seed);
+
+    let mut choice = None;
+    // Keep asking until a choice is made.
+    while choice.is_none() && !root.window_closed() {
+        choice = menu(&msg, &["New game", "Quit to main menu"], CHARACTER_SCREEN_WIDTH, root);
+    }
+    choice == Some(0)
+}
+
+/// Show the victory screen with a summary of the run. Returns whether the
+/// player wants to start a new game.
+fn victory_screen(game_state: &GameState, root: &mut Root) -> bool {
+    let player = &game_state.objects[PLAYER];
+    let msg = format!(
+"You escaped with the Crown of the Ancient Kings!
+
+Character level: {}
+Experience: {}
+Gold: {}
+Deepest level: {}
+Turns taken: {}
+Seed: {}
+",
+        player.level, player.fighter.map_or(0, |f| f.xp), game_state.gold, game_state.deepest_level(),
+        game_state.turn, game_state.seed);
+
+    let mut choice = None;
+    // Keep asking until a choice is made.
+    while choice.is_none() && !root.window_closed() {
+        choice = menu(&msg, &["New game", "Quit to main menu"], CHARACTER_SCREEN_WIDTH, root);
+    }
+    choice == Some(0)
+}
+
+/// Ask the player to type a line of text, shown in a window like `menu`.
+/// Returns None if the player pressed Escape.
+fn text_input(header: &str, max_length: usize, width: i32, root: &mut Root) -> Option<String> {
+    filtered_text_input(header, max_length, width, &|c| c.is_ascii_graphic() || c == ' ', root)
+}
+
+/// Ask the player how many of something, up to `max`. Entering nothing means
+/// all of them, and None means the player cancelled.
+fn quantity_input(header: &str, max: u32, root: &mut Root) -> Option<u32> {
+    let digits = max.to_string().len();
+    let text = filtered_text_input(header, digits, QUANTITY_WIDTH, &|c| c.is_ascii_digit(), root)?;
+    if text.is_empty() {
+        return Some(max);
+    }
+    match text.parse::<u32>() {
+        Ok(0) | Err(_) => None,
+        Ok(count) => Some(std::cmp::min(count, max)),
+    }
+}
+
+/// Like `text_input`, only taking the characters `accept` lets through.
+fn filtered_text_input(header: &str, max_length: usize, width: i32, accept: &Fn(char) -> bool, root: &mut Root)
+                       -> Option<String> {
+    let header_height = root.get_height_rect(0, 0, width, root.get_height(), header);
+    let height = header_height + 2;
+    let mut window = Offscreen::new(width, height);
+    let mut text = String::new();
+
+    while !root.window_closed() {
+        window.set_default_background(colors::BLACK);
+        window.clear();
+        window.set_default_foreground(colors::WHITE);
+        window.print_rect_ex(0, 0, width, height, BackgroundFlag::None, TextAlignment::Left, header);
+        window.print_ex(0, header_height + 1, BackgroundFlag::None, TextAlignment::Left,
+                        format!("> {}_", text));
+
+        let x = root.get_width() / 2 - width / 2;
+        let y = root.get_height() / 2 - height / 2;
+        console::blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+        root.flush();
+
+        match root.wait_for_keypress(true) {
+            Key { code: KeyCode::Enter, .. } | Key { code: KeyCode::NumPadEnter, .. } => return Some(text),
+            Key { code: KeyCode::Escape, .. } => return None,
+            Key { code: KeyCode::Backspace, .. } => {
+                text.pop();
+            },
+            Key { printable, .. } => {
+                if accept(printable) && text.len() < max_length {
+                    text.push(printable);
+                }
+            },
+        }
+    }
+    None
+}
+
+/// Ask for the player's name and start a new game, or None if cancelled.
+/// Without a seed, the run gets a random one.
+fn new_game(seed: Option<u64>, root: &mut Root) -> Option<GameState> {
+    text_input("What is your name, stranger?", MAX_NAME_LENGTH, 40, root).map(|name| {
+        let name = name.trim();
+        GameState::new(if name.is_empty() { "player" } else { name }, seed.unwrap_or_else(rand::random))
+    })
+}
+
+/// Ask the player for the seed of a run, or None if cancelled.
+fn seed_input(root: &mut Root) -> Option<u64> {
+    let max_length = u64::max_value().to_string().len();
+    let text = filtered_text_input("Enter the seed of the run:", max_length, 40, &|c| c.is_ascii_digit(), root)?;
+    match text.parse() {
+        Ok(seed) => Some(seed),
+        Err(_) => {
+            msgbox("\nThat is not a valid seed.\n", 30, root);
+            None
+        }
     }
 }
 
@@ -1038,10 +4532,268 @@ fn msgbox(text: &str, width: i32, root: &mut Root) {
     menu(text, options, width, root);
 }
 
+fn show_high_scores(scores: &[HighScore], root: &mut Root) {
+    if scores.is_empty() {
+        msgbox("\nNo high scores yet.\n", 24, root);
+        return;
+    }
+
+    let mut text = format!("High scores\n\n    {:<14} {:>5} {:>6} {:>6}  {}\n",
+                           "Name", "Depth", "XP", "Turns", "Fate");
+    for (rank, score) in scores.iter().enumerate() {
+        text.push_str(&format!("{:>2}. {:<14} {:>5} {:>6} {:>6}  {}\n",
+                               rank + 1, score.name, score.dungeon_level, score.xp, score.turns,
+                               score.cause_of_death));
+    }
+    msgbox(&text, HIGH_SCORES_WIDTH, root);
+}
+
+/// Show the whole message log full-screen, scrollable with the arrow and page
+/// keys, until Escape is pressed.
+fn message_log(messages: &Messages, root: &mut Root) {
+    let mut window = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let page = (SCREEN_HEIGHT - 2) as usize;
+    let max_scroll = messages.len().saturating_sub(1);
+    // Number of the newest messages scrolled off the bottom of the view.
+    let mut scroll = 0;
+
+    while !root.window_closed() {
+        window.set_default_background(colors::BLACK);
+        window.clear();
+
+        window.set_default_foreground(colors::WHITE);
+        window.print_ex(SCREEN_WIDTH / 2, 0, BackgroundFlag::None, TextAlignment::Center,
+                        "Message log");
+        window.set_default_foreground(colors::LIGHT_GREY);
+        window.print_ex(SCREEN_WIDTH / 2, SCREEN_HEIGHT - 1, BackgroundFlag::None, TextAlignment::Center,
+                        "Up/Down/PageUp/PageDown to scroll, Escape to close");
+
+        // Print the messages bottom-up, starting at the scroll position.
+        let mut y = SCREEN_HEIGHT - 1;
+        for &(ref msg, color, _) in messages.iter().rev().skip(scroll) {
+            let msg_height = window.get_height_rect(1, y, SCREEN_WIDTH - 2, 0, msg);
+            y -= msg_height;
+            if y < 1 {
+                break;
+            }
+            window.set_default_foreground(color);
+            window.print_rect(1, y, SCREEN_WIDTH - 2, 0, msg);
+        }
+
+        console::blit(&window, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
+        root.flush();
+
+        match root.wait_for_keypress(true) {
+            Key { code: KeyCode::Up, .. } => scroll = std::cmp::min(scroll + 1, max_scroll),
+            Key { code: KeyCode::Down, .. } => scroll = scroll.saturating_sub(1),
+            Key { code: KeyCode::PageUp, .. } => scroll = std::cmp::min(scroll + page, max_scroll),
+            Key { code: KeyCode::PageDown, .. } => scroll = scroll.saturating_sub(page),
+            Key { code: KeyCode::Escape, .. } => break,
+            _ => {},
+        }
+    }
+}
+
+/// What the player knows about an item: its stats, enchantment and charges.
+fn item_details(item: &Object) -> Vec<String> {
+    let mut details = vec![];
+    if let Some(equipment) = item.equipment {
+        let hands = if equipment.two_handed { ", two-handed" } else { "" };
+        details.push(format!("Slot: {}{}", equipment.slot, hands));
+        if equipment.power() != 0 {
+            details.push(format!("Power: {:+}", equipment.power()));
+        }
+        if equipment.defense() != 0 {
+            details.push(format!("Defense: {:+}", equipment.defense()));
+        }
+        if equipment.max_hp_bonus != 0 {
+            details.push(format!("Maximum HP: {:+}", equipment.max_hp_bonus));
+        }
+        if let Some(brand) = equipment.brand {
+            details.push(format!("Brand: {}, {} {} damage", brand, brand.damage(), brand.element()));
+        }
+        if let Some(resistance) = equipment.resistance {
+            details.push(format!("Resists: {}", resistance.element()));
+        }
+        if equipment.thorns != 0 {
+            details.push(format!("Thorns: {}% of melee damage dealt back", equipment.thorns));
+        }
+        if equipment.reflection {
+            details.push("Reflects bolt spells".into());
+        }
+        if let Some(artifact) = equipment.artifact {
+            details.push(format!("Artifact: {}", artifact.description()));
+        }
+        if equipment.cursed && equipment.curse_known {
+            details.push("Cursed: can't be taken off".into());
+        }
+        if equipment.equipped {
+            details.push("Equipped".into());
+        }
+    }
+    if let Some(kind) = item.item {
+        if kind.is_wand() {
+            details.push(format!("Charges: {}", item.charges));
+        }
+        if let Some(light_radius) = kind.light_radius() {
+            details.push(format!("Light radius: {}", light_radius));
+            details.push(format!("Fuel: {}/{} turns", item.fuel, kind.max_fuel()));
+        }
+        if kind.is_stackable() {
+            details.push(format!("Count: {}", item.count));
+        }
+        details.push(format!("Value: {} gold", kind.value() * item.count));
+    }
+    details
+}
+
+/// Show everything about an item full-screen, until a key is pressed.
+fn examine_screen(item: &Object, root: &mut Root) {
+    let mut window = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    window.set_default_background(colors::BLACK);
+    window.clear();
+
+    window.set_default_foreground(item.rarity.color());
+    window.print_ex(SCREEN_WIDTH / 2, 1, BackgroundFlag::None, TextAlignment::Center, item.display_name());
+    window.set_default_foreground(colors::WHITE);
+    let mut y = 3;
+    if let Some(kind) = item.item {
+        let text = descriptions::flavor_text(kind);
+        window.print_rect(2, y, SCREEN_WIDTH - 4, 0, text);
+        y += window.get_height_rect(2, y, SCREEN_WIDTH - 4, 0, text) + 1;
+    }
+    window.set_default_foreground(colors::LIGHT_GREY);
+    for line in item_details(item) {
+        window.print(2, y, line);
+        y += 1;
+    }
+    window.print_ex(SCREEN_WIDTH / 2, SCREEN_HEIGHT - 1, BackgroundFlag::None, TextAlignment::Center,
+                    "Press any key to close");
+
+    console::blit(&window, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
+    root.flush();
+    root.wait_for_keypress(true);
+}
+
+/// Let the player pick actions and press the keys to bind them to, until
+/// they go back. Every change is saved right away.
+fn key_bindings_screen(keys: &mut KeyBindings, root: &mut Root) {
+    while !root.window_closed() {
+        let options: Vec<String> = Action::ALL.iter()
+            .map(|&action| {
+                let key = keys.get(action).map_or("none".into(), |binding| binding.to_string());
+                format!("{}: {}", action.description(), key)
+            })
+            .collect();
+        let choice = menu("Choose an action to rebind, or any other key to go back.\n",
+                          &options, KEY_BINDINGS_WIDTH, root);
+        let action = match choice {
+            Some(index) => Action::ALL[index],
+            None => return,
+        };
+
+        // Ask for the new key, like `msgbox` but keeping the key press.
+        let header = format!("\nPress the new key for {}, or Escape to cancel.\n", action.description());
+        let height = root.get_height_rect(0, 0, KEY_BINDINGS_WIDTH, root.get_height(), &header);
+        let mut window = Offscreen::new(KEY_BINDINGS_WIDTH, height);
+        window.set_default_foreground(colors::WHITE);
+        window.print_rect_ex(0, 0, KEY_BINDINGS_WIDTH, height, BackgroundFlag::None, TextAlignment::Left, &header);
+        let x = root.get_width() / 2 - KEY_BINDINGS_WIDTH / 2;
+        let y = root.get_height() / 2 - height / 2;
+        console::blit(&window, (0, 0), (KEY_BINDINGS_WIDTH, height), root, (x, y), 1.0, 0.7);
+        root.flush();
+
+        // Skip presses that can't be bound, like Shift on its own.
+        let binding = loop {
+            let key = root.wait_for_keypress(true);
+            if key.code == KeyCode::Escape || root.window_closed() {
+                break None;
+            }
+            if let Some(binding) = KeyBinding::from_key(key) {
+                break Some(binding);
+            }
+        };
+
+        if let Some(binding) = binding {
+            let key_name = binding.to_string();
+            match keys.bind(action, binding) {
+                Ok(()) => if let Err(err) = keys.save() {
+                    msgbox(&format!("\nCould not save the key bindings: {}\n", err), 50, root);
+                },
+                Err(other) => msgbox(&format!("\n{} is already used for {}. Rebind that first.\n",
+                                              key_name, other.description()),
+                                     KEY_BINDINGS_WIDTH, root),
+            }
+        }
+    }
+}
+
+/// Pick a font from the assets folder and how its glyphs are laid out. The
+/// font is set when the window is created, so it takes a restart to change.
+fn graphics_menu(root: &mut Root) {
+    // Menus only go up to 26 options.
+    let mut fonts = config::available_fonts();
+    fonts.truncate(26);
+    if fonts.is_empty() {
+        msgbox("\nNo fonts found in the assets folder.\n", 24, root);
+        return;
+    }
+    let mut config = Config::load();
+
+    let header = format!("Current font: {}\nChoose a new one, or any other key to go back.\n", config.font);
+    let font = match menu(&header, &fonts, INVENTORY_WIDTH, root) {
+        Some(index) => fonts[index].clone(),
+        None => return,
+    };
+    let layouts: Vec<&str> = Layout::ALL.iter().map(|layout| layout.description()).collect();
+    let layout = match menu("How are the glyphs laid out in the font?\n", &layouts, INVENTORY_WIDTH, root) {
+        Some(index) => Layout::ALL[index],
+        None => return,
+    };
+
+    config.font = font;
+    config.font_layout = layout;
+    match config.save() {
+        Ok(()) => msgbox("\nThe new font will be used the next time you start the game.\n", 30, root),
+        Err(err) => msgbox(&format!("\nCould not save the settings: {}\n", err), 50, root),
+    }
+}
+
+/// Let the player toggle which kinds of items are picked up automatically,
+/// until they go back. Every change is saved right away.
+fn auto_pickup_menu(tcod: &mut Tcod) {
+    let mut config = Config::load();
+    while !tcod.root.window_closed() {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let settings = config.auto_pickup;
+        let options = [
+            format!("Gold: {}", on_off(settings.gold)),
+            format!("Potions: {}", on_off(settings.potions)),
+            format!("Scrolls: {}", on_off(settings.scrolls)),
+            format!("Ammo: {}", on_off(settings.ammo)),
+        ];
+        let setting = match menu("Choose what to pick up by walking over it, or any other key to go back.\n",
+                                 &options, INVENTORY_WIDTH, &mut tcod.root) {
+            Some(0) => &mut config.auto_pickup.gold,
+            Some(1) => &mut config.auto_pickup.potions,
+            Some(2) => &mut config.auto_pickup.scrolls,
+            Some(3) => &mut config.auto_pickup.ammo,
+            _ => return,
+        };
+        *setting = !*setting;
+        tcod.auto_pickup = config.auto_pickup;
+        if let Err(err) = config.save() {
+            msgbox(&format!("\nCould not save the settings: {}\n", err), 50, &mut tcod.root);
+        }
+    }
+}
+
 fn main_menu(tcod: &mut Tcod) {
     let img = tcod::image::Image::from_file("assets/menu_background.png")
         .ok().expect("Background image not found");
 
+    let mut keys = KeyBindings::load();
+
     while !tcod.root.window_closed() {
         // Show the background image, at twice the regular console resolution.
         tcod::image::blit_2x(&img, (0, 0), (-1, -1), &mut tcod.root, (0, 0));
@@ -1055,25 +4807,48 @@ fn main_menu(tcod: &mut Tcod) {
                            "By Mystal");
 
         // Show options and wait for the player's choice.
-        let choices = &["Play a new game", "Continue last game", "Quit"];
+        let choices = &["Play a new game", "Play a seeded game", "Continue last game", "High scores", "Controls",
+                        "Graphics", "Auto-pickup", "Quit"];
         let choice = menu("", choices, 24, &mut tcod.root);
 
         match choice {
             // New game.
             Some(0) => {
-                let mut game_state = GameState::new();
-                play_game(&mut game_state, tcod);
+                if let Some(mut game_state) = new_game(None, &mut tcod.root) {
+                    play_game(&mut game_state, &keys, tcod);
+                }
+            },
+            // New game, from a seed.
+            Some(1) => {
+                let game_state = seed_input(&mut tcod.root).and_then(|seed| new_game(Some(seed), &mut tcod.root));
+                if let Some(mut game_state) = game_state {
+                    play_game(&mut game_state, &keys, tcod);
+                }
             },
             // Load game.
-            Some(1) => match GameState::from_save() {
-                Ok(mut game_state) => play_game(&mut game_state, tcod),
-                Err(_) => {
-                    msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
+            Some(2) => match GameState::from_save() {
+                Ok(mut game_state) => play_game(&mut game_state, &keys, tcod),
+                Err(err) => {
+                    let no_save = err.downcast_ref::<io::Error>()
+                        .map_or(false, |err| err.kind() == io::ErrorKind::NotFound);
+                    if no_save {
+                        msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
+                    } else {
+                        msgbox(&format!("\nCould not load the saved game: {}\n", err), 50, &mut tcod.root);
+                    }
                     continue;
                 }
             },
+            // High scores.
+            Some(3) => show_high_scores(&highscore::load(), &mut tcod.root),
+            // Key bindings.
+            Some(4) => key_bindings_screen(&mut keys, &mut tcod.root),
+            // Font.
+            Some(5) => graphics_menu(&mut tcod.root),
+            // Auto-pickup.
+            Some(6) => auto_pickup_menu(tcod),
             // Quit.
-            Some(2) => break,
+            Some(7) => break,
             _ => {}
         }
     }
@@ -1082,18 +4857,323 @@ fn main_menu(tcod: &mut Tcod) {
 fn main() {
     tcod::system::set_fps(LIMIT_FPS);
 
+    // Fall back to the default font if the configured one can't be loaded,
+    // and say so once the window is up.
+    let mut config = Config::load();
+    let font_warning = config.validate_font();
+
     let root = Root::initializer()
         .size(SCREEN_WIDTH, SCREEN_HEIGHT)
         .title("Rust libtcod tutorial")
-        .font("assets/arial10x10.png", tcod::FontLayout::Tcod)
-        .font_type(tcod::FontType::Greyscale)
+        .font(&config.font, config.font_layout.to_tcod())
+        .font_type(config.font_type())
         .init();
 
     let mut tcod = Tcod {
         root: root,
-        con: Offscreen::new(map::MAP_WIDTH, map::MAP_HEIGHT),
+        con: Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT),
         panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
+        minimap: Offscreen::new(MINIMAP_WIDTH, MINIMAP_HEIGHT),
+        animate_projectiles: config.animations,
+        auto_pickup: config.auto_pickup,
     };
 
+    if let Some(warning) = font_warning {
+        msgbox(&format!("\n{}\n", warning), 50, &mut tcod.root);
+    }
+
     main_menu(&mut tcod);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::{SeedableRng, StdRng};
+
+    use map::Tile;
+
+    use super::*;
+
+    /// A fresh game with nothing carried, so tests start from a known
+    /// inventory.
+    fn new_game() -> GameState {
+        let mut game_state = GameState::new("tester", 1);
+        game_state.inventory.clear();
+        game_state
+    }
+
+    /// Put an uncursed item in the player's pack and return its inventory id.
+    fn give(game_state: &mut GameState, item: Item) -> usize {
+        let mut rng = StdRng::from_seed(&[game_state.inventory.len()]);
+        let mut object = map::make_item(item, 0, 0, 1, &mut rng);
+        if let Some(ref mut equipment) = object.equipment {
+            equipment.cursed = false;
+        }
+        game_state.inventory.push(object);
+        game_state.inventory.len() - 1
+    }
+
+    fn is_equipped(game_state: &GameState, inventory_id: usize) -> bool {
+        game_state.inventory[inventory_id].equipment.map_or(false, |e| e.equipped)
+    }
+
+    #[test]
+    fn equipping_swaps_out_an_occupied_slot() {
+        let mut game_state = new_game();
+        let first = give(&mut game_state, Item::Sword);
+        let second = give(&mut game_state, Item::Sword);
+        game_state.equip_item(first);
+        game_state.equip_item(second);
+        assert!(!is_equipped(&game_state, first));
+        assert!(is_equipped(&game_state, second));
+        assert_eq!(game_state.get_equipped_in_slot(Slot::RightHand), Some(second));
+    }
+
+    #[test]
+    fn two_handed_weapon_is_refused_while_a_shield_is_worn() {
+        let mut game_state = new_game();
+        let shield = give(&mut game_state, Item::Shield);
+        let greatsword = give(&mut game_state, Item::Greatsword);
+        game_state.equip_item(shield);
+        game_state.equip_item(greatsword);
+        assert!(is_equipped(&game_state, shield));
+        assert!(!is_equipped(&game_state, greatsword));
+        assert_eq!(game_state.get_equipped_in_slot(Slot::RightHand), None);
+    }
+
+    #[test]
+    fn light_stays_lit_with_both_hands_full() {
+        let mut game_state = new_game();
+        let torch = give(&mut game_state, Item::Torch);
+        let shield = give(&mut game_state, Item::Shield);
+        let greatsword = give(&mut game_state, Item::Greatsword);
+        game_state.equip_item(torch);
+        let lit_radius = game_state.sight_radius();
+        assert!(lit_radius > DARK_SIGHT_RADIUS);
+
+        game_state.equip_item(shield);
+        assert!(is_equipped(&game_state, torch));
+        assert_eq!(game_state.sight_radius(), lit_radius);
+
+        game_state.inventory[shield].unequip(&mut game_state.messages);
+        game_state.equip_item(greatsword);
+        assert!(is_equipped(&game_state, greatsword));
+        assert!(is_equipped(&game_state, torch));
+        assert_eq!(game_state.sight_radius(), lit_radius);
+    }
+
+    #[test]
+    fn dropped_equipment_stops_counting() {
+        let mut game_state = new_game();
+        let shield = give(&mut game_state, Item::Shield);
+        let fighter = game_state.objects[PLAYER].fighter.unwrap();
+        let bare_defense = fighter.defense(&game_state.get_all_equipped(PLAYER));
+        // Affixes can make it better or worse, so take its defense as it came.
+        let shield_defense = game_state.inventory[shield].equipment.unwrap().defense();
+        game_state.equip_item(shield);
+        assert_eq!(fighter.defense(&game_state.get_all_equipped(PLAYER)), bare_defense + shield_defense);
+
+        game_state.drop_item(shield, 1);
+        assert!(game_state.inventory.is_empty());
+        assert!(game_state.get_all_equipped(PLAYER).is_empty());
+        assert_eq!(fighter.defense(&game_state.get_all_equipped(PLAYER)), bare_defense);
+        let player_pos = game_state.objects[PLAYER].pos();
+        let dropped = game_state.objects.iter()
+            .find(|object| object.item == Some(Item::Shield) && object.pos() == player_pos)
+            .expect("the shield should be on the floor");
+        assert_eq!(dropped.equipment.map(|e| e.equipped), Some(false));
+    }
+
+    #[test]
+    fn scripted_moves_and_item_uses() {
+        // Clear out an open room with just the player and an orc in it.
+        let mut game_state = GameState::new("tester", 1);
+        game_state.objects.truncate(PLAYER + 1);
+        for x in 5..20 {
+            for y in 5..15 {
+                game_state.map[(x, y)] = Tile::empty();
+            }
+        }
+        game_state.objects[PLAYER].set_pos(10, 10);
+        let mut rng = StdRng::from_seed(&[1]);
+        let mut orc = map::make_monster("orc", 14, 10, 1, &mut rng);
+        orc.fighter.as_mut().unwrap().hp = 100;
+        game_state.objects.push(orc);
+        let orc_id = game_state.objects.len() - 1;
+        game_state.initialize_fov();
+        game_state.compute_fov();
+
+        // Walk up to it.
+        game_state.player_move_or_attack(1, 0);
+        game_state.player_move_or_attack(1, 0);
+        game_state.compute_fov();
+        assert_eq!(game_state.objects[PLAYER].pos(), (12, 10));
+        assert_eq!(game_state.objects[orc_id].pos(), (14, 10));
+
+        // Drink a healing potion after getting hurt.
+        game_state.objects[PLAYER].fighter.as_mut().unwrap().hp = 50;
+        let potion = give(&mut game_state, Item::Heal);
+        let carried = game_state.inventory.len();
+        let result = game_state.cast_heal(HEAL_AMOUNT);
+        game_state.finish_using(potion, result);
+        assert_eq!(game_state.objects[PLAYER].fighter.unwrap().hp, 50 + HEAL_AMOUNT);
+        assert_eq!(game_state.inventory.len(), carried - 1);
+        assert!(!game_state.inventory.iter().any(|item| item.item == Some(Item::Heal)));
+
+        // A healthy player can't drink another, and keeps it.
+        game_state.objects[PLAYER].fighter.as_mut().unwrap().hp = game_state.max_hp(PLAYER);
+        let potion = give(&mut game_state, Item::Heal);
+        let result = game_state.cast_heal(HEAL_AMOUNT);
+        game_state.finish_using(potion, result);
+        assert_eq!(game_state.inventory[potion].item, Some(Item::Heal));
+
+        // Strike the orc with lightning.
+        let scroll = give(&mut game_state, Item::Lightning);
+        let result = game_state.cast_lightning();
+        game_state.finish_using(scroll, result);
+        assert_eq!(game_state.objects[orc_id].fighter.unwrap().hp, 100 - LIGHTNING_DAMAGE);
+        assert!(!game_state.inventory.iter().any(|item| item.item == Some(Item::Lightning)));
+        assert_eq!(game_state.objects[PLAYER].pos(), (12, 10));
+    }
+
+    #[test]
+    fn last_target_follows_its_monster() {
+        let mut game_state = new_game();
+        game_state.objects.truncate(PLAYER + 1);
+        let mut rng = StdRng::from_seed(&[1]);
+        let (x, y) = game_state.objects[PLAYER].pos();
+        game_state.objects.push(map::make_gold(x, y, 1, &mut rng));
+        game_state.objects.push(map::make_monster("orc", x + 3, y, 1, &mut rng));
+        game_state.last_target = Some(2);
+
+        // Picking up the gold moves the orc into its place.
+        game_state.pick_up_gold();
+        assert_eq!(game_state.objects[1].name, "orc");
+        assert_eq!(game_state.last_target, Some(1));
+
+        game_state.change_level(Location::start().offset(1), Arrival::Landing);
+        assert_eq!(game_state.last_target, None);
+    }
+
+    #[test]
+    fn only_packmates_in_sight_are_seen_waking() {
+        // With no light, the player only sees the packmate next to them.
+        let mut game_state = new_game();
+        game_state.objects.truncate(PLAYER + 1);
+        for x in 5..30 {
+            for y in 5..15 {
+                game_state.map[(x, y)] = Tile::empty();
+            }
+        }
+        game_state.objects[PLAYER].set_pos(10, 10);
+        let mut rng = StdRng::from_seed(&[1]);
+        for &x in &[11, 25] {
+            let mut orc = map::make_monster("orc", x, 10, 1, &mut rng);
+            orc.pack = Some(0);
+            orc.fall_asleep();
+            game_state.objects.push(orc);
+        }
+        game_state.initialize_fov();
+        game_state.compute_fov();
+
+        let messages = game_state.messages.len();
+        game_state.alert_pack(1);
+        assert!(game_state.objects[1..].iter().all(|orc| !orc.is_asleep()));
+        let woke: Vec<&String> = game_state.messages[messages..].iter().map(|&(ref text, _, _)| text).collect();
+        assert_eq!(woke, vec!["The orc wakes up!"]);
+    }
+
+    #[test]
+    fn full_shop_refuses_new_wares() {
+        let mut game_state = new_game();
+        let mut shopkeeper = Object::new(0, 0, '@', "shopkeeper", colors::GOLD, true);
+        for i in 0..INVENTORY_SIZE {
+            shopkeeper.carried.push(Object::new(0, 0, '!', &format!("ware {}", i), colors::WHITE, false));
+        }
+        game_state.objects.push(shopkeeper);
+        let shopkeeper_id = game_state.objects.len() - 1;
+
+        let sword = give(&mut game_state, Item::Sword);
+        let gold = game_state.gold;
+        game_state.sell(shopkeeper_id, sword);
+        assert_eq!(game_state.inventory.len(), 1);
+        assert_eq!(game_state.gold, gold);
+        assert_eq!(game_state.objects[shopkeeper_id].carried.len(), INVENTORY_SIZE);
+    }
+
+    const CROWD_TURNS: u32 = 10;
+
+    /// A big open hall, with the player in the middle and a crowd of orcs
+    /// along the walls. Returns the game and the orcs' ids.
+    fn crowded_hall() -> (GameState, Vec<usize>) {
+        const MONSTERS: i32 = 40;
+        let mut game_state = GameState::new("tester", 1);
+        game_state.objects.truncate(PLAYER + 1);
+        for x in 1..(game_state.map.width - 1) {
+            for y in 1..(game_state.map.height - 1) {
+                game_state.map[(x, y)] = Tile::empty();
+            }
+        }
+        let (width, height) = (game_state.map.width, game_state.map.height);
+        game_state.objects[PLAYER].set_pos(width / 2, height / 2);
+        let mut rng = StdRng::from_seed(&[1]);
+        for i in 0..MONSTERS {
+            let (x, y) = if i % 2 == 0 { (2 + i, 2) } else { (1 + i, height - 3) };
+            game_state.objects.push(map::make_monster("orc", x, y, 1, &mut rng));
+        }
+        game_state.initialize_fov();
+        let monsters = (PLAYER + 1..game_state.objects.len()).collect();
+        (game_state, monsters)
+    }
+
+    /// How it was done before: a fresh map for every monster's search.
+    fn steps_with_a_map_per_monster(game_state: &mut GameState, monsters: &[usize]) -> Vec<Option<(i32, i32)>> {
+        let (player_x, player_y) = game_state.objects[PLAYER].pos();
+        let mut steps = vec![];
+        for _ in 0..CROWD_TURNS {
+            for &id in monsters {
+                game_state.path_map = FovMap::new(game_state.map.width, game_state.map.height);
+                game_state.update_path_map();
+                steps.push(game_state.path_step(id, player_x, player_y, 25));
+            }
+        }
+        steps
+    }
+
+    /// Now: one map per turn, shared by every monster.
+    fn steps_with_a_shared_map(game_state: &mut GameState, monsters: &[usize]) -> Vec<Option<(i32, i32)>> {
+        let (player_x, player_y) = game_state.objects[PLAYER].pos();
+        let mut steps = vec![];
+        for _ in 0..CROWD_TURNS {
+            game_state.update_path_map();
+            for &id in monsters {
+                steps.push(game_state.path_step(id, player_x, player_y, 25));
+            }
+        }
+        steps
+    }
+
+    #[test]
+    fn shared_path_map_finds_the_same_steps() {
+        let (mut game_state, monsters) = crowded_hall();
+        let rebuilt_steps = steps_with_a_map_per_monster(&mut game_state, &monsters);
+        let shared_steps = steps_with_a_shared_map(&mut game_state, &monsters);
+        assert_eq!(shared_steps, rebuilt_steps);
+    }
+
+    // Timing depends on the machine, so run it by hand with `--ignored`.
+    #[test]
+    #[ignore]
+    fn shared_path_map_beats_one_per_monster() {
+        let (mut game_state, monsters) = crowded_hall();
+        let start = Instant::now();
+        steps_with_a_map_per_monster(&mut game_state, &monsters);
+        let rebuilt = start.elapsed();
+
+        let start = Instant::now();
+        steps_with_a_shared_map(&mut game_state, &monsters);
+        let shared = start.elapsed();
+        assert!(shared < rebuilt);
+    }
+}
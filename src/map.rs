@@ -1,39 +1,358 @@
-use std::cmp;
+use std::cmp::{self, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::ops::{Index, IndexMut};
 
-use rand::{self, Rng, ThreadRng};
+use rand::{Rng, SeedableRng, StdRng};
 use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
-use tcod::colors;
+use tcod::colors::{self, Color};
 
+use affix::Affix;
+use arena;
+use artifact::Artifact;
+use boss::Boss;
+use branch::{Branch, Location};
+use bsp;
+use cave;
+use equipment::{Brand, Equipment, Slot};
+use feature::Feature;
+use maze;
+use monster::MonsterCatalog;
 use object::*;
+use rarity::Rarity;
+use ruins;
+use spawn::SpawnTables;
+use theme::{Pools, Theme, ThemeKind};
+use town;
+use vault::{self, Marker};
 
-pub const MAP_WIDTH: i32 = 100;
-pub const MAP_HEIGHT: i32 = 100;
 pub const ROOM_MAX_SIZE: i32 = 10;
 pub const ROOM_MIN_SIZE: i32 = 6;
 pub const MAX_ROOMS: i32 = 30;
 
-struct Transition {
-    level: u32,
-    value: u32,
+// Monsters spawned together as a pack.
+const PACK_MIN_SIZE: i32 = 3;
+const PACK_MAX_SIZE: i32 = 6;
+const PACK_RADIUS: i32 = 2;
+// Chance, in percent, that a monster, or a whole pack, is found asleep.
+const SLEEP_CHANCE: u32 = 70;
+// Chance, in percent, that a basic monster found awake is making its rounds,
+// and how many rooms they take it through.
+const PATROL_CHANCE: u32 = 40;
+const PATROL_MIN_WAYPOINTS: usize = 2;
+const PATROL_MAX_WAYPOINTS: usize = 4;
+// Chance, in percent, that a monster carries some gold to drop when it dies.
+const MONSTER_GOLD_CHANCE: u32 = 40;
+// Shops show up from this level, on some floors.
+const SHOP_MIN_LEVEL: u32 = 2;
+const SHOP_CHANCE: u32 = 30;
+const SHOP_STOCK_SIZE: i32 = 6;
+// Chance, in percent, that a piece of equipment is cursed.
+const CURSE_CHANCE: u32 = 15;
+const WAND_MIN_CHARGES: u32 = 3;
+const WAND_MAX_CHARGES: u32 = 6;
+// Chests, in percent per room, and how much they hold.
+const CHEST_CHANCE: u32 = 10;
+const CHEST_LOCKED_CHANCE: u32 = 40;
+const CHEST_MAX_ITEMS: i32 = 3;
+// Chance, in percent, of a room with locked doors, from the level they show up on.
+const LOCKED_ROOM_MIN_LEVEL: u32 = 2;
+const LOCKED_ROOM_CHANCE: u32 = 25;
+const LOCKED_ROOM_MAX_DOORS: usize = 3;
+// Chance, in percent, that a level holds one of the artifacts not found yet.
+const ARTIFACT_CHANCE: u32 = 10;
+
+// How much a step further from a threat counts for in a flee map, in tenths
+// of a step.
+const FLEE_MAP_FACTOR: i32 = 12;
+
+// Size of the areas monsters and items are spread over, on levels with no rooms.
+const AREA_SIZE: i32 = 10;
+
+// Items added to each area of a labyrinth, on top of the usual ones.
+const LABYRINTH_EXTRA_ITEMS: u32 = 1;
+// How close to the stairs a labyrinth's guardian waits.
+const GUARDIAN_RADIUS: i32 = 3;
+
+// What a boss may drop when killed, always at least rare.
+const BOSS_DROPS: [Item; 7] = [Item::Sword, Item::Greatsword, Item::Shield, Item::Armor, Item::Boots, Item::Ring,
+                               Item::Amulet];
+
+// Chance for each step of a winding tunnel to wander off in any direction,
+// and how much longer than the way straight there it may get before heading
+// straight there.
+const WINDING_WANDER_CHANCE: u32 = 35;
+const WINDING_MAX_STEPS_FACTOR: i32 = 4;
+// Width of a winding tunnel.
+const WINDING_WIDTH: i32 = 2;
+
+const POOL_MAX_RADIUS: i32 = 3;
+// Chance for each room to have a chasm down to the level below.
+const CHASM_CHANCE: u32 = 10;
+
+// Chance for each edge tile of an eroded room to be left as rock.
+const EROSION_CHANCE: u32 = 35;
+
+// Chance for each room to have rubble, bones and the like lying around, and
+// how many at most.
+const FEATURE_CHANCE: u32 = 40;
+const FEATURE_MAX_COUNT: i32 = 4;
+// Chance, in percent, that what's buried in rubble is gold rather than an item.
+const BURIED_GOLD_CHANCE: u32 = 50;
+
+// Chance for each modifier to apply to a level, from the level they show up on.
+const MODIFIER_MIN_LEVEL: u32 = 2;
+const MODIFIER_CHANCE: u32 = 10;
+// Monsters a room of a crowded level may have, on top of the usual.
+const CROWDED_EXTRA_MONSTERS: u32 = 2;
+
+// Items in the last room of a branch's bottom level.
+const HOARD_ITEMS: u32 = 6;
+
+// Chance for each monster or item to be picked from the table of a level this
+// much deeper down, and made as tough or fine as it would be there.
+const OUT_OF_DEPTH_CHANCE: u32 = 3;
+const OUT_OF_DEPTH_LEVELS: u32 = 3;
+const BRANCH_STAIRS_TRIES: u32 = 50;
+
+// Most stairs down a level can have. How many is picked apart from the rest
+// of the level, so that the level above and below agree on it.
+const MAX_STAIRS: usize = 3;
+const STAIRS_COUNT_SEED: usize = 0x57A1;
+
+const TOWN_WIDTH: i32 = 60;
+const TOWN_HEIGHT: i32 = 40;
+
+const VAULT_CHANCE: u32 = 25;
+// Spots tried for a vault before giving up on it.
+const VAULT_TRIES: u32 = 50;
+
+/// From which level on a value applies, in tables of values by depth.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
 }
 
 /// Returns a value that depends on level. the table specifies what
 /// value occurs after each level, default is 0.
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
     table.iter()
         .rev()
         .find(|transition| level >= transition.level)
         .map_or(0, |transition| transition.value)
 }
 
-// TODO: Make this a 1D Vec with coordinate accessors.
-pub type Map = Vec<Vec<Tile>>;
+/// The tiles of a dungeon level, stored row by row. Indexing it with a
+/// position outside of it panics; `get` and `get_mut` don't.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Map {
+    pub width: i32,
+    pub height: i32,
+    tiles: Vec<Tile>,
+    /// What the player senses on arriving here, if there's something unusual.
+    pub feeling: Option<Feeling>,
+    /// What the level is called, like "The Sunken Gallery".
+    pub name: String,
+    /// What the player notices whenever they arrive here.
+    pub ambience: String,
+    /// What sets the level apart from others of its depth.
+    pub modifiers: Vec<Modifier>,
+    /// How the level looks.
+    pub theme: Theme,
+}
+
+impl Map {
+    /// A map of the given size and theme, filled with copies of a tile.
+    pub fn new(width: i32, height: i32, tile: Tile, theme: Theme) -> Self {
+        Map {
+            width,
+            height,
+            tiles: vec![tile; (width * height) as usize],
+            feeling: None,
+            name: String::new(),
+            ambience: String::new(),
+            modifiers: vec![],
+            theme,
+        }
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    /// Where the tile at a position is stored.
+    pub fn idx(&self, x: i32, y: i32) -> usize {
+        debug_assert!(self.in_bounds(x, y), "Position ({}, {}) is outside the map.", x, y);
+        (y * self.width + x) as usize
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<&Tile> {
+        if self.in_bounds(x, y) {
+            Some(&self.tiles[self.idx(x, y)])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut Tile> {
+        if self.in_bounds(x, y) {
+            let idx = self.idx(x, y);
+            Some(&mut self.tiles[idx])
+        } else {
+            None
+        }
+    }
+}
+
+/// A level the player has left, kept as it was for when they come back.
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub location: Location,
+    pub map: Map,
+    /// Everything on it, but the player.
+    pub objects: Vec<Object>,
+}
+
+impl Index<(i32, i32)> for Map {
+    type Output = Tile;
+
+    fn index(&self, (x, y): (i32, i32)) -> &Tile {
+        &self.tiles[self.idx(x, y)]
+    }
+}
+
+impl IndexMut<(i32, i32)> for Map {
+    fn index_mut(&mut self, (x, y): (i32, i32)) -> &mut Tile {
+        let idx = self.idx(x, y);
+        &mut self.tiles[idx]
+    }
+}
+
+/// A hint of what's on a level, for the player to get on arriving there.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Feeling {
+    /// Monsters from deeper down.
+    Danger,
+    /// Items from deeper down, or an artifact.
+    Treasure,
+}
+
+impl Feeling {
+    pub fn message(self) -> &'static str {
+        match self {
+            Feeling::Danger => "You sense a powerful presence...",
+            Feeling::Treasure => "You sense something precious nearby...",
+        }
+    }
+}
+
+/// Something that sets a level apart from others of its depth.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Modifier {
+    /// More monsters than usual in every room.
+    Crowded,
+    /// The player's light doesn't reach as far.
+    Dim,
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 2] = [Modifier::Crowded, Modifier::Dim];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Modifier::Crowded => "crowded",
+            Modifier::Dim => "dim",
+        }
+    }
+
+    /// What the player is told on first arriving on a level with it.
+    pub fn message(self) -> &'static str {
+        match self {
+            Modifier::Crowded => "You hear many creatures stirring.",
+            Modifier::Dim => "Your light seems to falter here.",
+        }
+    }
+}
+
+/// How many monsters and items from deeper down went on a level.
+#[derive(Default)]
+struct OutOfDepth {
+    monsters: u32,
+    items: u32,
+}
+
+/// What the floor of a tile is made of.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Terrain {
+    Ground,
+    /// Slows down whoever wades through it.
+    ShallowWater,
+    /// Only the player swims in it, and things may slip out of their pack.
+    DeepWater,
+    /// Burns whoever stands in it.
+    Lava,
+    /// A drop to the level below. Whoever steps in falls through, unless they fly.
+    Chasm,
+}
+
+impl Terrain {
+    /// Whether monsters keep out of it, and nothing gets placed on it.
+    pub fn is_hazard(self) -> bool {
+        self == Terrain::DeepWater || self == Terrain::Lava || self == Terrain::Chasm
+    }
+
+    /// Background color, in and out of sight. Plain ground takes the theme's.
+    pub fn color(self, visible: bool) -> Option<Color> {
+        match (self, visible) {
+            (Terrain::Ground, _) => None,
+            (Terrain::ShallowWater, false) => Some(Color { r: 20, g: 50, b: 90 }),
+            (Terrain::ShallowWater, true) => Some(Color { r: 60, g: 120, b: 190 }),
+            (Terrain::DeepWater, false) => Some(Color { r: 10, g: 20, b: 60 }),
+            (Terrain::DeepWater, true) => Some(Color { r: 20, g: 50, b: 140 }),
+            (Terrain::Lava, false) => Some(Color { r: 90, g: 20, b: 0 }),
+            (Terrain::Lava, true) => Some(Color { r: 230, g: 80, b: 10 }),
+            (Terrain::Chasm, false) => Some(Color { r: 5, g: 5, b: 10 }),
+            (Terrain::Chasm, true) => Some(Color { r: 20, g: 15, b: 30 }),
+        }
+    }
+
+    pub fn glyph(self) -> char {
+        match self {
+            Terrain::Ground => ' ',
+            Terrain::ShallowWater | Terrain::DeepWater | Terrain::Lava => '~',
+            Terrain::Chasm => ':',
+        }
+    }
+
+    /// What the player is told when stepping into it.
+    pub fn entering_message(self) -> Option<&'static str> {
+        match self {
+            Terrain::Ground => None,
+            Terrain::ShallowWater => Some("You wade into the water."),
+            Terrain::DeepWater => Some("You swim into deep water. Your pack feels loose."),
+            Terrain::Lava => Some("You step into the lava!"),
+            Terrain::Chasm => Some("You leap into the chasm!"),
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Tile {
     pub blocked: bool,
     pub block_sight: bool,
     pub explored: bool,
+    /// For a locked door, the level whose key opens it.
+    pub lock: Option<u32>,
+    pub terrain: Terrain,
+    /// Whether a wall can be dug through. The map's border and the walls of
+    /// vaults can't.
+    pub diggable: bool,
+    /// Whether it's shrouded in magical darkness, that swallows the light.
+    pub dark: bool,
+    /// Rubble, bones or the like lying on it.
+    pub feature: Option<Feature>,
+    /// The last turn the player was on it. Their scent fades from then on.
+    pub scent: Option<u32>,
 }
 
 impl Tile {
@@ -42,6 +361,12 @@ impl Tile {
             blocked: false,
             block_sight: false,
             explored: false,
+            lock: None,
+            terrain: Terrain::Ground,
+            diggable: true,
+            dark: false,
+            feature: None,
+            scent: None,
         }
     }
 
@@ -50,13 +375,57 @@ impl Tile {
             blocked: true,
             block_sight: true,
             explored: false,
+            lock: None,
+            terrain: Terrain::Ground,
+            diggable: true,
+            dark: false,
+            feature: None,
+            scent: None,
+        }
+    }
+
+    /// A wall that can't be dug through.
+    pub fn permanent_wall() -> Self {
+        Tile {
+            diggable: false,
+            ..Tile::wall()
+        }
+    }
+
+    pub fn locked_door(lock: u32) -> Self {
+        Tile {
+            blocked: true,
+            block_sight: true,
+            explored: false,
+            lock: Some(lock),
+            terrain: Terrain::Ground,
+            diggable: false,
+            dark: false,
+            feature: None,
+            scent: None,
         }
     }
+
+    pub fn is_diggable(&self) -> bool {
+        self.blocked && self.diggable
+    }
+
+    /// Whether monsters can walk on it.
+    pub fn is_walkable(&self) -> bool {
+        !self.blocked && !self.terrain.is_hazard()
+    }
 }
 
+/// Whether a monster can't step on a tile, or nothing can be put there:
+/// there's a wall, a blocking object, deep water or lava.
 pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+    map[(x, y)].terrain.is_hazard() || is_obstructed(x, y, map, objects)
+}
+
+/// Whether a wall or a blocking object is in the way, whatever the terrain.
+pub fn is_obstructed(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
     // First test the map tile.
-    if map[x as usize][y as usize].blocked {
+    if map[(x, y)].blocked {
         return true;
     }
 
@@ -66,16 +435,158 @@ pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
     })
 }
 
+/// Flood fill from the given tile over walkable ones, ignoring objects.
+/// Returns, for every tile, whether it can be walked to from the start.
+pub fn reachable_tiles(map: &Map, start_x: i32, start_y: i32) -> Vec<Vec<bool>> {
+    flood_fill(map, (start_x, start_y), &Tile::is_walkable)
+}
+
+/// Flood fill from the given tile over the ones `passable` lets through.
+fn flood_fill(map: &Map, (start_x, start_y): (i32, i32), passable: &Fn(&Tile) -> bool) -> Vec<Vec<bool>> {
+    let mut reachable = vec![vec![false; map.height as usize]; map.width as usize];
+    let mut to_visit = vec![(start_x, start_y)];
+    while let Some((x, y)) = to_visit.pop() {
+        if !map.in_bounds(x, y) {
+            continue;
+        }
+        let (column, row) = (x as usize, y as usize);
+        if reachable[column][row] || !passable(&map[(x, y)]) {
+            continue;
+        }
+        reachable[column][row] = true;
+        to_visit.extend_from_slice(&[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+    }
+    reachable
+}
+
+/// Steps to a goal from every tile monsters can walk to it from, or None for
+/// the ones they can't.
+fn distance_map(map: &Map, (goal_x, goal_y): (i32, i32)) -> Vec<Vec<Option<i32>>> {
+    let mut distances = vec![vec![None; map.height as usize]; map.width as usize];
+    distances[goal_x as usize][goal_y as usize] = Some(0);
+    let mut to_visit = VecDeque::new();
+    to_visit.push_back((goal_x, goal_y, 0));
+    while let Some((x, y, distance)) = to_visit.pop_front() {
+        for &(next_x, next_y) in &[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            let walkable = map.get(next_x, next_y).map_or(false, Tile::is_walkable);
+            if walkable && distances[next_x as usize][next_y as usize].is_none() {
+                distances[next_x as usize][next_y as usize] = Some(distance + 1);
+                to_visit.push_back((next_x, next_y, distance + 1));
+            }
+        }
+    }
+    distances
+}
+
+/// Which tiles a noise made on a tile is heard on, up to some steps away. It
+/// goes around walls, but not through them.
+pub fn earshot(map: &Map, (x, y): (i32, i32), radius: i32) -> Vec<Vec<bool>> {
+    let mut heard = vec![vec![false; map.height as usize]; map.width as usize];
+    heard[x as usize][y as usize] = true;
+    let mut to_visit = VecDeque::new();
+    to_visit.push_back((x, y, 0));
+    while let Some((x, y, distance)) = to_visit.pop_front() {
+        if distance == radius {
+            continue;
+        }
+        for &(next_x, next_y) in &[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            let open = map.get(next_x, next_y).map_or(false, |tile| !tile.blocked);
+            if open && !heard[next_x as usize][next_y as usize] {
+                heard[next_x as usize][next_y as usize] = true;
+                to_visit.push_back((next_x, next_y, distance + 1));
+            }
+        }
+    }
+    heard
+}
+
+/// A map for running away from a threat, walking to whichever neighbor has
+/// the lowest value. It's the distance map to the threat turned upside down,
+/// then smoothed over so that dead ends lose their appeal: a monster backed
+/// into one would rather slip out past the threat towards open space.
+pub fn flee_map(map: &Map, threat: (i32, i32)) -> Vec<Vec<Option<i32>>> {
+    // In tenths of a step.
+    let mut values: Vec<Vec<Option<i32>>> = distance_map(map, threat).into_iter()
+        .map(|column| column.into_iter().map(|distance| distance.map(|d| -d * FLEE_MAP_FACTOR)).collect())
+        .collect();
+    let mut to_visit = BinaryHeap::new();
+    for (x, column) in values.iter().enumerate() {
+        for (y, value) in column.iter().enumerate() {
+            if let Some(value) = *value {
+                to_visit.push(Reverse((value, x as i32, y as i32)));
+            }
+        }
+    }
+    while let Some(Reverse((value, x, y))) = to_visit.pop() {
+        if values[x as usize][y as usize] != Some(value) {
+            // Already got somewhere lower since.
+            continue;
+        }
+        for &(next_x, next_y) in &[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            if !map.in_bounds(next_x, next_y) {
+                continue;
+            }
+            if let Some(ref mut next_value) = values[next_x as usize][next_y as usize] {
+                if value + 10 < *next_value {
+                    *next_value = value + 10;
+                    to_visit.push(Reverse((value + 10, next_x, next_y)));
+                }
+            }
+        }
+    }
+    values
+}
+
+/// Whether all the given tiles can be walked to from the start.
+pub fn is_connected(map: &Map, start: (i32, i32), targets: &[(i32, i32)]) -> bool {
+    let reachable = reachable_tiles(map, start.0, start.1);
+    targets.iter().all(|&(x, y)| reachable[x as usize][y as usize])
+}
+
+/// The tiles on a straight line between two points, using Bresenham's
+/// algorithm. The start is left out, and the end is included.
+pub fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = (to.0 - x).signum();
+    let step_y = (to.1 - y).signum();
+    let mut error = dx + dy;
+
+    let mut tiles = vec![];
+    while (x, y) != to {
+        let double_error = 2 * error;
+        if double_error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if double_error <= dx {
+            error += dx;
+            y += step_y;
+        }
+        tiles.push((x, y));
+    }
+    tiles
+}
+
+/// The tiles a projectile flies over towards a target, stopping short of
+/// the first wall in the way.
+pub fn projectile_path(map: &Map, from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    line(from, to).into_iter()
+        .take_while(|&(x, y)| !map[(x, y)].blocked)
+        .collect()
+}
+
 #[derive(Clone, Copy, Debug)]
-struct Rect {
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
 }
 
 impl Rect {
-    fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
         Rect {
             x1: x,
             y1: y,
@@ -96,34 +607,283 @@ impl Rect {
     }
 }
 
-fn create_room(room: Rect, map: &mut Map) {
+pub fn create_room(room: Rect, map: &mut Map) {
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[(x, y)] = Tile::empty();
+        }
+    }
+}
+
+/// The shapes a room's floor can take, inside its rectangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoomShape {
+    Rectangle,
+    Ellipse,
+    /// A rectangle missing one corner quarter.
+    LShape,
+    /// A rectangle with a grid of pillars, clear along the middle.
+    Pillars,
+    /// A rectangle with cut corners and ragged edges.
+    Eroded,
+}
+
+impl RoomShape {
+    pub fn random<R: Rng>(rng: &mut R) -> Self {
+        let shape_chances = &mut [
+            Weighted {weight: 50, item: RoomShape::Rectangle},
+            Weighted {weight: 15, item: RoomShape::Ellipse},
+            Weighted {weight: 15, item: RoomShape::LShape},
+            Weighted {weight: 10, item: RoomShape::Pillars},
+            Weighted {weight: 10, item: RoomShape::Eroded},
+        ];
+        WeightedChoice::new(shape_chances).ind_sample(rng)
+    }
+}
+
+/// Carve a room of the given shape. Like `create_room`, it only digs out
+/// floor, so tunnels already dug through its rectangle stay open. The middle
+/// row and column, where tunnels come in, are always floor.
+pub fn create_shaped_room(room: Rect, shape: RoomShape, map: &mut Map, rng: &mut StdRng) {
+    let (center_x, center_y) = room.center();
+    // The middle of the floor, which may fall between two tiles.
+    let (middle_x, middle_y) = ((room.x1 + room.x2) as f32 / 2.0, (room.y1 + room.y2) as f32 / 2.0);
+    let (radius_x, radius_y) = ((room.x2 - room.x1 - 1) as f32 / 2.0, (room.y2 - room.y1 - 1) as f32 / 2.0);
+    // The quarter an L-shaped room is missing.
+    let (corner_x, corner_y) = (if rng.gen() { 1 } else { -1 }, if rng.gen() { 1 } else { -1 });
+
     for x in (room.x1 + 1)..room.x2 {
         for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+            let (dx, dy) = (x - center_x, y - center_y);
+            let on_edge_x = x == room.x1 + 1 || x == room.x2 - 1;
+            let on_edge_y = y == room.y1 + 1 || y == room.y2 - 1;
+            let floor = dx == 0 || dy == 0 || match shape {
+                RoomShape::Rectangle => true,
+                RoomShape::Ellipse => {
+                    ((x as f32 - middle_x) / radius_x).powi(2) + ((y as f32 - middle_y) / radius_y).powi(2) <= 1.0
+                }
+                RoomShape::LShape => dx * corner_x < 0 || dy * corner_y < 0,
+                RoomShape::Pillars => on_edge_x || on_edge_y || (x - room.x1) % 2 == 1 || (y - room.y1) % 2 == 1,
+                // Every edge tile left is next to one further in, so the floor
+                // stays in one piece.
+                RoomShape::Eroded => {
+                    !(on_edge_x && on_edge_y) && !((on_edge_x || on_edge_y) && rng.gen_range(0, 100) < EROSION_CHANCE)
+                }
+            };
+            if floor {
+                map[(x, y)] = Tile::empty();
+            }
         }
     }
 }
 
 fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
     for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+        map[(x, y)] = Tile::empty();
     }
 }
 
 fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
     for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+        map[(x, y)] = Tile::empty();
     }
 }
 
-pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
-    // Player is the first element, remove everything else.
-    objects.truncate(1);
+/// How the tunnels between rooms are dug.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corridor {
+    /// Two straight lines, at a right angle.
+    Straight,
+    /// A jagged line, going both ways at once.
+    Diagonal,
+    /// A wide passage, wandering its way there like a natural cave.
+    Winding,
+}
+
+/// Connect two rooms with a tunnel between their centers.
+pub fn join_rooms(from: Rect, to: Rect, corridor: Corridor, map: &mut Map, rng: &mut StdRng) {
+    dig_tunnel(from.center(), to.center(), corridor, map, rng);
+}
+
+/// Dig a tunnel between two tiles.
+pub fn dig_tunnel(from: (i32, i32), to: (i32, i32), corridor: Corridor, map: &mut Map, rng: &mut StdRng) {
+    match corridor {
+        Corridor::Straight => dig_straight_tunnel(from, to, map, rng),
+        Corridor::Diagonal => dig_diagonal_tunnel(from, to, map, rng),
+        Corridor::Winding => dig_winding_tunnel(from, to, map, rng),
+    }
+}
+
+/// Dig an L-shaped tunnel between two tiles.
+fn dig_straight_tunnel(from: (i32, i32), to: (i32, i32), map: &mut Map, rng: &mut StdRng) {
+    let (prev_x, prev_y) = from;
+    let (new_x, new_y) = to;
+
+    // Flip a coin (random bool value -- either true or false).
+    if rng.gen() {
+        // First move horizontally, then vertically.
+        create_h_tunnel(prev_x, new_x, prev_y, map);
+        create_v_tunnel(prev_y, new_y, new_x, map);
+    } else {
+        // First move vertically, then horizontally.
+        create_v_tunnel(prev_y, new_y, prev_x, map);
+        create_h_tunnel(prev_x, new_x, new_y, map);
+    }
+}
+
+/// Dig a tunnel that zigzags between two tiles, taking random steps along
+/// either axis as much as there's left to go along it. Steps are never
+/// diagonal, so it can be walked through.
+fn dig_diagonal_tunnel(from: (i32, i32), to: (i32, i32), map: &mut Map, rng: &mut StdRng) {
+    let (mut x, mut y) = from;
+    map[(x, y)] = Tile::empty();
+    while (x, y) != to {
+        let (left_x, left_y) = ((to.0 - x).abs(), (to.1 - y).abs());
+        if rng.gen_range(0, left_x + left_y) < left_x {
+            x += (to.0 - x).signum();
+        } else {
+            y += (to.1 - y).signum();
+        }
+        map[(x, y)] = Tile::empty();
+    }
+}
+
+/// Dig a wide tunnel that wanders on its way between two tiles. If it takes
+/// too long getting there, it finishes with a zigzag.
+fn dig_winding_tunnel(from: (i32, i32), to: (i32, i32), map: &mut Map, rng: &mut StdRng) {
+    let (mut x, mut y) = from;
+    let max_steps = distance(from, to) * WINDING_MAX_STEPS_FACTOR;
+    let mut steps = 0;
+    while (x, y) != to && steps < max_steps {
+        let (dx, dy) = if rng.gen_range(0, 100) < WINDING_WANDER_CHANCE {
+            *rng.choose(&[(1, 0), (-1, 0), (0, 1), (0, -1)]).unwrap()
+        } else if rng.gen_range(0, distance((x, y), to)) < (to.0 - x).abs() {
+            ((to.0 - x).signum(), 0)
+        } else {
+            (0, (to.1 - y).signum())
+        };
+        // Keep the outer edge of the map solid.
+        if x + dx > 0 && y + dy > 0 && x + dx + WINDING_WIDTH < map.width && y + dy + WINDING_WIDTH < map.height {
+            x += dx;
+            y += dy;
+        }
+        for tunnel_x in x..(x + WINDING_WIDTH) {
+            for tunnel_y in y..(y + WINDING_WIDTH) {
+                if let Some(tile) = map.get_mut(tunnel_x, tunnel_y) {
+                    *tile = Tile::empty();
+                }
+            }
+        }
+        steps += 1;
+    }
+    dig_diagonal_tunnel((x, y), to, map, rng);
+}
+
+/// Steps between two tiles, moving straight.
+pub fn distance(from: (i32, i32), to: (i32, i32)) -> i32 {
+    (from.0 - to.0).abs() + (from.1 - to.1).abs()
+}
+
+/// For levels with no rooms: cut the map into squares, and make an area
+/// around a random open tile in each one that has any.
+pub fn spread_areas(map: &Map, rng: &mut StdRng) -> Vec<Rect> {
+    let mut areas = vec![];
+    for square_x in 0..(map.width / AREA_SIZE) {
+        for square_y in 0..(map.height / AREA_SIZE) {
+            let mut open = vec![];
+            for x in (square_x * AREA_SIZE)..((square_x + 1) * AREA_SIZE) {
+                for y in (square_y * AREA_SIZE)..((square_y + 1) * AREA_SIZE) {
+                    if !map[(x, y)].blocked {
+                        open.push((x, y));
+                    }
+                }
+            }
+            if let Some(&(x, y)) = rng.choose(&open) {
+                // Keep it centered on the tile, and inside the map.
+                let half = *[AREA_SIZE / 2, x, y, map.width - 1 - x, map.height - 1 - y].iter().min().unwrap();
+                areas.push(Rect { x1: x - half, y1: y - half, x2: x + half, y2: y + half });
+            }
+        }
+    }
+    areas
+}
+
+/// Move an area the player can start in first, and the one farthest from it
+/// last, for the stairs. Only areas centered on a tile `allowed` accepts are
+/// picked.
+pub fn pick_start_and_stairs(areas: &mut Vec<Rect>, allowed: &Fn((i32, i32)) -> bool) {
+    let start = match areas.iter().position(|area| allowed(area.center())) {
+        Some(start) => start,
+        None => return,
+    };
+    areas.swap(0, start);
+    let start = areas[0].center();
+    let stairs = (1..areas.len())
+        .filter(|&i| allowed(areas[i].center()))
+        .max_by_key(|&i| distance(start, areas[i].center()));
+    if let Some(stairs) = stairs {
+        let last = areas.len() - 1;
+        areas.swap(stairs, last);
+    }
+}
+
+/// The ways a level's rooms can be laid out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Generator {
+    /// Rooms scattered at random, each joined to the one before it.
+    Rooms,
+    /// Binary space partition, for tighter layouts.
+    Bsp,
+    /// Winding natural caverns, with no rooms at all.
+    Caves,
+    /// Chaotic ruins, dug out by a drunkard's walk.
+    Ruins,
+    /// A maze of narrow corridors, with more loot and a guardian by the stairs.
+    Labyrinth,
+    /// An arena between the start and the stairs, for a boss to fight in.
+    Arena,
+}
 
-    let mut rng = rand::thread_rng();
+impl Generator {
+    /// Pick how to lay out the given dungeon level. The first level always
+    /// has scattered rooms.
+    fn for_level(level: u32, theme: &Theme, rng: &mut StdRng) -> Self {
+        let generator_chances = &mut [
+            Weighted {weight: 60, item: Generator::Rooms},
+            Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 40}], level),
+                      item: Generator::Bsp},
+            Weighted {weight: from_dungeon_level(&[Transition{level: 3, value: 30}], level),
+                      item: Generator::Caves},
+            Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 15}], level),
+                      item: Generator::Ruins},
+            Weighted {weight: from_dungeon_level(&[Transition{level: 3, value: 8}], level),
+                      item: Generator::Labyrinth},
+        ];
+        for chance in generator_chances.iter_mut() {
+            chance.weight = chance.weight * chance.item.theme_weight(theme.kind) / 100;
+        }
+        WeightedChoice::new(generator_chances).ind_sample(rng)
+    }
+
+    /// How much more or less common the layout is with a theme, in percent.
+    fn theme_weight(self, theme: ThemeKind) -> u32 {
+        match (theme, self) {
+            (ThemeKind::Overgrown, Generator::Caves) | (ThemeKind::Flooded, Generator::Caves) => 300,
+            (ThemeKind::Flooded, Generator::Ruins) => 200,
+            (ThemeKind::Crypt, Generator::Bsp) | (ThemeKind::Crypt, Generator::Labyrinth) => 200,
+            (ThemeKind::Crypt, Generator::Caves) => 0,
+            (ThemeKind::Volcanic, Generator::Caves) | (ThemeKind::Volcanic, Generator::Ruins) => 200,
+            (ThemeKind::Mines, Generator::Caves) => 400,
+            (ThemeKind::Mines, Generator::Ruins) => 150,
+            (ThemeKind::Mines, Generator::Labyrinth) => 0,
+            _ => 100,
+        }
+    }
+}
 
-    // Fill the map with "unblocked" tiles.
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+/// Scatter rooms at random, skipping any that overlap an earlier one, and
+/// connect each to the one before it.
+fn scatter_rooms(map: &mut Map, corridor: Corridor, rng: &mut StdRng) -> Vec<Rect> {
     let mut rooms = vec![];
 
     for _ in 0..MAX_ROOMS {
@@ -131,8 +891,8 @@ pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
         let width = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
         let height = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
         // Random position without going out of the boundaries of the map.
-        let x = rng.gen_range(0, MAP_WIDTH - width);
-        let y = rng.gen_range(0, MAP_HEIGHT - height);
+        let x = rng.gen_range(0, map.width - width);
+        let y = rng.gen_range(0, map.height - height);
 
         let new_room = Rect::new(x, y, width, height);
 
@@ -144,181 +904,1781 @@ pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
             // No intersections, so this room is valid.
 
             // "Paint" it to the map's tiles.
-            create_room(new_room, &mut map);
-
-            // Center coordinates of the new room, will be useful later.
-            let (new_x, new_y) = new_room.center();
-
-            if rooms.is_empty() {
-                // This is the first room, where the player starts at.
-                objects[0].set_pos(new_x, new_y);
-            } else {
-                // All rooms after the first:
-                // Connect it to the previous room with a tunnel.
-
-                // Center coordinates of the previous room.
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-
-                // Flip a coin (random bool value -- either true or false).
-                if rng.gen() {
-                    // First move horizontally, then vertically.
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    // First move vertically, then horizontally.
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                }
+            let shape = RoomShape::random(rng);
+            create_shaped_room(new_room, shape, map, rng);
 
-                // Add some content to this room, such as monsters.
-                // NOTE: No objects are placed in the player's starting room.
-                place_objects(new_room, &map, objects, level, &mut rng);
+            // Connect it to the previous room with a tunnel.
+            if let Some(&prev_room) = rooms.last() {
+                join_rooms(prev_room, new_room, corridor, map, rng);
             }
 
             // Finally, append the new room to the list.
             rooms.push(new_room);
         }
     }
+    rooms
+}
 
-    // Create stairs at the center of the last room.
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
-    let mut stairs = Object::new(last_room_x, last_room_y, '>', "stairs", colors::WHITE, false);
-    stairs.always_visible = true;
-    objects.push(stairs);
+/// Generate a level, themed for its location. Any artifact placed on it is
+/// added to `artifacts`, so that it never shows up again. The same run seed
+/// always makes the same level at the same location.
+pub fn make_map(objects: &mut Vec<Object>, location: Location, artifacts: &mut Vec<Artifact>, seed: u64) -> Map {
+    generate_map(objects, location, artifacts, seed).0
+}
 
-    map
+/// Generate a level, like `make_map`, along with its rooms. The player starts
+/// in the first one. The town has none.
+fn generate_map(objects: &mut Vec<Object>, location: Location, artifacts: &mut Vec<Artifact>, seed: u64)
+                -> (Map, Vec<Rect>) {
+    // Start over until a level comes out with everything on it reachable.
+    let theme = Theme::for_location(location);
+    let artifacts_found = artifacts.len();
+    let mut attempt = 0;
+    loop {
+        if let Some(level) = try_generate_map(objects, location, &theme, artifacts, seed, attempt) {
+            return level;
+        }
+        artifacts.truncate(artifacts_found);
+        attempt += 1;
+    }
 }
 
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32, rng: &mut ThreadRng) {
-    let max_monsters = from_dungeon_level(&[
-        Transition {level: 1, value: 2},
-        Transition {level: 4, value: 3},
-        Transition {level: 6, value: 5},
-    ], level);
+/// Make one attempt at generating a level. Returns None if something on it
+/// couldn't be connected to the start without breaking walls that must stay.
+fn try_generate_map(objects: &mut Vec<Object>, location: Location, theme: &Theme, artifacts: &mut Vec<Artifact>,
+                    seed: u64, attempt: usize) -> Option<(Map, Vec<Rect>)> {
+    // Player is the first element, remove everything else.
+    objects.truncate(1);
 
-    // Choose random number of monsters.
-    let num_monsters = rng.gen_range(0, max_monsters + 1);
+    let mut rng = level_rng(seed, location, attempt);
+    let level = location.level();
+    if location.is_town() {
+        return Some((make_town(objects, theme, &mut rng), vec![]));
+    }
 
-    // Monster random table.
-    let troll_chance = from_dungeon_level(&[
-        Transition {level: 3, value: 15},
-        Transition {level: 5, value: 30},
-        Transition {level: 7, value: 60},
-    ], level);
+    // Fill the map with "blocked" tiles, then carve out the rooms.
+    let (width, height) = map_size(level);
+    let mut map = Map::new(width, height, Tile::wall(), theme.clone());
+    let boss = location.boss();
+    let generator = if boss.is_some() {
+        Generator::Arena
+    } else {
+        Generator::for_level(level, theme, &mut rng)
+    };
+    let mut rooms = match generator {
+        Generator::Rooms => scatter_rooms(&mut map, theme.corridor(), &mut rng),
+        Generator::Bsp => bsp::make_rooms(&mut map, theme.corridor(), &mut rng),
+        Generator::Caves => cave::make_caves(&mut map, theme.corridor(), &mut rng),
+        Generator::Ruins => ruins::make_ruins(&mut map, &mut rng),
+        Generator::Labyrinth => maze::make_maze(&mut map, &mut rng),
+        Generator::Arena => arena::make_arena(&mut map, &mut rng),
+    };
 
-    let monster_chances = &mut [
-        Weighted {weight: 80, item: "orc"},
-        Weighted {weight: troll_chance, item: "troll"},
-    ];
+    // In the unlikely case no room fit, force one in the middle of the map.
+    if rooms.is_empty() {
+        let room = Rect::new((map.width - ROOM_MAX_SIZE) / 2, (map.height - ROOM_MAX_SIZE) / 2,
+                             ROOM_MAX_SIZE, ROOM_MAX_SIZE);
+        create_room(room, &mut map);
+        rooms.push(room);
+    }
 
-    let monster_choice = WeightedChoice::new(monster_chances);
+    // The first room is where the player starts at.
+    let (start_x, start_y) = rooms[0].center();
+    objects[0].set_pos(start_x, start_y);
 
-    for _ in 0..num_monsters {
-        // Choose random spot for this monster.
-        let x = rng.gen_range(room.x1 + 1, room.x2);
-        let y = rng.gen_range(room.y1 + 1, room.y2);
+    if let Some(pools) = theme.pools() {
+        add_pools(&mut map, &rooms, &pools, &mut rng);
+    }
+    // Chasms drop to the level below, so not where there's nothing below, nor
+    // around a boss floor, where falling would skip the boss.
+    if !location.is_bottom() && boss.is_none() && location.offset(1).boss().is_none() {
+        let chasms = Pools {
+            middle: Terrain::Chasm,
+            edge: Terrain::Chasm,
+            chance: CHASM_CHANCE,
+        };
+        add_pools(&mut map, &rooms, &chasms, &mut rng);
+    }
 
+    // Deeper down, some rooms are shrouded in magical darkness. Not on boss
+    // floors, where the fight is hard enough as it is.
+    if boss.is_none() {
+        add_darkness(&mut map, &rooms, level, &mut rng);
+    }
 
-        // Only place it if the tile is not blocked.
-        if !is_blocked(x, y, map, objects) {
-            let mut monster = match monster_choice.ind_sample(rng) {
-                "orc" => {
-                    // Create an orc.
-                    let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
-                    orc.fighter = Some(Fighter {
-                        max_hp: 20,
-                        hp: 20,
-                        defense: 0,
-                        power: 4,
-                        xp: 35,
-                        on_death: DeathCallback::Monster,
-                    });
-                    orc.ai = Some(Ai::Basic);
-                    orc
-                }
-                "troll" => {
-                    // Create a troll.
-                    let mut troll = Object::new(x, y, 'T', "troll", colors::DARKER_GREEN, true);
-                    troll.fighter = Some(Fighter {
-                        max_hp: 30,
-                        hp: 30,
-                        defense: 2,
-                        power: 8,
-                        xp: 100,
-                        on_death: DeathCallback::Monster,
-                    });
-                    troll.ai = Some(Ai::Basic);
-                    troll
-                }
-                _ => unreachable!(),
-            };
-            monster.alive = true;
+    // Name the level, and now and then, set it apart some. Not boss floors,
+    // which are set apart enough.
+    map.name = theme.level_name(&mut rng);
+    map.ambience = theme.ambience(&mut rng).into();
+    if boss.is_none() && level >= MODIFIER_MIN_LEVEL {
+        map.modifiers = Modifier::ALL.iter()
+            .cloned()
+            .filter(|_| rng.gen_range(0, 100) < MODIFIER_CHANCE)
+            .collect();
+    }
 
-            objects.push(monster);
+    // Add some content to every other room, such as monsters.
+    // NOTE: No objects are placed in the player's starting room.
+    let mut out_of_depth = OutOfDepth::default();
+    for &room in &rooms[1..] {
+        place_objects(room, &map, objects, level, theme, &mut out_of_depth, &mut rng);
+        if generator == Generator::Labyrinth {
+            place_items(room, LABYRINTH_EXTRA_ITEMS, &map, objects, level, theme, &mut out_of_depth, &mut rng);
         }
     }
 
-    // Maximum number of items per room.
-    let max_items = from_dungeon_level(&[
-        Transition {level: 1, value: 1},
-        Transition {level: 4, value: 2},
-    ], level);
+    // Every room should already be reachable through the tunnels, but dig a
+    // straight tunnel from the start to any that isn't, just in case.
+    let mut reachable = reachable_tiles(&map, start_x, start_y);
+    for room in &rooms {
+        let (x, y) = room.center();
+        if !reachable[x as usize][y as usize] {
+            create_h_tunnel(start_x, x, start_y, &mut map);
+            create_v_tunnel(start_y, y, x, &mut map);
+            reachable = reachable_tiles(&map, start_x, start_y);
+        }
+    }
+    add_patrols(&rooms, objects, &mut rng);
 
-    // Choose random number of items.
-    let num_items = rng.gen_range(0, max_items + 1);
+    // Now and then, add a hand-made vault. It may have a spot for the stairs.
+    // Not on boss floors, where the only way down is past the boss.
+    let vault_stairs = if boss.is_none() && rng.gen_range(0, 100) < VAULT_CHANCE {
+        place_vault(&mut map, objects, level, theme, &reachable, &mut rng)
+    } else {
+        None
+    };
 
-    // Item random table.
-    let item_chances = &mut [
-        // healing potion always shows up, even if all other items have 0 chance
-        Weighted {weight: 35, item: Item::Heal},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 25}], level),
-                  item: Item::Lightning},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 6, value: 25}], level),
-                  item: Item::Fireball},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 10}], level),
-                  item: Item::Confuse},
-    ];
+    // Create stairs at the center of the last room. With a single room, that's
+    // where the player starts, so use a corner of it instead.
+    let last_room = rooms[rooms.len() - 1];
+    let (stairs_x, stairs_y) = if let Some(stairs) = vault_stairs {
+        stairs
+    } else if rooms.len() > 1 {
+        last_room.center()
+    } else {
+        (last_room.x1 + 1, last_room.y1 + 1)
+    };
+    debug_assert!((stairs_x, stairs_y) != (start_x, start_y), "The stairs are on the player's start.");
+    debug_assert!(is_connected(&map, (start_x, start_y), &[(stairs_x, stairs_y)]),
+                  "The stairs can't be reached from the start.");
+    if location.is_bottom() {
+        // The bottom of a branch has a hoard, instead of stairs down. The
+        // dungeon's also has the relic.
+        place_items(last_room, HOARD_ITEMS, &map, objects, level, theme, &mut out_of_depth, &mut rng);
+        if location.has_relic() {
+            objects.push(make_item(Item::Relic, stairs_x, stairs_y, level, &mut rng));
+        }
+    } else {
+        let mut stairs = Object::new(stairs_x, stairs_y, theme.stairs_char, "stairs", colors::WHITE, false);
+        stairs.always_visible = true;
+        objects.push(stairs);
+    }
 
-    let item_choice = WeightedChoice::new(item_chances);
+    // Every level has stairs back up, where the player starts. On the first
+    // one, they lead up to the town.
+    let mut upstairs = Object::new(start_x, start_y, '<', "upstairs", colors::WHITE, false);
+    upstairs.always_visible = true;
+    objects.push(upstairs);
 
-    for _ in 0..num_items {
-        // Choose random spot for this item.
-        let x = rng.gen_range(room.x1 + 1, room.x2);
-        let y = rng.gen_range(room.y1 + 1, room.y2);
+    // Some levels have more than one way down, so the one below has as many
+    // ways up. The extra stairs go in the middle of other rooms.
+    let extra_stairs = if location.is_bottom() { 0 } else { stairs_count(seed, location) - 1 };
+    let extra_upstairs = match location.above() {
+        Some(above) if above.branch == location.branch => stairs_count(seed, above) - 1,
+        _ => 0,
+    };
+    let mut spots: Vec<(i32, i32)> = rooms.iter()
+        .skip(1)
+        .take(rooms.len().saturating_sub(2))
+        .map(Rect::center)
+        .filter(|&spot| spot != (stairs_x, stairs_y))
+        .collect();
+    rng.shuffle(&mut spots);
+    for (i, &(x, y)) in spots.iter().take(extra_stairs + extra_upstairs).enumerate() {
+        let mut stairs = if i < extra_stairs {
+            Object::new(x, y, theme.stairs_char, "stairs", colors::WHITE, false)
+        } else {
+            Object::new(x, y, '<', "upstairs", colors::WHITE, false)
+        };
+        stairs.always_visible = true;
+        objects.push(stairs);
+    }
 
-        // Only place it if the tile is not blocked.
-        if !is_blocked(x, y, map, objects) {
-            let mut item = match item_choice.ind_sample(rng) {
-                Item::Heal => {
-                    // Create a healing potion.
-                    let mut object = Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::Lightning => {
-                    // Create a lightning bolt scroll.
-                    let mut object = Object::new(x, y, '#', "scroll of lightning bolt",
-                                                 colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Lightning);
-                    object
-                }
-                Item::Fireball => {
-                    // Create a fireball scroll.
-                    let mut object = Object::new(x, y, '#', "scroll of fireball",
-                                                 colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Fireball);
-                    object
-                }
-                Item::Confuse => {
-                    // Create a confuse scroll.
-                    let mut object = Object::new(x, y, '#', "scroll of confusion",
-                                                 colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Confuse);
-                    object
-                }
-            };
-            item.always_visible = true;
-            objects.push(item);
+    if generator == Generator::Labyrinth {
+        if let Some((x, y)) = guard_spot(&map, objects, (stairs_x, stairs_y), GUARDIAN_RADIUS, &mut rng) {
+            objects.push(make_monster("minotaur", x, y, level, &mut rng));
+        }
+    }
+    if let Some(boss) = boss {
+        if let Some((x, y)) = guard_spot(&map, objects, (stairs_x, stairs_y), 1, &mut rng) {
+            objects.push(make_boss(boss, x, y, level, &mut rng));
+        }
+    }
+
+    let locked_room = if boss.is_none() && level >= LOCKED_ROOM_MIN_LEVEL && rooms.len() > 1 &&
+        rng.gen_range(0, 100) < LOCKED_ROOM_CHANCE {
+        lock_room(&rooms[1..], &mut map, objects, level, (start_x, start_y), &mut rng)
+    } else {
+        None
+    };
+
+    let artifacts_found = artifacts.len();
+    if rooms.len() > 1 && rng.gen_range(0, 100) < ARTIFACT_CHANCE {
+        place_artifact(&rooms[1..], &map, objects, level, artifacts, &mut rng);
+    }
+
+    if boss.is_none() && level >= SHOP_MIN_LEVEL && rooms.len() > 1 && rng.gen_range(0, 100) < SHOP_CHANCE {
+        place_shop(&rooms[1..], &map, objects, level, theme, (stairs_x, stairs_y), &mut rng);
+    }
+
+    for &branch in Branch::ALL.iter().filter(|branch| branch.entrance() == Some(location)) {
+        place_branch_stairs(branch, &rooms[1..], &map, objects, (start_x, start_y), &mut rng);
+    }
+
+    add_features(&mut map, &rooms, objects, theme.kind, &mut rng);
+    if !connect_objects(&mut map, objects, (start_x, start_y), locked_room) {
+        return None;
+    }
+    seal_border(&mut map);
+
+    // Danger from deeper down is worth a warning more than treasure is.
+    map.feeling = if out_of_depth.monsters > 0 {
+        Some(Feeling::Danger)
+    } else if out_of_depth.items > 0 || artifacts.len() > artifacts_found {
+        Some(Feeling::Treasure)
+    } else {
+        None
+    };
+    Some((map, rooms))
+}
+
+/// Generate the town: no monsters, only the shopkeeper, the healer and the
+/// player's stash, around the stairs down.
+fn make_town(objects: &mut Vec<Object>, theme: &Theme, rng: &mut StdRng) -> Map {
+    let mut map = Map::new(TOWN_WIDTH, TOWN_HEIGHT, Tile::wall(), theme.clone());
+    let town = town::make_town(&mut map, rng);
+    map.name = theme.level_name(rng);
+    map.ambience = theme.ambience(rng).into();
+    objects[0].set_pos(town.start.0, town.start.1);
+
+    let mut stairs = Object::new(town.stairs.0, town.stairs.1, theme.stairs_char, "stairs", colors::WHITE, false);
+    stairs.always_visible = true;
+    objects.push(stairs);
+
+    // The shop sells what's found on the first levels.
+    objects.push(make_shopkeeper(town.shop.0, town.shop.1, 1, theme, rng));
+
+    let mut healer = Object::new(town.healer.0, town.healer.1, '@', "healer", colors::LIGHT_BLUE, true);
+    healer.fighter = Some(Fighter {
+        max_hp: 60,
+        hp: 60,
+        defense: 3,
+        power: 10,
+        xp: 200,
+        flee_below: 0,
+        on_death: DeathCallback::Monster,
+    });
+    healer.ai = Some(Ai::Healer);
+    healer.alive = true;
+    objects.push(healer);
+
+    let mut stash = Object::new(town.stash.0, town.stash.1, '&', "stash", colors::LIGHT_AMBER, true);
+    stash.chest = Some(Chest {
+        locked: false,
+        stash: true,
+    });
+    objects.push(stash);
+
+    objects.push(make_road(town.road.0, town.road.1));
+
+    seal_border(&mut map);
+    map
+}
+
+/// Build the road out of town, into the wilderness.
+pub fn make_road(x: i32, y: i32) -> Object {
+    let mut road = Object::new(x, y, '=', "road", colors::LIGHT_SEPIA, false);
+    road.always_visible = true;
+    road
+}
+
+/// Make sure the stairs, items and chests on a level can all be walked to from
+/// the start, with the locked doors open. Rooms, vaults and pools added after
+/// a tunnel can cut it off, so dig a new one to anything that is, through as
+/// few walls as it takes to meet the rest of the level. Returns false if
+/// something can only be reached through walls that have to stay.
+fn connect_objects(map: &mut Map, objects: &[Object], start: (i32, i32), locked_room: Option<Rect>) -> bool {
+    let targets: Vec<(i32, i32)> = objects[1..].iter()
+        .filter(|object| object.item.is_some() || object.chest.is_some() || object.name.contains("stairs"))
+        .map(Object::pos)
+        .collect();
+
+    let mut reachable = flood_fill(map, start, &is_open_for_tunnels);
+    for &(x, y) in &targets {
+        if reachable[x as usize][y as usize] {
+            continue;
+        }
+        let tunnel = match tunnel_route(map, (x, y), &reachable, locked_room) {
+            Some(tunnel) => tunnel,
+            None => return false,
+        };
+        for (dig_x, dig_y) in tunnel {
+            if !is_open_for_tunnels(&map[(dig_x, dig_y)]) {
+                map[(dig_x, dig_y)] = Tile::empty();
+            }
+        }
+        reachable = flood_fill(map, start, &is_open_for_tunnels);
+    }
+    true
+}
+
+/// Whether a tunnel can go through a tile without digging: it can be walked
+/// on, or it's a locked door.
+fn is_open_for_tunnels(tile: &Tile) -> bool {
+    tile.is_walkable() || tile.lock.is_some()
+}
+
+/// The tiles of a tunnel from a spot to the reachable part of a level, going
+/// through as few walls as it can. It can't go through permanent walls, like
+/// a vault's, nor the map's border, nor into a locked room but by its doors.
+/// None if there's no way through.
+fn tunnel_route(map: &Map, from: (i32, i32), reachable: &[Vec<bool>], locked_room: Option<Rect>)
+                -> Option<Vec<(i32, i32)>> {
+    let can_tunnel = |x: i32, y: i32| {
+        let tile = &map[(x, y)];
+        let on_border = x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1;
+        let in_locked_wall = locked_room.map_or(false, |room| {
+            let inside = x >= room.x1 && x <= room.x2 && y >= room.y1 && y <= room.y2;
+            inside && (x == room.x1 || x == room.x2 || y == room.y1 || y == room.y2)
+        });
+        !on_border && (is_open_for_tunnels(tile) || (tile.diggable && !in_locked_wall))
+    };
+
+    // Walls dug through to get to every tile, searched in that order: open
+    // tiles go to the front of the queue, and ones to dig to the back.
+    let mut walls = vec![vec![None; map.height as usize]; map.width as usize];
+    let mut came_from = vec![vec![None; map.height as usize]; map.width as usize];
+    walls[from.0 as usize][from.1 as usize] = Some(0);
+    let mut to_visit = VecDeque::new();
+    to_visit.push_back(from);
+    while let Some((x, y)) = to_visit.pop_front() {
+        if reachable[x as usize][y as usize] {
+            // Follow the way back to where the tunnel started.
+            let mut tunnel = vec![(x, y)];
+            let mut step = (x, y);
+            while let Some(previous) = came_from[step.0 as usize][step.1 as usize] {
+                tunnel.push(previous);
+                step = previous;
+            }
+            return Some(tunnel);
+        }
+        let walls_here = walls[x as usize][y as usize].unwrap();
+        for &(next_x, next_y) in &[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            if !map.in_bounds(next_x, next_y) || !can_tunnel(next_x, next_y) {
+                continue;
+            }
+            let open = is_open_for_tunnels(&map[(next_x, next_y)]);
+            let next_walls = if open { walls_here } else { walls_here + 1 };
+            if walls[next_x as usize][next_y as usize].map_or(true, |walls| next_walls < walls) {
+                walls[next_x as usize][next_y as usize] = Some(next_walls);
+                came_from[next_x as usize][next_y as usize] = Some((x, y));
+                if open {
+                    to_visit.push_front((next_x, next_y));
+                } else {
+                    to_visit.push_back((next_x, next_y));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Make the border of the map permanent walls, so that nothing digs its way
+/// off the map.
+fn seal_border(map: &mut Map) {
+    for x in 0..map.width {
+        for y in 0..map.height {
+            if x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1 {
+                map[(x, y)] = Tile::permanent_wall();
+            }
+        }
+    }
+}
+
+/// How big a level is. The first levels are small, and they grow deeper down.
+fn map_size(level: u32) -> (i32, i32) {
+    let width = from_dungeon_level(&[
+        Transition {level: 1, value: 70},
+        Transition {level: 4, value: 85},
+        Transition {level: 7, value: 100},
+        Transition {level: 10, value: 120},
+    ], level);
+    let height = from_dungeon_level(&[
+        Transition {level: 1, value: 60},
+        Transition {level: 4, value: 75},
+        Transition {level: 7, value: 100},
+        Transition {level: 10, value: 110},
+    ], level);
+    (width as i32, height as i32)
+}
+
+/// How many stairs lead down from a level, the same every time for a run.
+/// Boss floors have a single one, past the boss, and so does the town.
+fn stairs_count(seed: u64, location: Location) -> usize {
+    if location.is_town() || location.boss().is_some() {
+        return 1;
+    }
+    let mut rng = StdRng::from_seed(&[seed as usize, (seed >> 32) as usize, location.branch as usize,
+                                      location.depth as usize, STAIRS_COUNT_SEED]);
+    rng.gen_range(1, MAX_STAIRS + 1)
+}
+
+/// The random number generator for a level of a run, on some attempt at
+/// generating it.
+fn level_rng(seed: u64, location: Location, attempt: usize) -> StdRng {
+    let mut key = vec![seed as usize, (seed >> 32) as usize, location.branch as usize, location.depth as usize];
+    // Retries need a fresh start, but the first try stays the same as ever.
+    if attempt > 0 {
+        key.push(attempt);
+    }
+    StdRng::from_seed(&key[..])
+}
+
+/// Put the stairs into a branch on a free tile of one of the given rooms,
+/// that can be walked to from the start.
+fn place_branch_stairs(branch: Branch, rooms: &[Rect], map: &Map, objects: &mut Vec<Object>, start: (i32, i32),
+                       rng: &mut StdRng) {
+    for _ in 0..BRANCH_STAIRS_TRIES {
+        let room = match rng.choose(rooms) {
+            Some(&room) => room,
+            None => return,
+        };
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+        let taken = objects.iter().any(|object| object.pos() == (x, y));
+        if !taken && !is_blocked(x, y, map, objects) && is_connected(map, start, &[(x, y)]) {
+            let mut stairs = Object::new(x, y, '>', &branch.stairs_name(), colors::GOLD, false);
+            stairs.always_visible = true;
+            objects.push(stairs);
+            return;
+        }
+    }
+}
+
+/// Shroud some of the rooms, but the first, in magical darkness, walls and all.
+fn add_darkness(map: &mut Map, rooms: &[Rect], level: u32, rng: &mut StdRng) {
+    let dark_chance = from_dungeon_level(&[
+        Transition {level: 3, value: 5},
+        Transition {level: 6, value: 10},
+        Transition {level: 9, value: 15},
+    ], level);
+    for room in &rooms[1..] {
+        if rng.gen_range(0, 100) >= dark_chance {
+            continue;
+        }
+        for x in room.x1..(room.x2 + 1) {
+            for y in room.y1..(room.y2 + 1) {
+                if let Some(tile) = map.get_mut(x, y) {
+                    tile.dark = true;
+                }
+            }
+        }
+    }
+}
+
+/// Scatter rubble, bones, statues and bloodstains around some of the rooms,
+/// but the first, on floor with nothing on it yet.
+fn add_features(map: &mut Map, rooms: &[Rect], objects: &[Object], theme: ThemeKind, rng: &mut StdRng) {
+    let mut weights: Vec<Weighted<Feature>> = Feature::ALL.iter()
+        .map(|&feature| Weighted {weight: feature.weight(theme), item: feature})
+        .collect();
+    let feature_choice = WeightedChoice::new(&mut weights);
+    for room in &rooms[1..] {
+        if rng.gen_range(0, 100) >= FEATURE_CHANCE {
+            continue;
+        }
+        for _ in 0..rng.gen_range(1, FEATURE_MAX_COUNT + 1) {
+            let (x, y) = (rng.gen_range(room.x1 + 1, room.x2), rng.gen_range(room.y1 + 1, room.y2));
+            let taken = map[(x, y)].feature.is_some() || objects.iter().any(|object| object.pos() == (x, y));
+            if !taken && !is_blocked(x, y, map, objects) {
+                map[(x, y)].feature = Some(feature_choice.ind_sample(rng));
+            }
+        }
+    }
+}
+
+/// Put pools of water or lava, or chasms, in some of the rooms, but the first.
+/// A pool that would cut rooms off from the start is left out.
+fn add_pools(map: &mut Map, rooms: &[Rect], pools: &Pools, rng: &mut StdRng) {
+    let start = rooms[0].center();
+    let centers: Vec<(i32, i32)> = rooms.iter().map(|room| room.center()).collect();
+    for room in &rooms[1..] {
+        if rng.gen_range(0, 100) >= pools.chance {
+            continue;
+        }
+        let (pool_x, pool_y) = (rng.gen_range(room.x1 + 1, room.x2), rng.gen_range(room.y1 + 1, room.y2));
+        let radius = rng.gen_range(1, POOL_MAX_RADIUS + 1);
+        let before = map.clone();
+        for x in (pool_x - radius)..(pool_x + radius + 1) {
+            for y in (pool_y - radius)..(pool_y + radius + 1) {
+                let distance_squared = (x - pool_x).pow(2) + (y - pool_y).pow(2);
+                let tile = match map.get_mut(x, y) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                if tile.blocked || distance_squared > radius.pow(2) {
+                    continue;
+                }
+                tile.terrain = if distance_squared < radius.pow(2) / 2 { pools.middle } else { pools.edge };
+            }
+        }
+        if !is_connected(map, start, &centers) {
+            *map = before;
+        }
+    }
+}
+
+/// Stamp one of the vaults from the assets into solid rock, and dig a tunnel
+/// to it from the closest reachable tile. Monsters and items go on its
+/// markers. Returns where the stairs go, if it has a spot for them.
+fn place_vault(map: &mut Map, objects: &mut Vec<Object>, level: u32, theme: &Theme, reachable: &[Vec<bool>],
+               rng: &mut StdRng) -> Option<(i32, i32)> {
+    let vaults = vault::load_vaults();
+    let vault = rng.choose(&vaults)?;
+    let (width, height) = (vault.width(), vault.height());
+    if width > map.width - 4 || height > map.height - 4 {
+        return None;
+    }
+
+    // Find a spot where the vault and the walls around it are all rock.
+    let mut spot = None;
+    for _ in 0..VAULT_TRIES {
+        let x = rng.gen_range(2, map.width - width - 1);
+        let y = rng.gen_range(2, map.height - height - 1);
+        let solid = ((x - 1)..(x + width + 1)).all(|tile_x| {
+            ((y - 1)..(y + height + 1)).all(|tile_y| map[(tile_x, tile_y)].blocked)
+        });
+        if solid {
+            spot = Some((x, y));
+            break;
+        }
+    }
+    let (x, y) = spot?;
+    let floor = vault.stamp(x, y, map);
+
+    // Connect it up.
+    let mut open = vec![];
+    for tile_x in 0..map.width {
+        for tile_y in 0..map.height {
+            if reachable[tile_x as usize][tile_y as usize] {
+                open.push((tile_x, tile_y));
+            }
+        }
+    }
+    let tunnel = floor.iter()
+        .flat_map(|&(inside, _)| open.iter().map(move |&outside| (outside, inside)))
+        .min_by_key(|&(outside, inside)| distance(outside, inside));
+    // A straight tunnel leads up to the vault, whatever the rest of the level.
+    if let Some((outside, inside)) = tunnel {
+        dig_tunnel(outside, inside, Corridor::Straight, map, rng);
+    }
+
+    let monster_chances = &mut monster_chances(level, theme);
+    let monster_choice = WeightedChoice::new(monster_chances);
+    let item_chances = &mut item_chances(level, theme);
+    let item_choice = WeightedChoice::new(item_chances);
+    let mut stairs = None;
+    for &((tile_x, tile_y), marker) in &floor {
+        match marker {
+            Some(Marker::Monster) => {
+                objects.push(make_monster(&monster_choice.ind_sample(rng), tile_x, tile_y, level, rng));
+            }
+            Some(Marker::Item) => objects.push(make_item(item_choice.ind_sample(rng), tile_x, tile_y, level, rng)),
+            Some(Marker::Stairs) => stairs = Some((tile_x, tile_y)),
+            None => {}
+        }
+    }
+    stairs
+}
+
+/// Pick a free tile close to the stairs, for a monster to guard them from.
+fn guard_spot(map: &Map, objects: &[Object], stairs: (i32, i32), radius: i32, rng: &mut StdRng)
+              -> Option<(i32, i32)> {
+    let mut spots = vec![];
+    for x in (stairs.0 - radius)..(stairs.0 + radius + 1) {
+        for y in (stairs.1 - radius)..(stairs.1 + radius + 1) {
+            if map.in_bounds(x, y) && (x, y) != stairs && !is_blocked(x, y, map, objects) {
+                spots.push((x, y));
+            }
+        }
+    }
+    rng.choose(&spots).cloned()
+}
+
+/// Put locked doors on every way into one of the given rooms, and drop their
+/// key somewhere that can be reached from the start without going through them.
+/// Returns the room, if it was locked.
+fn lock_room(rooms: &[Rect], map: &mut Map, objects: &mut Vec<Object>, level: u32, start: (i32, i32),
+             rng: &mut StdRng) -> Option<Rect> {
+    let room = match rng.choose(rooms) {
+        Some(room) => *room,
+        None => return None,
+    };
+
+    // The ways in are the gaps tunnels made in the room's walls.
+    let mut entrances = vec![];
+    for x in room.x1..(room.x2 + 1) {
+        for y in room.y1..(room.y2 + 1) {
+            let on_wall = x == room.x1 || x == room.x2 || y == room.y1 || y == room.y2;
+            if on_wall && !map[(x, y)].blocked {
+                entrances.push((x, y));
+            }
+        }
+    }
+    // A tunnel running along the wall makes too many doors.
+    if entrances.is_empty() || entrances.len() > LOCKED_ROOM_MAX_DOORS {
+        return None;
+    }
+    if entrances.iter().any(|&(x, y)| objects.iter().any(|object| object.pos() == (x, y))) {
+        return None;
+    }
+    for &(x, y) in &entrances {
+        map[(x, y)] = Tile::locked_door(level);
+    }
+
+    // Anywhere still reachable with the doors shut will do for the key.
+    let reachable = reachable_tiles(map, start.0, start.1);
+    let mut spots = vec![];
+    for x in 0..map.width {
+        for y in 0..map.height {
+            let free = !map[(x, y)].blocked && !objects.iter().any(|object| object.pos() == (x, y));
+            if reachable[x as usize][y as usize] && free {
+                spots.push((x, y));
+            }
+        }
+    }
+    match rng.choose(&spots) {
+        Some(&(x, y)) => {
+            objects.push(make_item(Item::DoorKey(level), x, y, level, rng));
+            Some(room)
+        },
+        None => {
+            // Nowhere to put the key, so leave the room open.
+            for &(x, y) in &entrances {
+                map[(x, y)] = Tile::empty();
+            }
+            None
+        }
+    }
+}
+
+/// Put one of the artifacts that can be found at this depth, and wasn't yet,
+/// in one of the given rooms.
+fn place_artifact(rooms: &[Rect], map: &Map, objects: &mut Vec<Object>, level: u32, artifacts: &mut Vec<Artifact>,
+                  rng: &mut StdRng) {
+    let candidates: Vec<Artifact> = Artifact::ALL.iter()
+        .cloned()
+        .filter(|artifact| artifact.min_level() <= level && !artifacts.contains(artifact))
+        .collect();
+    let (artifact, room) = match (rng.choose(&candidates), rng.choose(rooms)) {
+        (Some(&artifact), Some(&room)) => (artifact, room),
+        _ => return,
+    };
+    let x = rng.gen_range(room.x1 + 1, room.x2);
+    let y = rng.gen_range(room.y1 + 1, room.y2);
+    if is_blocked(x, y, map, objects) {
+        return;
+    }
+    objects.push(make_artifact(artifact, x, y, rng));
+    artifacts.push(artifact);
+}
+
+/// Put a shopkeeper with some stock in one of the given rooms, away from
+/// the stairs.
+fn place_shop(rooms: &[Rect], map: &Map, objects: &mut Vec<Object>, level: u32, theme: &Theme,
+              stairs: (i32, i32), rng: &mut StdRng) {
+    let room = match rng.choose(rooms) {
+        Some(room) => *room,
+        None => return,
+    };
+    let x = rng.gen_range(room.x1 + 1, room.x2);
+    let y = rng.gen_range(room.y1 + 1, room.y2);
+    if (x, y) == stairs || is_blocked(x, y, map, objects) {
+        return;
+    }
+    objects.push(make_shopkeeper(x, y, level, theme, rng));
+}
+
+/// Build a shopkeeper, stocked with items that could be found at the given
+/// dungeon level.
+fn make_shopkeeper(x: i32, y: i32, level: u32, theme: &Theme, rng: &mut StdRng) -> Object {
+    let mut shopkeeper = Object::new(x, y, '@', "shopkeeper", colors::LIGHT_GREEN, true);
+    shopkeeper.fighter = Some(Fighter {
+        max_hp: 60,
+        hp: 60,
+        defense: 3,
+        power: 10,
+        xp: 200,
+        flee_below: 0,
+        on_death: DeathCallback::Monster,
+    });
+    shopkeeper.ai = Some(Ai::Shopkeeper);
+    shopkeeper.alive = true;
+    scale_to_depth(&mut shopkeeper, level);
+
+    // Stock up on anything that could be found at this depth, but gold.
+    let item_chances = &mut item_chances(level, theme);
+    item_chances.retain(|chance| chance.item != Item::Gold);
+    let item_choice = WeightedChoice::new(item_chances);
+    for _ in 0..SHOP_STOCK_SIZE {
+        let item = make_item(item_choice.ind_sample(rng), x, y, level, rng);
+        let stack = shopkeeper.carried.iter_mut().find(|other| {
+            item.item.map_or(false, |i| i.is_stackable()) && other.item == item.item
+        });
+        match stack {
+            Some(stack) => stack.count += 1,
+            None => shopkeeper.carried.push(item),
+        }
+    }
+    shopkeeper
+}
+
+/// Build a monster of the given kind from the catalog at the given position,
+/// as tough as it should be at that dungeon level.
+pub fn make_monster<R: Rng>(kind: &str, x: i32, y: i32, level: u32, rng: &mut R) -> Object {
+    let mut monster = match MonsterCatalog::load().get(kind) {
+        Some(monster) => monster.make(x, y),
+        None => panic!("There's no {} in the monster catalog.", kind),
+    };
+    monster.alive = true;
+    scale_to_depth(&mut monster, level);
+    if rng.gen_range(0, 100) < MONSTER_GOLD_CHANCE {
+        monster.carried.push(make_gold(x, y, level, rng));
+    }
+    monster
+}
+
+/// Build a boss at the given position. It carries a rare piece of equipment
+/// or better, which it drops when killed.
+fn make_boss<R: Rng>(boss: Boss, x: i32, y: i32, level: u32, rng: &mut R) -> Object {
+    let (glyph, color) = boss.glyph();
+    let mut monster = Object::new(x, y, glyph, boss.name(), color, true);
+    monster.fighter = Some(boss.fighter());
+    monster.ai = Some(Ai::Boss { phase: 0 });
+    monster.boss = Some(boss);
+    monster.alive = true;
+    scale_to_depth(&mut monster, level);
+
+    let drop = loop {
+        let item = *rng.choose(&BOSS_DROPS).unwrap();
+        let drop = make_item_of_rarity(item, x, y, level, Rarity::Rare, rng);
+        if !drop.equipment.map_or(false, |e| e.cursed) {
+            break drop;
+        }
+    };
+    monster.carried.push(drop);
+    monster
+}
+
+/// Make a pile of gold, bigger the deeper it is.
+pub fn make_gold<R: Rng>(x: i32, y: i32, level: u32, rng: &mut R) -> Object {
+    let mut gold = Object::new(x, y, '$', "pile of gold", colors::GOLD, false);
+    gold.item = Some(Item::Gold);
+    gold.count = rng.gen_range(level * 2, level * 6 + 1);
+    gold
+}
+
+/// Something the player dug out of rubble: some gold, or an item as found on
+/// the level.
+pub fn make_buried<R: Rng>(x: i32, y: i32, level: u32, theme: &Theme, rng: &mut R) -> Object {
+    if rng.gen_range(0, 100) < BURIED_GOLD_CHANCE {
+        return make_gold(x, y, level, rng);
+    }
+    let item = WeightedChoice::new(&mut item_chances(level, theme)).ind_sample(rng);
+    make_item(item, x, y, level, rng)
+}
+
+/// Build an artifact at the given position. Unlike other equipment, it's
+/// always the same, and never cursed.
+pub fn make_artifact<R: Rng>(artifact: Artifact, x: i32, y: i32, rng: &mut R) -> Object {
+    let mut object = make_item(artifact.item(), x, y, 1, rng);
+    object.name = artifact.name().into();
+    object.rarity = Rarity::Legendary;
+    object.color = Rarity::Legendary.color();
+    let (power, defense, max_hp) = match artifact {
+        Artifact::Sunsword => (5, 0, 0),
+        Artifact::RingOfTheTrollKing => (2, 0, 10),
+        Artifact::AmuletOfClarity => (0, 1, 20),
+        Artifact::BootsOfTheWisp => (0, 3, 0),
+    };
+    if let Some(ref mut equipment) = object.equipment {
+        equipment.power_bonus = power;
+        equipment.defense_bonus = defense;
+        equipment.max_hp_bonus = max_hp;
+        equipment.cursed = false;
+        equipment.curse_known = false;
+        equipment.enchantment = 0;
+        equipment.brand = if artifact == Artifact::Sunsword { Some(Brand::Flaming) } else { None };
+        equipment.resistance = None;
+        equipment.artifact = Some(artifact);
+        equipment.thorns = 0;
+        equipment.reflection = false;
+    }
+    object
+}
+
+/// Make a monster tougher the deeper it is, and worth more experience to match.
+fn scale_to_depth(monster: &mut Object, level: u32) {
+    // Extra HP and experience, in percent of the base values.
+    let bonus_percent = from_dungeon_level(&[
+        Transition {level: 3, value: 25},
+        Transition {level: 5, value: 50},
+        Transition {level: 7, value: 100},
+        Transition {level: 10, value: 150},
+    ], level) as i32;
+    let bonus_power = from_dungeon_level(&[
+        Transition {level: 3, value: 1},
+        Transition {level: 5, value: 2},
+        Transition {level: 7, value: 3},
+        Transition {level: 10, value: 5},
+    ], level) as i32;
+
+    if let Some(ref mut fighter) = monster.fighter {
+        fighter.max_hp += fighter.max_hp * bonus_percent / 100;
+        fighter.hp = fighter.max_hp;
+        fighter.power += bonus_power;
+        fighter.xp += fighter.xp * bonus_percent / 100;
+    }
+}
+
+/// Place up to `size` monsters of the same kind on open tiles around the
+/// given spot, closest tiles first. They make up a new pack.
+fn place_pack(kind: &str, size: i32, x: i32, y: i32, level: u32, map: &Map, objects: &mut Vec<Object>,
+              rng: &mut StdRng) {
+    let pack = objects.iter().filter_map(|object| object.pack).max().map_or(0, |pack| pack + 1);
+    let mut spots = vec![];
+    for spot_x in (x - PACK_RADIUS)..(x + PACK_RADIUS + 1) {
+        for spot_y in (y - PACK_RADIUS)..(y + PACK_RADIUS + 1) {
+            if map.in_bounds(spot_x, spot_y) {
+                spots.push((spot_x, spot_y));
+            }
+        }
+    }
+    // Shuffle first, so the pack doesn't always fill the same tiles at each distance.
+    rng.shuffle(&mut spots);
+    spots.sort_by_key(|&(spot_x, spot_y)| cmp::max((spot_x - x).abs(), (spot_y - y).abs()));
+
+    let mut placed = 0;
+    for (spot_x, spot_y) in spots {
+        if placed == size {
+            break;
+        }
+        if !is_blocked(spot_x, spot_y, map, objects) {
+            let mut monster = make_monster(kind, spot_x, spot_y, level, rng);
+            monster.pack = Some(pack);
+            objects.push(monster);
+            placed += 1;
+        }
+    }
+}
+
+/// Send some of the basic monsters that are awake on their rounds, from the
+/// room they're in through a few others and back.
+fn add_patrols(rooms: &[Rect], objects: &mut [Object], rng: &mut StdRng) {
+    if rooms.len() < PATROL_MIN_WAYPOINTS {
+        return;
+    }
+    for monster in objects.iter_mut().filter(|object| object.ai == Some(Ai::Basic)) {
+        if rng.gen_range(0, 100) >= PATROL_CHANCE {
+            continue;
+        }
+        let mut route: Vec<(i32, i32)> = rooms.iter().map(Rect::center).collect();
+        rng.shuffle(&mut route);
+        route.truncate(rng.gen_range(PATROL_MIN_WAYPOINTS, PATROL_MAX_WAYPOINTS + 1));
+        route.insert(0, monster.pos());
+        monster.ai = Some(Ai::Patrolling {
+            route,
+            next: 1,
+            last_seen: None,
+        });
+    }
+}
+
+/// Build a hidden trap of the given kind at the given position.
+fn make_trap(kind: TrapKind, x: i32, y: i32) -> Object {
+    let (name, color) = match kind {
+        TrapKind::BearTrap => ("bear trap", colors::LIGHT_GREY),
+        TrapKind::Dart => ("dart trap", colors::LIGHT_RED),
+        TrapKind::Teleport => ("teleport trap", colors::LIGHT_VIOLET),
+        TrapKind::Confusion => ("confusion trap", colors::LIGHT_GREEN),
+        TrapKind::Alarm => ("alarm trap", colors::LIGHT_YELLOW),
+        TrapKind::Pit => ("pit", colors::DARK_SEPIA),
+    };
+    let mut trap = Object::new(x, y, '^', name, color, false);
+    trap.trap = Some(Trap {
+        kind,
+        revealed: false,
+    });
+    trap.always_visible = true;
+    trap
+}
+
+/// How likely each monster is to be found alone at the given dungeon level.
+fn monster_chances(level: u32, theme: &Theme) -> Vec<Weighted<String>> {
+    MonsterCatalog::load().monsters(level, theme.kind)
+}
+
+/// How likely each item is to be found at the given dungeon level.
+fn item_chances(level: u32, theme: &Theme) -> Vec<Weighted<Item>> {
+    SpawnTables::load().items(level, theme.kind)
+}
+
+/// Affixes a piece of equipment can get at some depth. Rusty gear gets
+/// rarer deeper down, and finer gear more common.
+fn prefix_chances(level: u32) -> Vec<Weighted<Option<Affix>>> {
+    vec![
+        Weighted {weight: 60, item: None},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 1, value: 20}, Transition{level: 4, value: 10},
+                                               Transition{level: 7, value: 0}], level),
+                  item: Some(Affix::Rusty)},
+        Weighted {weight: 15, item: Some(Affix::Sturdy)},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 3, value: 10}], level),
+                  item: Some(Affix::Fine)},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 6, value: 5}], level),
+                  item: Some(Affix::Masterwork)},
+    ]
+}
+
+fn suffix_chances(level: u32, is_armor: bool) -> Vec<Weighted<Option<Affix>>> {
+    let mut chances = vec![
+        Weighted {weight: 70, item: None},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 10}], level),
+                  item: Some(Affix::OfTheBear)},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 10}], level),
+                  item: Some(Affix::OfWarding)},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 3, value: 8}], level),
+                  item: Some(Affix::OfMight)},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 5}], level),
+                  item: Some(Affix::OfFireResistance)},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 5}], level),
+                  item: Some(Affix::OfWarmth)},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 5}], level),
+                  item: Some(Affix::OfGrounding)},
+    ];
+    // Only armor gets hit back through.
+    if is_armor {
+        chances.push(Weighted {weight: from_dungeon_level(&[Transition{level: 3, value: 5}], level),
+                               item: Some(Affix::OfThorns)});
+        chances.push(Weighted {weight: from_dungeon_level(&[Transition{level: 5, value: 4}], level),
+                               item: Some(Affix::OfReflection)});
+    }
+    chances
+}
+
+/// Pick how rare a piece of equipment is. The rarer tiers only show up deeper.
+fn roll_rarity<R: Rng>(level: u32, rng: &mut R) -> Rarity {
+    let rarity_chances = &mut [
+        Weighted {weight: 70, item: Rarity::Common},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 20}], level),
+                  item: Rarity::Uncommon},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 8}], level),
+                  item: Rarity::Rare},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 7, value: 2}], level),
+                  item: Rarity::Legendary},
+    ];
+    WeightedChoice::new(rarity_chances).ind_sample(rng)
+}
+
+/// Lights have a slot of their own, so they never take a hand, and no
+/// bonuses of their own.
+fn light_equipment() -> Equipment {
+    Equipment {
+        slot: Slot::Light,
+        two_handed: false,
+        equipped: false,
+        power_bonus: 0,
+        defense_bonus: 0,
+        max_hp_bonus: 0,
+        cursed: false,
+        curse_known: false,
+        enchantment: 0,
+        brand: None,
+        resistance: None,
+        artifact: None,
+        thorns: 0,
+        reflection: false,
+    }
+}
+
+/// Ammunition goes in the quiver, with no bonuses of its own.
+fn quiver_equipment() -> Equipment {
+    Equipment {
+        slot: Slot::Quiver,
+        two_handed: false,
+        equipped: false,
+        power_bonus: 0,
+        defense_bonus: 0,
+        max_hp_bonus: 0,
+        cursed: false,
+        curse_known: false,
+        enchantment: 0,
+        brand: None,
+        resistance: None,
+        artifact: None,
+        thorns: 0,
+        reflection: false,
+    }
+}
+
+/// Build an item of the given kind at the given position. Equipment is
+/// sometimes cursed.
+pub fn make_item<R: Rng>(item: Item, x: i32, y: i32, level: u32, rng: &mut R) -> Object {
+    make_item_of_rarity(item, x, y, level, Rarity::Common, rng)
+}
+
+/// Build an item, like `make_item`. If it's equipment, it's at least as rare
+/// as the given rarity.
+fn make_item_of_rarity<R: Rng>(item: Item, x: i32, y: i32, level: u32, min_rarity: Rarity, rng: &mut R)
+                               -> Object {
+    let mut object = match item {
+        Item::Heal => {
+            // Create a healing potion.
+            let mut object = Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
+            object.item = Some(Item::Heal);
+            object
+        }
+        Item::GreaterHeal => {
+            // Create a greater healing potion.
+            let mut object = Object::new(x, y, '!', "greater healing potion", colors::LIGHT_VIOLET, false);
+            object.item = Some(Item::GreaterHeal);
+            object
+        }
+        Item::Lightning => {
+            // Create a lightning bolt scroll.
+            let mut object = Object::new(x, y, '#', "scroll of lightning bolt",
+                                         colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Lightning);
+            object
+        }
+        Item::Fireball => {
+            // Create a fireball scroll.
+            let mut object = Object::new(x, y, '#', "scroll of fireball",
+                                         colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Fireball);
+            object
+        }
+        Item::Confuse => {
+            // Create a confuse scroll.
+            let mut object = Object::new(x, y, '#', "scroll of confusion",
+                                         colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Confuse);
+            object
+        }
+        Item::Torch => {
+            // Create a torch.
+            let mut object = Object::new(x, y, '(', "torch", colors::FLAME, false);
+            object.item = Some(Item::Torch);
+            object.equipment = Some(light_equipment());
+            object.fuel = Item::Torch.max_fuel();
+            object
+        }
+        Item::Lantern => {
+            // Create a lantern.
+            let mut object = Object::new(x, y, '(', "lantern", colors::YELLOW, false);
+            object.item = Some(Item::Lantern);
+            object.equipment = Some(light_equipment());
+            object.fuel = Item::Lantern.max_fuel();
+            object
+        }
+        Item::OilFlask => {
+            // Create a flask of oil.
+            let mut object = Object::new(x, y, '!', "flask of oil", colors::AMBER, false);
+            object.item = Some(Item::OilFlask);
+            object
+        }
+        Item::MagicMapping => {
+            // Create a magic mapping scroll.
+            let mut object = Object::new(x, y, '#', "scroll of magic mapping",
+                                         colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::MagicMapping);
+            object
+        }
+        Item::WandLightning => {
+            // Create a wand of lightning.
+            let mut object = Object::new(x, y, '/', "wand of lightning", colors::LIGHT_BLUE, false);
+            object.item = Some(Item::WandLightning);
+            object.charges = rng.gen_range(WAND_MIN_CHARGES, WAND_MAX_CHARGES + 1);
+            object
+        }
+        Item::WandConfusion => {
+            // Create a wand of confusion.
+            let mut object = Object::new(x, y, '/', "wand of confusion", colors::LIGHT_PURPLE, false);
+            object.item = Some(Item::WandConfusion);
+            object.charges = rng.gen_range(WAND_MIN_CHARGES, WAND_MAX_CHARGES + 1);
+            object
+        }
+        Item::EnchantWeapon => {
+            // Create an enchant weapon scroll.
+            let mut object = Object::new(x, y, '#', "scroll of enchant weapon", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::EnchantWeapon);
+            object
+        }
+        Item::EnchantArmor => {
+            // Create an enchant armor scroll.
+            let mut object = Object::new(x, y, '#', "scroll of enchant armor", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::EnchantArmor);
+            object
+        }
+        Item::DoorKey(lock) => {
+            // Create the key to this level's locked doors.
+            let name = format!("brass key (level {})", lock);
+            let mut object = Object::new(x, y, '\'', &name, colors::BRASS, false);
+            object.item = Some(Item::DoorKey(lock));
+            object
+        }
+        Item::Mortar => {
+            // Create a mortar and pestle, to mix potions with.
+            let mut object = Object::new(x, y, '(', "mortar and pestle", colors::LIGHTER_GREY, false);
+            object.item = Some(Item::Mortar);
+            object
+        }
+        Item::Pickaxe => {
+            // Create a pickaxe, a poor weapon but good for digging.
+            let mut object = Object::new(x, y, '(', "pickaxe", colors::LIGHT_SEPIA, false);
+            object.item = Some(Item::Pickaxe);
+            object.equipment = Some(Equipment {
+                slot: Slot::RightHand,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 1,
+                defense_bonus: 0,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Digging => {
+            // Create a digging scroll.
+            let mut object = Object::new(x, y, '#', "scroll of digging", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Digging);
+            object
+        }
+        Item::Relic => {
+            // Create the relic, the crown the player came for.
+            let mut object = Object::new(x, y, '*', "Crown of the Ancient Kings", colors::AMBER, false);
+            object.item = Some(Item::Relic);
+            object.rarity = Rarity::Legendary;
+            object
+        }
+        Item::Key => {
+            // Create a key, which opens any lock once.
+            let mut object = Object::new(x, y, '\'', "iron key", colors::LIGHT_GREY, false);
+            object.item = Some(Item::Key);
+            object
+        }
+        Item::Dagger => {
+            // Create a dagger, good for throwing too.
+            let mut object = Object::new(x, y, '-', "dagger", colors::LIGHT_GREY, false);
+            object.item = Some(Item::Dagger);
+            object.equipment = Some(Equipment {
+                slot: Slot::RightHand,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 1,
+                defense_bonus: 0,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Bow => {
+            // Create a bow, which needs both hands to draw.
+            let mut object = Object::new(x, y, '}', "bow", colors::SEPIA, false);
+            object.item = Some(Item::Bow);
+            object.equipment = Some(Equipment {
+                slot: Slot::RightHand,
+                two_handed: true,
+                equipped: false,
+                power_bonus: 0,
+                defense_bonus: 0,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Sling => {
+            // Create a sling.
+            let mut object = Object::new(x, y, '}', "sling", colors::LIGHT_SEPIA, false);
+            object.item = Some(Item::Sling);
+            object.equipment = Some(Equipment {
+                slot: Slot::RightHand,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 0,
+                defense_bonus: 0,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Arrow => {
+            // Create a bundle of arrows.
+            let mut object = Object::new(x, y, '|', "arrow", colors::SEPIA, false);
+            object.item = Some(Item::Arrow);
+            object.equipment = Some(quiver_equipment());
+            object.count = rng.gen_range(5, 16);
+            object
+        }
+        Item::Stone => {
+            // Create a handful of sling stones.
+            let mut object = Object::new(x, y, '*', "sling stone", colors::GREY, false);
+            object.item = Some(Item::Stone);
+            object.equipment = Some(quiver_equipment());
+            object.count = rng.gen_range(8, 21);
+            object
+        }
+        Item::Sword => {
+            // Create a sword.
+            let mut object = Object::new(x, y, '/', "sword", colors::SKY, false);
+            object.item = Some(Item::Sword);
+            object.equipment = Some(Equipment {
+                slot: Slot::RightHand,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 3,
+                defense_bonus: 0,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Greatsword => {
+            // Create a greatsword, which needs both hands.
+            let mut object = Object::new(x, y, '/', "greatsword", colors::LIGHT_SKY, false);
+            object.item = Some(Item::Greatsword);
+            object.equipment = Some(Equipment {
+                slot: Slot::RightHand,
+                two_handed: true,
+                equipped: false,
+                power_bonus: 6,
+                defense_bonus: 0,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Shield => {
+            // Create a shield.
+            let mut object = Object::new(x, y, '[', "shield", colors::DARKER_ORANGE, false);
+            object.item = Some(Item::Shield);
+            object.equipment = Some(Equipment {
+                slot: Slot::LeftHand,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 0,
+                defense_bonus: 2,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Armor => {
+            // Create a suit of leather armor.
+            let mut object = Object::new(x, y, '[', "leather armor", colors::DARK_ORANGE, false);
+            object.item = Some(Item::Armor);
+            object.equipment = Some(Equipment {
+                slot: Slot::Body,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 0,
+                defense_bonus: 2,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Boots => {
+            // Create a pair of boots.
+            let mut object = Object::new(x, y, '[', "boots", colors::DARKER_SEPIA, false);
+            object.item = Some(Item::Boots);
+            object.equipment = Some(Equipment {
+                slot: Slot::Feet,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 0,
+                defense_bonus: 1,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Ring => {
+            // Create a ring of strength.
+            let mut object = Object::new(x, y, '=', "ring of strength", colors::GOLD, false);
+            object.item = Some(Item::Ring);
+            object.equipment = Some(Equipment {
+                slot: Slot::LeftRing,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 2,
+                defense_bonus: 0,
+                max_hp_bonus: 0,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::Amulet => {
+            // Create an amulet of vitality.
+            let mut object = Object::new(x, y, '"', "amulet of vitality", colors::LIGHT_AMBER, false);
+            object.item = Some(Item::Amulet);
+            object.equipment = Some(Equipment {
+                slot: Slot::Neck,
+                two_handed: false,
+                equipped: false,
+                power_bonus: 0,
+                defense_bonus: 0,
+                max_hp_bonus: 20,
+                cursed: false,
+                curse_known: false,
+                enchantment: 0,
+                brand: None,
+                resistance: None,
+                artifact: None,
+                thorns: 0,
+                reflection: false,
+            });
+            object
+        }
+        Item::RemoveCurse => {
+            // Create a remove curse scroll.
+            let mut object = Object::new(x, y, '#', "scroll of remove curse", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::RemoveCurse);
+            object
+        }
+        Item::Gold => make_gold(x, y, level, rng),
+    };
+    // Ammo always comes plain, so that it stacks, and so do lights.
+    let is_light = item.light_radius().is_some();
+    if let Some(equipment) = object.equipment.as_mut().filter(|e| e.slot != Slot::Quiver && !is_light) {
+        // Weapons and armor can be better or worse made. Anything can get a suffix.
+        let mut affix_chances = vec![suffix_chances(level, !equipment.is_weapon())];
+        if !equipment.slot.is_jewelry() {
+            affix_chances.push(prefix_chances(level));
+        }
+        for chances in &mut affix_chances {
+            if let Some(affix) = WeightedChoice::new(chances).ind_sample(rng) {
+                affix.apply(equipment);
+                object.name = affix.rename(&object.name);
+            }
+        }
+        // Rarer equipment shows in its rarity's color, with bigger bonuses.
+        let rarity = cmp::max(roll_rarity(level, rng), min_rarity);
+        if rarity != Rarity::Common {
+            object.rarity = rarity;
+            object.color = rarity.color();
+            let percent = rarity.bonus_percent();
+            equipment.power_bonus = equipment.power_bonus * percent / 100;
+            equipment.defense_bonus = equipment.defense_bonus * percent / 100;
+            equipment.max_hp_bonus = equipment.max_hp_bonus * percent / 100;
+        }
+        // Deeper down, some equipment comes enchanted.
+        let enchant_chance = from_dungeon_level(&[
+            Transition {level: 3, value: 10},
+            Transition {level: 6, value: 20},
+            Transition {level: 9, value: 30},
+        ], level);
+        if rng.gen_range(0, 100) < enchant_chance {
+            equipment.enchantment = rng.gen_range(1, level as i32 / 3 + 2);
+        }
+        // Some weapons are imbued with an element, more often deeper down.
+        let brand_chance = from_dungeon_level(&[
+            Transition {level: 3, value: 8},
+            Transition {level: 6, value: 15},
+        ], level);
+        if equipment.is_weapon() && rng.gen_range(0, 100) < brand_chance {
+            equipment.brand = rng.choose(&Brand::ALL).cloned();
+        }
+        if rng.gen_range(0, 100) < CURSE_CHANCE {
+            equipment.cursed = true;
+            // Some curses turn the item against its wearer.
+            if rng.gen() {
+                equipment.power_bonus = -equipment.power_bonus.abs() - 1;
+                equipment.defense_bonus = -equipment.defense_bonus.abs();
+                equipment.max_hp_bonus = -equipment.max_hp_bonus.abs();
+            }
+        }
+    }
+    object
+}
+
+fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32, theme: &Theme,
+                 out_of_depth: &mut OutOfDepth, rng: &mut StdRng) {
+    let mut max_monsters = from_dungeon_level(&[
+        Transition {level: 1, value: 2},
+        Transition {level: 4, value: 3},
+        Transition {level: 6, value: 5},
+    ], level);
+    if map.modifiers.contains(&Modifier::Crowded) {
+        max_monsters += CROWDED_EXTRA_MONSTERS;
+    }
+
+    // Choose random number of monsters.
+    let num_monsters = rng.gen_range(0, max_monsters + 1);
+
+    // Monster random table.
+    let monster_chances = &mut monster_chances(level, theme);
+    let monster_choice = WeightedChoice::new(monster_chances);
+
+    // Chance that the room's monsters come as a single pack instead.
+    let pack_chance = from_dungeon_level(&[
+        Transition {level: 2, value: 10},
+        Transition {level: 4, value: 20},
+        Transition {level: 6, value: 30},
+    ], level);
+
+    // Pack random table. Rats only ever show up in swarms.
+    let pack_chances = &mut MonsterCatalog::load().packs(level, theme.kind);
+    let pack_choice = WeightedChoice::new(pack_chances);
+
+    if num_monsters > 0 && rng.gen_range(0, 100) < pack_chance {
+        // Choose random spot for the pack to gather around.
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+
+        // Keep packs in line with how many monsters this level would have anyway.
+        let size = cmp::min(rng.gen_range(PACK_MIN_SIZE, PACK_MAX_SIZE + 1), max_monsters as i32 * 2);
+        let first = objects.len();
+        place_pack(&pack_choice.ind_sample(rng), size, x, y, level, map, objects, rng);
+        if rng.gen_range(0, 100) < SLEEP_CHANCE {
+            for monster in &mut objects[first..] {
+                monster.fall_asleep();
+            }
+        }
+    } else {
+        for _ in 0..num_monsters {
+            // Choose random spot for this monster.
+            let x = rng.gen_range(room.x1 + 1, room.x2);
+            let y = rng.gen_range(room.y1 + 1, room.y2);
+
+            // Only place it if the tile is not blocked.
+            if !is_blocked(x, y, map, objects) {
+                let spawn_level = spawn_level(level, rng);
+                let kind = if spawn_level > level {
+                    out_of_depth.monsters += 1;
+                    WeightedChoice::new(&mut self::monster_chances(spawn_level, theme)).ind_sample(rng)
+                } else {
+                    monster_choice.ind_sample(rng)
+                };
+                let mut monster = make_monster(&kind, x, y, spawn_level, rng);
+                if rng.gen_range(0, 100) < SLEEP_CHANCE {
+                    monster.fall_asleep();
+                }
+                objects.push(monster);
+            }
+        }
+    }
+
+    // Maximum number of items per room.
+    let max_items = from_dungeon_level(&[
+        Transition {level: 1, value: 1},
+        Transition {level: 4, value: 2},
+    ], level);
+
+    // Choose random number of items.
+    let num_items = rng.gen_range(0, max_items + 1);
+    place_items(room, num_items, map, objects, level, theme, out_of_depth, rng);
+
+    // Chance of a hidden trap in this room.
+    let trap_chance = from_dungeon_level(&[
+        Transition {level: 1, value: 15},
+        Transition {level: 4, value: 30},
+        Transition {level: 7, value: 45},
+    ], level);
+
+    // Trap random table.
+    let trap_chances = &mut [
+        Weighted {weight: 50, item: TrapKind::BearTrap},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 30}], level),
+                  item: TrapKind::Dart},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 3, value: 20}], level),
+                  item: TrapKind::Teleport},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 20}], level),
+                  item: TrapKind::Confusion},
+        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 15}, Transition{level: 5, value: 25}],
+                                             level),
+                  item: TrapKind::Alarm},
+        Weighted {weight: 20, item: TrapKind::Pit},
+    ];
+
+    let trap_choice = WeightedChoice::new(trap_chances);
+
+    if rng.gen_range(0, 100) < trap_chance {
+        // Choose random spot for this trap.
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+
+        // Only place it if the tile is not blocked.
+        if !is_blocked(x, y, map, objects) {
+            objects.push(make_trap(trap_choice.ind_sample(rng), x, y));
+        }
+    }
+
+    // Chance of a chest in this room.
+    if rng.gen_range(0, 100) < CHEST_CHANCE {
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+        if !is_blocked(x, y, map, objects) {
+            objects.push(make_chest(x, y, level, theme, rng));
+        }
+    }
+}
+
+/// Scatter some items from the level's item table around a room.
+fn place_items(room: Rect, num_items: u32, map: &Map, objects: &mut Vec<Object>, level: u32, theme: &Theme,
+               out_of_depth: &mut OutOfDepth, rng: &mut StdRng) {
+    let item_chances = &mut item_chances(level, theme);
+    let item_choice = WeightedChoice::new(item_chances);
+
+    for _ in 0..num_items {
+        // Choose random spot for this item.
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+
+        // Only place it if the tile is not blocked.
+        if !is_blocked(x, y, map, objects) {
+            let spawn_level = spawn_level(level, rng);
+            let item = if spawn_level > level {
+                out_of_depth.items += 1;
+                WeightedChoice::new(&mut self::item_chances(spawn_level, theme)).ind_sample(rng)
+            } else {
+                item_choice.ind_sample(rng)
+            };
+            objects.push(make_item(item, x, y, spawn_level, rng));
+        }
+    }
+}
+
+/// The level to make a monster or item for. Now and then, it's one from
+/// deeper down.
+fn spawn_level(level: u32, rng: &mut StdRng) -> u32 {
+    if rng.gen_range(0, 100) < OUT_OF_DEPTH_CHANCE {
+        level + OUT_OF_DEPTH_LEVELS
+    } else {
+        level
+    }
+}
+
+/// Build a chest holding a few items from the level's item table, maybe locked.
+fn make_chest(x: i32, y: i32, level: u32, theme: &Theme, rng: &mut StdRng) -> Object {
+    let mut chest = Object::new(x, y, '&', "chest", colors::DARK_AMBER, true);
+    chest.chest = Some(Chest {
+        locked: rng.gen_range(0, 100) < CHEST_LOCKED_CHANCE,
+        stash: false,
+    });
+    let item_chances = &mut item_chances(level, theme);
+    let item_choice = WeightedChoice::new(item_chances);
+    for _ in 0..rng.gen_range(1, CHEST_MAX_ITEMS + 1) {
+        chest.carried.push(make_item(item_choice.ind_sample(rng), x, y, level, rng));
+    }
+    chest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: u64 = 10;
+    const STRESS_SEEDS: u64 = 2000;
+
+    /// Every level of every branch.
+    fn all_locations() -> Vec<Location> {
+        Branch::ALL.iter()
+            .flat_map(|&branch| (1..branch.bottom().unwrap() + 1).map(move |depth| Location { branch, depth }))
+            .collect()
+    }
+
+    #[test]
+    fn stairs_and_rooms_are_reachable() {
+        for seed in 0..SEEDS {
+            for location in all_locations() {
+                let mut objects = vec![Object::new(0, 0, '@', "player", colors::WHITE, true)];
+                let (mut map, rooms) = generate_map(&mut objects, location, &mut vec![], seed);
+
+                // Locked doors count as open: their key is always reachable.
+                for x in 0..map.width {
+                    for y in 0..map.height {
+                        if map[(x, y)].lock.is_some() {
+                            map[(x, y)] = Tile::empty();
+                        }
+                    }
+                }
+                let (start_x, start_y) = objects[0].pos();
+                let reachable = reachable_tiles(&map, start_x, start_y);
+                let is_reachable = |(x, y): (i32, i32)| reachable[x as usize][y as usize];
+                for stairs in objects.iter().filter(|object| object.name.contains("stairs")) {
+                    assert!(is_reachable(stairs.pos()), "Seed {} at {:?}: the {} at {:?} can't be reached.",
+                            seed, location, stairs.name, stairs.pos());
+                }
+                for room in &rooms {
+                    assert!(is_reachable(room.center()), "Seed {} at {:?}: the room at {:?} can't be reached.",
+                            seed, location, room.center());
+                }
+            }
+        }
+    }
+
+    /// A walled-in map with an open row near the top, where the player is, and
+    /// a potion further down.
+    fn potion_below(potion: (i32, i32)) -> (Map, Vec<Object>) {
+        let mut map = Map::new(12, 12, Tile::wall(), Theme::for_level(1));
+        for x in 1..11 {
+            map[(x, 2)] = Tile::empty();
+        }
+        map[potion] = Tile::empty();
+        let mut potion_object = Object::new(potion.0, potion.1, '!', "healing potion", colors::VIOLET, false);
+        potion_object.item = Some(Item::Heal);
+        (map, vec![Object::new(1, 2, '@', "player", colors::WHITE, true), potion_object])
+    }
+
+    #[test]
+    fn tunnels_dig_through_plain_walls() {
+        let (mut map, objects) = potion_below((5, 6));
+        assert!(connect_objects(&mut map, &objects, (1, 2), None));
+        assert!(is_connected(&map, (1, 2), &[(5, 6)]));
+        // Straight up, the shortest way.
+        assert!((3..6).all(|y| !map[(5, y)].blocked));
+    }
+
+    #[test]
+    fn tunnels_keep_out_of_permanent_walls() {
+        let (mut map, objects) = potion_below((5, 6));
+        for x in 4..7 {
+            for y in 5..8 {
+                if (x, y) != (5, 6) {
+                    map[(x, y)] = Tile::permanent_wall();
+                }
+            }
+        }
+        assert!(!connect_objects(&mut map, &objects, (1, 2), None));
+    }
+
+    #[test]
+    fn tunnels_go_around_locked_rooms() {
+        let (mut map, objects) = potion_below((5, 10));
+        let room = Rect::new(3, 4, 4, 4);
+        create_room(room, &mut map);
+        map[(5, 3)] = Tile::empty();
+        map[(5, 4)] = Tile::locked_door(1);
+        assert!(connect_objects(&mut map, &objects, (1, 2), Some(room)));
+        assert!(is_connected(&map, (1, 2), &[(5, 10)]));
+        assert!((room.x1..room.x2 + 1).all(|x| map[(x, room.y2)].blocked));
+    }
+
+    #[test]
+    fn deeper_monsters_are_tougher() {
+        let mut rng = StdRng::from_seed(&[1]);
+        let shallow = make_monster("orc", 0, 0, 1, &mut rng).fighter.unwrap();
+        let deep = make_monster("orc", 0, 0, 8, &mut rng).fighter.unwrap();
+        assert!(deep.max_hp > shallow.max_hp);
+        assert_eq!(deep.hp, deep.max_hp);
+        assert!(deep.power > shallow.power);
+        assert!(deep.xp > shallow.xp);
+    }
+
+    #[test]
+    fn thousands_of_maps_have_valid_stairs() {
+        let locations = all_locations();
+        for seed in 0..STRESS_SEEDS {
+            // A different level for every seed, so that all get their share.
+            let location = locations[seed as usize % locations.len()];
+            let mut objects = vec![Object::new(0, 0, '@', "player", colors::WHITE, true)];
+            let map = make_map(&mut objects, location, &mut vec![], seed);
+
+            let start = objects[0].pos();
+            let stairs: Vec<(i32, i32)> = objects.iter()
+                .filter(|object| object.name == "stairs")
+                .map(Object::pos)
+                .collect();
+            if location.is_bottom() {
+                assert!(stairs.is_empty(), "Seed {} at {:?}: there are stairs down from the bottom.", seed, location);
+            } else {
+                assert!(!stairs.is_empty(), "Seed {} at {:?}: there are no stairs down.", seed, location);
+            }
+            for &(x, y) in &stairs {
+                assert!(!map[(x, y)].blocked,
+                        "Seed {} at {:?}: the stairs at {:?} are in a wall.", seed, location, (x, y));
+                assert_ne!((x, y), start, "Seed {} at {:?}: the stairs are on the player's start.", seed, location);
+            }
         }
     }
 }
@@ -0,0 +1,39 @@
+use rand::{Rng, StdRng};
+
+use map::{self, Map, Rect, Tile};
+
+/// Carve a perfect maze, with a single way between any two spots, using a
+/// recursive backtracker. Corridors are one tile wide, running between cells
+/// on the odd coordinates.
+///
+/// There are no rooms, so this returns areas to put things in.
+pub fn make_maze(map: &mut Map, rng: &mut StdRng) -> Vec<Rect> {
+    map[(1, 1)] = Tile::empty();
+    let mut path = vec![(1, 1)];
+    while let Some(&(x, y)) = path.last() {
+        // Cells next door that haven't been dug into yet.
+        let unvisited: Vec<(i32, i32)> = [(2, 0), (-2, 0), (0, 2), (0, -2)].iter()
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .filter(|&(next_x, next_y)| {
+                next_x > 0 && next_y > 0 && next_x < map.width - 1 && next_y < map.height - 1 &&
+                    map[(next_x, next_y)].blocked
+            })
+            .collect();
+        match rng.choose(&unvisited) {
+            Some(&(next_x, next_y)) => {
+                // Knock down the wall between the cells, and move on.
+                map[((x + next_x) / 2, (y + next_y) / 2)] = Tile::empty();
+                map[(next_x, next_y)] = Tile::empty();
+                path.push((next_x, next_y));
+            }
+            None => {
+                // Dead end, back up.
+                path.pop();
+            }
+        }
+    }
+
+    let mut areas = map::spread_areas(map, rng);
+    map::pick_start_and_stairs(&mut areas, &|_| true);
+    areas
+}
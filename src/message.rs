@@ -2,29 +2,56 @@ use std::ops::{Deref, DerefMut};
 
 use tcod::colors::Color;
 
+/// How many messages are kept around for the message log viewer. The panel
+/// only shows the most recent ones that fit.
+const MAX_HISTORY: usize = 200;
+
+/// How much a message matters, so routine ones can be hidden.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MessageKind {
+    Info,
+    Combat,
+    Warning,
+    Critical,
+}
+
+impl MessageKind {
+    /// Whether the message is still shown when routine messages are hidden.
+    pub fn is_important(self) -> bool {
+        match self {
+            MessageKind::Info | MessageKind::Combat => false,
+            MessageKind::Warning | MessageKind::Critical => true,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
-pub struct Messages(Vec<(String, Color)>);
+pub struct Messages(Vec<(String, Color, MessageKind)>);
 
 impl Messages {
-    pub fn new(capacity: usize) -> Self {
-        Messages(Vec::with_capacity(capacity))
+    pub fn new() -> Self {
+        Messages(Vec::with_capacity(MAX_HISTORY))
     }
 
     // TODO: Rename to add
     pub fn message<T: Into<String>>(&mut self, message: T, color: Color) {
+        self.message_kind(message, color, MessageKind::Info);
+    }
+
+    pub fn message_kind<T: Into<String>>(&mut self, message: T, color: Color, kind: MessageKind) {
         // TODO: Consider using a VecDeque?
-        // If the buffer is full, remove the first message to make room for the new one.
-        if self.len() == self.capacity() {
+        // If the history is full, remove the oldest message to make room for the new one.
+        if self.len() >= MAX_HISTORY {
             self.remove(0);
         }
 
-        // Add the new line as a tuple, with the text and the color.
-        self.push((message.into(), color));
+        // Add the new line as a tuple, with the text, the color and the kind.
+        self.push((message.into(), color, kind));
     }
 }
 
 impl Deref for Messages {
-    type Target = Vec<(String, Color)>;
+    type Target = Vec<(String, Color, MessageKind)>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::rc::Rc;
+
+use json;
+use rand::distributions::Weighted;
+use tcod::colors::Color;
+
+use map::Transition;
+use object::{Ai, DeathCallback, Fighter, Object};
+use spawn::{self, ThemeWeight};
+use theme::ThemeKind;
+
+const MONSTERS_FILE: &str = "assets/monsters.json";
+// The monsters the game shipped with, for when the file is missing or broken.
+const DEFAULT_MONSTERS: &str = include_str!("../assets/monsters.json");
+// Kinds of monsters the game itself asks for: the guardian of a labyrinth,
+// and the ones bosses call to their side.
+const REQUIRED_KINDS: [&str; 3] = ["minotaur", "orc", "rat"];
+
+thread_local! {
+    // Read once, the first time it's asked for, then shared for the rest of the game.
+    static CATALOG: Rc<MonsterCatalog> = Rc::new(MonsterCatalog::read());
+}
+
+/// A kind of monster: how it looks, how tough it is before being made
+/// tougher for the depth, and where it's found.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MonsterDef {
+    /// What the spawn tables and the game call it by.
+    pub kind: String,
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub xp: i32,
+    /// Below this percentage of its max HP, it runs away. 0 never flees.
+    #[serde(default)]
+    pub flee_below: i32,
+    pub ai: Ai,
+    /// Whether it picks up items lying around.
+    #[serde(default)]
+    pub collects_items: bool,
+    /// Whether it flies, right over chasms.
+    #[serde(default)]
+    pub flying: bool,
+    /// How likely it is to be found alone, by depth.
+    #[serde(default)]
+    alone: Vec<Transition>,
+    /// How likely it is to be found in a pack, by depth.
+    #[serde(default)]
+    pack: Vec<Transition>,
+    #[serde(default)]
+    themes: Vec<ThemeWeight>,
+}
+
+impl MonsterDef {
+    /// Build one at the given position, unhurt.
+    pub fn make(&self, x: i32, y: i32) -> Object {
+        let mut monster = Object::new(x, y, self.glyph, &self.name, self.color, true);
+        monster.fighter = Some(Fighter {
+            max_hp: self.hp,
+            hp: self.hp,
+            defense: self.defense,
+            power: self.power,
+            xp: self.xp,
+            flee_below: self.flee_below,
+            on_death: DeathCallback::Monster,
+        });
+        monster.ai = Some(self.ai.clone());
+        monster.collects_items = self.collects_items;
+        monster.flying = self.flying;
+        monster
+    }
+}
+
+/// Every kind of monster, loaded from `assets/monsters.json` so that new
+/// ones can be added without rebuilding the game.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MonsterCatalog(Vec<MonsterDef>);
+
+impl MonsterCatalog {
+    /// The catalog, read from its file the first time. A missing or broken file
+    /// gives the monsters the game shipped with.
+    pub fn load() -> Rc<Self> {
+        CATALOG.with(Rc::clone)
+    }
+
+    fn read() -> Self {
+        read_catalog().unwrap_or_else(|err| {
+            eprintln!("Could not load {}, using the built-in monsters: {}", MONSTERS_FILE, err);
+            json::from_str(DEFAULT_MONSTERS).expect("The built-in monster catalog is broken.")
+        })
+    }
+
+    pub fn get(&self, kind: &str) -> Option<&MonsterDef> {
+        self.0.iter().find(|monster| monster.kind == kind)
+    }
+
+    /// How likely each monster is to be found alone at a dungeon level.
+    pub fn monsters(&self, level: u32, theme: ThemeKind) -> Vec<Weighted<String>> {
+        self.weighted(|monster| &monster.alone, level, theme)
+    }
+
+    /// How likely each kind of pack is at a dungeon level.
+    pub fn packs(&self, level: u32, theme: ThemeKind) -> Vec<Weighted<String>> {
+        self.weighted(|monster| &monster.pack, level, theme)
+    }
+
+    fn weighted(&self, chance: fn(&MonsterDef) -> &Vec<Transition>, level: u32, theme: ThemeKind)
+                -> Vec<Weighted<String>> {
+        self.0.iter()
+            .map(|monster| Weighted {
+                weight: spawn::weight(chance(monster), &monster.themes, level, theme),
+                item: monster.kind.clone(),
+            })
+            .collect()
+    }
+
+    /// Make sure the game finds every monster it asks for, each kind only
+    /// once, and that there's something to spawn at every depth, in every
+    /// theme.
+    fn validate(&self) -> Result<(), Box<Error>> {
+        if let Some(missing) = REQUIRED_KINDS.iter().find(|&&kind| self.get(kind).is_none()) {
+            return Err(format!("The monster catalog has no {}.", missing).into());
+        }
+        for (i, monster) in self.0.iter().enumerate() {
+            if self.0[..i].iter().any(|other| other.kind == monster.kind) {
+                return Err(format!("The monster catalog has more than one {}.", monster.kind).into());
+            }
+        }
+
+        // Past the deepest change in any table, nothing changes anymore.
+        let deepest = self.0.iter()
+            .flat_map(|monster| monster.alone.iter().chain(&monster.pack))
+            .map(|transition| transition.level)
+            .max()
+            .unwrap_or(1);
+        for level in 1..(deepest + 1) {
+            for &theme in &ThemeKind::ALL {
+                let totals = [spawn::total(&self.monsters(level, theme)), spawn::total(&self.packs(level, theme))];
+                if totals.contains(&0) {
+                    return Err(format!("No monster spawns at level {} in the {:?} theme.", level, theme).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_catalog() -> Result<MonsterCatalog, Box<Error>> {
+    let mut text = String::new();
+    File::open(MONSTERS_FILE)?.read_to_string(&mut text)?;
+    let catalog: MonsterCatalog = json::from_str(&text)?;
+    catalog.validate()?;
+    Ok(catalog)
+}
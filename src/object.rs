@@ -1,9 +1,22 @@
+use std::cmp;
+
 use tcod::{BackgroundFlag, Console};
 use tcod::colors::{self, Color};
+use tcod::map::Map as FovMap;
+
+use boss::Boss;
+use equipment::{Brand, Equipment};
+use message::{MessageKind, Messages};
+use rarity::Rarity;
 
-use message::Messages;
+// How long the effects of a weapon brand last, and how much burning hurts each turn.
+const BURN_TURNS: i32 = 3;
+const SLOW_TURNS: i32 = 5;
+pub const BURN_DAMAGE: i32 = 2;
+// How many times as hard a blow lands on a sleeping target.
+const SNEAK_ATTACK_MULTIPLIER: i32 = 2;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Object {
     pub x: i32,
     pub y: i32,
@@ -15,8 +28,32 @@ pub struct Object {
     pub fighter: Option<Fighter>,
     pub ai: Option<Ai>,
     pub item: Option<Item>,
+    pub trap: Option<Trap>,
+    pub chest: Option<Chest>,
+    pub equipment: Option<Equipment>,
     pub always_visible: bool,
     pub level: i32,
+    /// How many identical items this object stands for, in an inventory stack.
+    pub count: u32,
+    /// Whether this monster picks up items lying next to it.
+    pub collects_items: bool,
+    /// Items a monster picked up, dropped when it dies.
+    pub carried: Vec<Object>,
+    /// Uses left in a wand.
+    pub charges: u32,
+    pub rarity: Rarity,
+    /// Turns left on fire, taking damage each turn.
+    pub burning_turns: i32,
+    /// Turns left slowed down, acting only every other turn.
+    pub slowed_turns: i32,
+    /// Turns a torch or lantern has left to burn.
+    pub fuel: u32,
+    /// Which boss this monster is, if it's one of them.
+    pub boss: Option<Boss>,
+    /// Whether this monster flies, right over chasms.
+    pub flying: bool,
+    /// Which pack this monster belongs to, to hunt the player together.
+    pub pack: Option<u32>,
 }
 
 impl Object {
@@ -32,8 +69,22 @@ impl Object {
             fighter: None,
             ai: None,
             item: None,
+            trap: None,
+            chest: None,
+            equipment: None,
             always_visible: false,
             level: 1,
+            count: 1,
+            collects_items: false,
+            carried: Vec::new(),
+            charges: 0,
+            rarity: Rarity::Common,
+            burning_turns: 0,
+            slowed_turns: 0,
+            fuel: 0,
+            boss: None,
+            flying: false,
+            pack: None,
         }
     }
 
@@ -46,6 +97,83 @@ impl Object {
         self.y = y;
     }
 
+    /// The name to show in the inventory, with the stack size if there's
+    /// more than one, or where it's equipped.
+    pub fn inventory_name(&self) -> String {
+        let name = if self.item.map_or(false, |i| i.light_radius().is_some()) {
+            format!("{} ({} turns of fuel)", self.display_name(), self.fuel)
+        } else {
+            self.display_name()
+        };
+        match self.equipment {
+            Some(equipment) if equipment.equipped => format!("{} (on {})", name, equipment.slot),
+            Some(_) => name,
+            _ if self.item.map_or(false, |i| i.is_wand()) => format!("{} ({} charges)", self.name, self.charges),
+            _ if self.count > 1 => format!("{} (x{})", self.name, self.count),
+            _ => self.name.clone(),
+        }
+    }
+
+    /// The name, with any brand and enchantment, marked as cursed once the
+    /// curse is known.
+    pub fn display_name(&self) -> String {
+        let equipment = match self.equipment {
+            Some(equipment) => equipment,
+            None => return self.name.clone(),
+        };
+        // Artifacts are known by their own names.
+        let name = match equipment.brand {
+            Some(brand) if equipment.artifact.is_none() => format!("{} {}", brand, self.name),
+            _ => self.name.clone(),
+        };
+        let name = if equipment.enchantment != 0 {
+            format!("{:+} {}", equipment.enchantment, name)
+        } else {
+            name
+        };
+        if equipment.cursed && equipment.curse_known {
+            format!("cursed {}", name)
+        } else {
+            name
+        }
+    }
+
+    /// Equip the object, if it's equipment. Freeing up the slot is up to the caller.
+    pub fn equip(&mut self, messages: &mut Messages) {
+        if let Some(ref mut equipment) = self.equipment {
+            if !equipment.equipped {
+                equipment.equipped = true;
+                messages.message(format!("Equipped {} on {}.", self.name, equipment.slot), colors::LIGHT_GREEN);
+                if let Some(artifact) = equipment.artifact {
+                    messages.message(format!("The {} {}.", self.name, artifact.description()), colors::ORANGE);
+                }
+                if equipment.cursed && !equipment.curse_known {
+                    equipment.curse_known = true;
+                    messages.message_kind(format!("The {} tightens its grip on you. It's cursed!", self.name),
+                                          colors::RED, MessageKind::Warning);
+                }
+            }
+        }
+    }
+
+    /// Take the object off, if it's equipped and not cursed. Returns whether
+    /// it's off.
+    pub fn unequip(&mut self, messages: &mut Messages) -> bool {
+        if let Some(ref mut equipment) = self.equipment {
+            if equipment.equipped {
+                if equipment.cursed {
+                    equipment.curse_known = true;
+                    messages.message_kind(format!("You can't take off the {}, it's cursed!", self.name),
+                                          colors::RED, MessageKind::Warning);
+                    return false;
+                }
+                equipment.equipped = false;
+                messages.message(format!("Unequipped {} from {}.", self.name, equipment.slot), colors::LIGHT_YELLOW);
+            }
+        }
+        true
+    }
+
     /// Return the distance to another object.
     pub fn distance_to(&self, other: &Object) -> f32 {
         let dx = other.x - self.x;
@@ -58,12 +186,14 @@ impl Object {
         (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
     }
 
-    /// Heal by the given amount, without going over the maximum.
-    pub fn heal(&mut self, amount: i32) {
+    /// Heal by the given amount, without going over the maximum with the
+    /// given equipment.
+    pub fn heal(&mut self, amount: i32, equipped: &[Equipment]) {
         if let Some(ref mut fighter) = self.fighter {
+            let max_hp = fighter.max_hp(equipped);
             fighter.hp += amount;
-            if fighter.hp > fighter.max_hp {
-                fighter.hp = fighter.max_hp;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
             }
         }
     }
@@ -77,6 +207,11 @@ impl Object {
             }
         }
 
+        // Hurting a shopkeeper is the end of any deal.
+        if damage > 0 {
+            self.anger(messages);
+        }
+
         // Check for death, call the death function.
         if let Some(fighter) = self.fighter {
             if fighter.hp <= 0 {
@@ -85,21 +220,148 @@ impl Object {
                 return Some(fighter.xp);
             }
         }
+        // Anyone who lives through it wakes up.
+        if damage > 0 {
+            self.wake(messages);
+        }
         None
     }
 
-    pub fn attack(&mut self, target: &mut Object, messages: &mut Messages) {
+    pub fn is_asleep(&self) -> bool {
+        match self.ai {
+            Some(Ai::Sleeping { .. }) => true,
+            _ => false,
+        }
+    }
+
+    /// Put a monster to sleep, to go on with what it was doing once it wakes.
+    pub fn fall_asleep(&mut self) {
+        if let Some(ai) = self.ai.take() {
+            self.ai = Some(Ai::Sleeping {
+                previous_ai: Box::new(ai),
+            });
+        }
+    }
+
+    /// Wake a sleeping monster up.
+    pub fn wake(&mut self, messages: &mut Messages) {
+        if self.wake_silently() {
+            messages.message_kind(format!("The {} wakes up!", self.name), colors::ORANGE, MessageKind::Warning);
+        }
+    }
+
+    /// Wake a sleeping monster up, but only tell the player about it if they
+    /// can see it.
+    pub fn wake_if_seen(&mut self, fov_map: &FovMap, messages: &mut Messages) {
+        if fov_map.is_in_fov(self.x, self.y) {
+            self.wake(messages);
+        } else {
+            self.wake_silently();
+        }
+    }
+
+    /// Wake a sleeping monster up without a word. Returns whether it was
+    /// asleep.
+    fn wake_silently(&mut self) -> bool {
+        match self.ai.take() {
+            Some(Ai::Sleeping { previous_ai }) => {
+                self.ai = Some(*previous_ai);
+                true
+            }
+            ai => {
+                self.ai = ai;
+                false
+            }
+        }
+    }
+
+    /// Turn a peaceful monster against the player.
+    pub fn anger(&mut self, messages: &mut Messages) {
+        if self.ai.as_ref().map_or(false, |ai| !ai.is_hostile()) {
+            self.ai = Some(Ai::Basic);
+            messages.message_kind(format!("The {} turns hostile!", self.name), colors::RED, MessageKind::Warning);
+        }
+    }
+
+    /// Attack another object, each side getting the bonuses of its equipment.
+    /// Branded weapons add their elemental damage, whatever the defense, unless
+    /// the target resists it. A sleeping target is hit all the harder, and
+    /// woken up either way.
+    pub fn attack(&mut self, target: &mut Object, equipped: &[Equipment], target_equipped: &[Equipment],
+                  messages: &mut Messages) {
         // A simple formula for attack damage.
-        let damage = self.fighter.map_or(0, |f| f.power) - target.fighter.map_or(0, |f| f.defense);
-        if damage > 0 {
+        let mut damage = cmp::max(0, self.fighter.map_or(0, |f| f.power(equipped)) -
+            target.fighter.map_or(0, |f| f.defense(target_equipped)));
+        if target.is_asleep() {
+            messages.message_kind(format!("{} catches {} asleep!", self.name, target.name), colors::LIGHT_AZURE,
+                                  MessageKind::Combat);
+            damage *= SNEAK_ATTACK_MULTIPLIER;
+        }
+        let (resisted, brands): (Vec<Brand>, Vec<Brand>) = equipped.iter()
+            .filter_map(|e| e.brand)
+            .partition(|&b| target_equipped.iter().any(|e| e.resistance == Some(b)));
+        let total = damage + brands.iter().map(|b| b.damage()).sum::<i32>() +
+            resisted.iter().map(|b| b.damage() / 2).sum::<i32>();
+        if total > 0 {
             // Make the target take some damage.
-            messages.message(format!("{} attacks {} for {} hit points.", self.name, target.name, damage), colors::WHITE);
-            if let Some(xp) = target.take_damage(damage, messages) {
+            let elements: String = brands.iter()
+                .map(|b| format!(", {} {}", b.damage(), b.element()))
+                .chain(resisted.iter().map(|b| format!(", {} {} (resisted)", b.damage() / 2, b.element())))
+                .collect();
+            messages.message_kind(format!("{} attacks {} for {} hit points{}.", self.name, target.name, total,
+                                          elements),
+                                  colors::WHITE, MessageKind::Combat);
+            let killed = target.take_damage(total, messages);
+            target.on_damaged(self, total, target_equipped, messages);
+            if let Some(xp) = killed {
                 // Yield experience to the player.
                 self.fighter.as_mut().unwrap().xp += xp;
+            } else {
+                for &brand in &brands {
+                    target.apply_brand(brand, messages);
+                }
             }
         } else {
-            messages.message(format!("{} attacks {} but it has no effect!", self.name, target.name), colors::WHITE);
+            messages.message_kind(format!("{} attacks {} but it has no effect!", self.name, target.name),
+                                  colors::WHITE, MessageKind::Combat);
+            target.wake(messages);
+        }
+    }
+
+    /// React to being hit in melee, with the equipment it has on. Thorns hurt
+    /// the attacker for a share of the damage.
+    fn on_damaged(&mut self, attacker: &mut Object, damage: i32, equipped: &[Equipment], messages: &mut Messages) {
+        let thorns = equipped.iter().map(|e| e.thorns).sum::<i32>() * damage / 100;
+        if thorns > 0 && attacker.alive {
+            messages.message_kind(format!("{} is pricked by {}'s thorns for {} hit points.", attacker.name,
+                                          self.name, thorns),
+                                  colors::LIGHT_GREEN, MessageKind::Combat);
+            if let Some(xp) = attacker.take_damage(thorns, messages) {
+                if let Some(fighter) = self.fighter.as_mut() {
+                    fighter.xp += xp;
+                }
+            }
+        }
+    }
+
+    /// Suffer the lasting effect of a brand that hit.
+    fn apply_brand(&mut self, brand: Brand, messages: &mut Messages) {
+        match brand {
+            Brand::Flaming => {
+                if self.burning_turns == 0 {
+                    messages.message_kind(format!("{} catches fire!", self.name), colors::ORANGE,
+                                          MessageKind::Combat);
+                }
+                self.burning_turns = cmp::max(self.burning_turns, BURN_TURNS);
+            }
+            Brand::Freezing => {
+                if self.slowed_turns == 0 {
+                    messages.message_kind(format!("{} is slowed by the cold!", self.name), colors::LIGHT_BLUE,
+                                          MessageKind::Combat);
+                }
+                self.slowed_turns = cmp::max(self.slowed_turns, SLOW_TURNS);
+            }
+            Brand::Shocking => {}
         }
     }
 
@@ -132,7 +394,7 @@ impl DeathCallback {
 
 fn player_death(player: &mut Object, messages: &mut Messages) {
     // The game ended!
-    messages.message("You died!", colors::RED);
+    messages.message_kind("You died!", colors::RED, MessageKind::Critical);
 
     // for added effect, transform the player into a corpse!
     player.char = '%';
@@ -142,9 +404,10 @@ fn player_death(player: &mut Object, messages: &mut Messages) {
 fn monster_death(monster: &mut Object, messages: &mut Messages) {
     // Transform it into a nasty corpse! It doesn't block, can't be
     // attacked and doesn't move.
-    messages.message(
+    messages.message_kind(
         format!("{} is dead! You gain {} experience points.", monster.name, monster.fighter.unwrap().xp),
         colors::ORANGE,
+        MessageKind::Combat,
     );
     monster.char = '%';
     monster.color = colors::DARK_RED;
@@ -157,27 +420,330 @@ fn monster_death(monster: &mut Object, messages: &mut Messages) {
 // Combat-related properties and methods (monster, player, NPC).
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Fighter {
+    /// Base max HP, before equipment bonuses. The same goes for defense and power.
     pub max_hp: i32,
     pub hp: i32,
     pub defense: i32,
     pub power: i32,
     pub xp: i32,
+    /// Below this percentage of its max HP, a monster runs away. 0 never flees.
+    pub flee_below: i32,
     pub on_death: DeathCallback,
 }
 
+impl Fighter {
+    pub fn max_hp(&self, equipped: &[Equipment]) -> i32 {
+        self.max_hp + equipped.iter().map(|e| e.max_hp_bonus).sum::<i32>()
+    }
+
+    pub fn defense(&self, equipped: &[Equipment]) -> i32 {
+        self.defense + equipped.iter().map(|e| e.defense()).sum::<i32>()
+    }
+
+    pub fn power(&self, equipped: &[Equipment]) -> i32 {
+        self.power + equipped.iter().map(|e| e.power()).sum::<i32>()
+    }
+
+    /// Whether it's hurt badly enough to run away.
+    pub fn is_afraid(&self) -> bool {
+        self.hp * 100 < self.max_hp * self.flee_below
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Ai {
     Basic,
+    /// Heads for where the player was last seen, giving up after some turns.
+    Chasing {
+        last_seen: (i32, i32),
+        num_turns: i32,
+    },
     Confused {
         previous_ai: Box<Ai>,
         num_turns: i32,
     },
+    /// Runs away from the player, fighting back only when cornered, then
+    /// goes back to what it was doing.
+    Fleeing {
+        previous_ai: Box<Ai>,
+        num_turns: i32,
+    },
+    /// Stays put and trades with the player, until hurt.
+    Shopkeeper,
+    /// Stays put and heals the player for gold, until hurt.
+    Healer,
+    /// Guards the stairs, and fights the player in phases as it gets hurt.
+    Boss {
+        phase: usize,
+    },
+    /// Keeps its distance and shoots at the player from afar.
+    Ranged,
+    /// Goes to see what made a noise, giving up after some turns.
+    Investigating {
+        noise: (i32, i32),
+        num_turns: i32,
+    },
+    /// Makes its rounds from one waypoint to the next. It goes after the player
+    /// on sight, and once it loses them, checks where they were last seen
+    /// before going back to its rounds.
+    Patrolling {
+        route: Vec<(i32, i32)>,
+        next: usize,
+        last_seen: Option<(i32, i32)>,
+    },
+    /// Follows the player's scent, wherever it's freshest, with or without
+    /// seeing them.
+    Tracking,
+    /// Does nothing until the player comes close or makes a racket, then
+    /// goes about its business.
+    Sleeping {
+        previous_ai: Box<Ai>,
+    },
+}
+
+impl Ai {
+    /// A short description of what the monster is up to.
+    pub fn describe(&self) -> &'static str {
+        match *self {
+            Ai::Basic => "unaware",
+            Ai::Chasing { .. } => "hunting",
+            Ai::Confused { .. } => "confused",
+            Ai::Fleeing { .. } => "fleeing",
+            Ai::Shopkeeper => "minding the shop",
+            Ai::Healer => "tending the sick",
+            Ai::Boss { phase: 0 } => "guarding the stairs",
+            Ai::Boss { .. } => "fighting for its life",
+            Ai::Ranged => "keeping its distance",
+            Ai::Investigating { .. } => "investigating a noise",
+            Ai::Patrolling { last_seen: Some(_), .. } => "hunting",
+            Ai::Patrolling { .. } => "on patrol",
+            Ai::Tracking => "on the scent",
+            Ai::Sleeping { .. } => "asleep",
+        }
+    }
+
+    /// Whether it means the player any harm.
+    pub fn is_hostile(&self) -> bool {
+        match *self {
+            Ai::Shopkeeper | Ai::Healer => false,
+            Ai::Confused { ref previous_ai, .. } | Ai::Sleeping { ref previous_ai } => previous_ai.is_hostile(),
+            _ => true,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Item {
     Heal,
+    /// A stronger healing potion, only made by crafting.
+    GreaterHeal,
     Lightning,
     Confuse,
     Fireball,
+    Torch,
+    Lantern,
+    /// Refuels the torch or lantern in use.
+    OilFlask,
+    MagicMapping,
+    Sword,
+    Greatsword,
+    Shield,
+    Armor,
+    Boots,
+    Ring,
+    Amulet,
+    RemoveCurse,
+    Dagger,
+    Bow,
+    Sling,
+    Arrow,
+    Stone,
+    WandLightning,
+    WandConfusion,
+    Key,
+    EnchantWeapon,
+    EnchantArmor,
+    /// Opens the locked doors of the dungeon level it was found on.
+    DoorKey(u32),
+    /// Mixes two potions, for better or worse.
+    Mortar,
+    /// Digs through walls when wielded and walked into them.
+    Pickaxe,
+    /// Digs a tunnel in a straight line.
+    Digging,
+    /// A pile of gold pieces, as many as its count.
+    Gold,
+    /// The crown at the bottom of the dungeon. Bringing it back up wins the game.
+    Relic,
+}
+
+impl Item {
+    /// Whether the item can be used (drunk, read, lit...) from the inventory.
+    pub fn is_usable(self) -> bool {
+        match self {
+            Item::Key | Item::DoorKey(_) | Item::Gold | Item::Relic => false,
+            _ => true,
+        }
+    }
+
+    /// Whether several of this item share a single inventory slot.
+    pub fn is_stackable(self) -> bool {
+        match self {
+            Item::Heal | Item::GreaterHeal | Item::Lightning | Item::Confuse | Item::Fireball | Item::OilFlask |
+            Item::MagicMapping | Item::RemoveCurse | Item::EnchantWeapon | Item::EnchantArmor | Item::Arrow |
+            Item::Stone | Item::Key | Item::Gold | Item::Digging => true,
+            Item::Dagger | Item::Sword | Item::Greatsword | Item::Shield | Item::Armor | Item::Boots |
+            Item::Ring | Item::Amulet | Item::Bow | Item::Sling | Item::WandLightning | Item::Torch |
+            Item::Lantern | Item::WandConfusion | Item::DoorKey(_) | Item::Mortar | Item::Pickaxe |
+            Item::Relic => false,
+        }
+    }
+
+    /// What a shop sells the item for, in gold. It buys items back for half.
+    pub fn value(self) -> u32 {
+        match self {
+            Item::Heal => 20,
+            Item::GreaterHeal => 50,
+            Item::Lightning => 40,
+            Item::Confuse => 30,
+            Item::Fireball => 60,
+            Item::Torch => 5,
+            Item::Lantern => 25,
+            Item::OilFlask => 10,
+            Item::MagicMapping => 50,
+            Item::Sword => 60,
+            Item::Greatsword => 100,
+            Item::Shield => 80,
+            Item::Armor => 70,
+            Item::Boots => 30,
+            Item::Ring => 120,
+            Item::Amulet => 120,
+            Item::RemoveCurse => 40,
+            Item::Dagger => 15,
+            Item::Bow => 50,
+            Item::Sling => 20,
+            Item::Arrow => 2,
+            Item::Stone => 1,
+            Item::WandLightning => 80,
+            Item::WandConfusion => 60,
+            Item::Key => 25,
+            Item::EnchantWeapon => 60,
+            Item::EnchantArmor => 60,
+            Item::DoorKey(_) => 0,
+            Item::Mortar => 30,
+            Item::Pickaxe => 40,
+            Item::Digging => 50,
+            Item::Gold => 1,
+            Item::Relic => 0,
+        }
+    }
+
+    /// Whether the item can be thrown, applying its effect where it lands.
+    pub fn is_throwable(self) -> bool {
+        match self {
+            Item::Heal | Item::Dagger => true,
+            Item::GreaterHeal | Item::Lightning | Item::Confuse | Item::Fireball | Item::Torch | Item::Lantern |
+            Item::OilFlask | Item::MagicMapping | Item::RemoveCurse | Item::Sword | Item::Greatsword | Item::Shield |
+            Item::Armor | Item::Boots | Item::Ring | Item::Amulet | Item::Bow | Item::Sling | Item::Arrow |
+            Item::Stone | Item::WandLightning | Item::WandConfusion | Item::Key | Item::EnchantWeapon |
+            Item::EnchantArmor | Item::DoorKey(_) | Item::Mortar | Item::Pickaxe | Item::Digging |
+            Item::Gold | Item::Relic => false,
+        }
+    }
+
+    /// How far it lights up around the player while equipped, if it's a light.
+    pub fn light_radius(self) -> Option<i32> {
+        match self {
+            Item::Torch => Some(8),
+            Item::Lantern => Some(11),
+            _ => None,
+        }
+    }
+
+    /// The most fuel a light holds, in turns.
+    pub fn max_fuel(self) -> u32 {
+        match self {
+            Item::Torch => 400,
+            Item::Lantern => 800,
+            _ => 0,
+        }
+    }
+
+    /// Whether it's a potion, that can be mixed with a mortar.
+    pub fn is_potion(self) -> bool {
+        self == Item::Heal || self == Item::GreaterHeal
+    }
+
+    /// Whether it's a scroll, read once.
+    pub fn is_scroll(self) -> bool {
+        match self {
+            Item::Lightning | Item::Confuse | Item::Fireball | Item::MagicMapping | Item::RemoveCurse |
+            Item::EnchantWeapon | Item::EnchantArmor | Item::Digging => true,
+            _ => false,
+        }
+    }
+
+    /// Whether it can break when a chest holding it is bashed open.
+    pub fn is_fragile(self) -> bool {
+        self.is_potion()
+    }
+
+    /// Whether it's a wand, used up a charge at a time.
+    pub fn is_wand(self) -> bool {
+        match self {
+            Item::WandLightning | Item::WandConfusion => true,
+            _ => false,
+        }
+    }
+
+    /// The ammunition a launcher shoots.
+    pub fn ammo(self) -> Option<Item> {
+        match self {
+            Item::Bow => Some(Item::Arrow),
+            Item::Sling => Some(Item::Stone),
+            _ => None,
+        }
+    }
+
+    /// Damage dealt by a shot from a launcher, before the target's defense.
+    pub fn ranged_damage(self) -> i32 {
+        match self {
+            Item::Bow => 8,
+            Item::Sling => 5,
+            _ => 0,
+        }
+    }
+
+    /// Whether the item drops where it's thrown, instead of breaking.
+    pub fn lands_when_thrown(self) -> bool {
+        self == Item::Dagger
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapKind {
+    BearTrap,
+    Dart,
+    Teleport,
+    Confusion,
+    /// Rings a bell that sets the monsters around on the player.
+    Alarm,
+    /// Hurts the player, and keeps them slowed while they climb out.
+    Pit,
+}
+
+/// A container on the map, holding its items in `carried`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chest {
+    /// A locked chest needs a key, or a good bash, to open.
+    pub locked: bool,
+    /// The player's stash in town, which they can put items in, too.
+    pub stash: bool,
+}
+
+/// A hidden hazard, sprung by stepping on it until it's been found.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub revealed: bool,
 }
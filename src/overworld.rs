@@ -0,0 +1,138 @@
+use std::f32::consts::PI;
+
+use rand::{Rng, SeedableRng, StdRng};
+use tcod::colors::{self, Color};
+
+use branch::{Branch, Location};
+
+/// Size of the wilderness, in tiles of the overworld screen.
+pub const WIDTH: i32 = 48;
+pub const HEIGHT: i32 = 18;
+// Mixed into the run seed, so that the wilderness doesn't follow the levels.
+const SITES_SEED: usize = 0x517E;
+const LAND_SEED: usize = 0x1A4D;
+// Percent of the land that's forest, and hills.
+const FOREST_CHANCE: u32 = 20;
+const HILLS_CHANCE: u32 = 8;
+const LAKES: u32 = 3;
+const LAKE_MAX_RADIUS: i32 = 3;
+// How far from the edge of the wilderness the furthest dungeon lies.
+const SITE_MARGIN: i32 = 2;
+
+/// What the land of a tile of the wilderness is like.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Land {
+    Plains,
+    Forest,
+    Hills,
+    Water,
+}
+
+impl Land {
+    pub fn glyph(self) -> (char, Color) {
+        match self {
+            Land::Plains => ('.', colors::DARK_GREEN),
+            Land::Forest => ('"', colors::DARKER_GREEN),
+            Land::Hills => ('^', colors::DARK_SEPIA),
+            Land::Water => ('~', colors::DARK_AZURE),
+        }
+    }
+}
+
+/// A place in the wilderness: the town, or the way into one of the dungeons
+/// out there.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Site {
+    /// The town, or the first level of the dungeon.
+    pub location: Location,
+    pub pos: (i32, i32),
+    /// Whether the player has been there.
+    pub visited: bool,
+    /// Whether the player has made it to the bottom of its dungeon.
+    pub cleared: bool,
+}
+
+/// The wilderness around the town, and how far the player got in each of the
+/// dungeons out there.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Overworld {
+    pub sites: Vec<Site>,
+}
+
+impl Overworld {
+    /// Lay out the wilderness for a run: the town in the middle, and the
+    /// dungeons all around it. The harder a dungeon, the further out it lies.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = overworld_rng(seed, SITES_SEED);
+        let town = Site {
+            location: Location::town(),
+            pos: (WIDTH / 2, HEIGHT / 2),
+            visited: true,
+            cleared: false,
+        };
+        let branches: Vec<Branch> = Branch::ALL.iter().cloned().filter(|branch| branch.in_wilderness()).collect();
+        let start_angle = rng.gen::<f32>() * 2.0 * PI;
+        let mut sites = vec![town];
+        for (i, &branch) in branches.iter().enumerate() {
+            let angle = start_angle + 2.0 * PI * i as f32 / branches.len() as f32;
+            let distance = (i + 1) as f32 / branches.len() as f32;
+            let x = WIDTH / 2 + (angle.cos() * distance * (WIDTH / 2 - SITE_MARGIN) as f32) as i32;
+            let y = HEIGHT / 2 + (angle.sin() * distance * (HEIGHT / 2 - SITE_MARGIN) as f32) as i32;
+            sites.push(Site {
+                location: Location { branch, depth: 1 },
+                pos: (x, y),
+                visited: false,
+                cleared: false,
+            });
+        }
+        Overworld { sites }
+    }
+
+    /// The land of the wilderness, by column, the same every time for a run.
+    pub fn land(seed: u64) -> Vec<Vec<Land>> {
+        let mut rng = overworld_rng(seed, LAND_SEED);
+        let mut land = vec![vec![Land::Plains; HEIGHT as usize]; WIDTH as usize];
+        for column in &mut land {
+            for tile in column.iter_mut() {
+                let roll = rng.gen_range(0, 100);
+                if roll < FOREST_CHANCE {
+                    *tile = Land::Forest;
+                } else if roll < FOREST_CHANCE + HILLS_CHANCE {
+                    *tile = Land::Hills;
+                }
+            }
+        }
+        for _ in 0..LAKES {
+            let (lake_x, lake_y) = (rng.gen_range(0, WIDTH), rng.gen_range(0, HEIGHT));
+            let radius = rng.gen_range(1, LAKE_MAX_RADIUS + 1);
+            for x in clamp(lake_x - radius, WIDTH)..clamp(lake_x + radius + 1, WIDTH) {
+                for y in clamp(lake_y - radius, HEIGHT)..clamp(lake_y + radius + 1, HEIGHT) {
+                    if (x - lake_x).pow(2) + (y - lake_y).pow(2) <= radius.pow(2) {
+                        land[x as usize][y as usize] = Land::Water;
+                    }
+                }
+            }
+        }
+        land
+    }
+
+    /// Keep track of the player arriving somewhere: they've now been to the
+    /// dungeon it's in, and reaching its bottom clears it.
+    pub fn arrive(&mut self, location: Location) {
+        let sites = self.sites.iter_mut()
+            .filter(|site| site.location.branch == location.branch && !site.location.is_town());
+        for site in sites {
+            site.visited = true;
+            site.cleared |= location.is_bottom();
+        }
+    }
+}
+
+/// Keep a coordinate inside the wilderness, from 0 up to its size.
+fn clamp(value: i32, size: i32) -> i32 {
+    value.max(0).min(size)
+}
+
+fn overworld_rng(seed: u64, salt: usize) -> StdRng {
+    StdRng::from_seed(&[seed as usize, (seed >> 32) as usize, salt])
+}
@@ -0,0 +1,32 @@
+use tcod::colors::{self, Color};
+
+/// How rare an item is. Rarer equipment has bigger bonuses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Legendary,
+}
+
+impl Rarity {
+    /// The color items of this rarity are shown in.
+    pub fn color(self) -> Color {
+        match self {
+            Rarity::Common => colors::WHITE,
+            Rarity::Uncommon => colors::LIGHT_GREEN,
+            Rarity::Rare => colors::LIGHT_BLUE,
+            Rarity::Legendary => colors::ORANGE,
+        }
+    }
+
+    /// Equipment bonuses at this rarity, in percent of the common ones.
+    pub fn bonus_percent(self) -> i32 {
+        match self {
+            Rarity::Common => 100,
+            Rarity::Uncommon => 150,
+            Rarity::Rare => 200,
+            Rarity::Legendary => 300,
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::cmp;
+
+use rand::{Rng, StdRng};
+
+use map::{self, Map, Rect, Tile};
+
+// Percent of the map to dig out.
+const FLOOR_PERCENT: i32 = 35;
+// Steps each walker takes before giving up.
+const WALK_LENGTH: u32 = 400;
+
+/// Dig out winding, chaotic passages by letting walkers stumble around at
+/// random, each one starting somewhere already dug, until enough of the map
+/// is open. Everything dug is connected that way.
+///
+/// Like caves, ruins have no rooms, so this returns areas to put things in.
+pub fn make_ruins(map: &mut Map, rng: &mut StdRng) -> Vec<Rect> {
+    let target = ((map.width - 2) * (map.height - 2) * FLOOR_PERCENT / 100) as usize;
+    let center = (map.width / 2, map.height / 2);
+    map[center] = Tile::empty();
+    let mut dug = vec![center];
+
+    while dug.len() < target {
+        let (mut x, mut y) = *rng.choose(&dug).unwrap();
+        for _ in 0..WALK_LENGTH {
+            match rng.gen_range(0, 4) {
+                0 => x += 1,
+                1 => x -= 1,
+                2 => y += 1,
+                _ => y -= 1,
+            }
+            // Keep the outer edge of the map solid.
+            x = cmp::max(1, cmp::min(map.width - 2, x));
+            y = cmp::max(1, cmp::min(map.height - 2, y));
+            if map[(x, y)].blocked {
+                map[(x, y)] = Tile::empty();
+                dug.push((x, y));
+            }
+        }
+    }
+
+    let mut areas = map::spread_areas(map, rng);
+    map::pick_start_and_stairs(&mut areas, &|_| true);
+    areas
+}
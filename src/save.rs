@@ -0,0 +1,959 @@
+use std::error::Error;
+
+use json::{self, Map, Value};
+use rand;
+
+use branch::Location;
+use map;
+use object::Item;
+use overworld::Overworld;
+use theme::{Theme, ThemeKind};
+use town;
+use INVENTORY_SIZE;
+
+/// Version of the save format written by this build. Bump it, and add a step
+/// to `migrate`, whenever a change to `GameState` would break older saves.
+pub const VERSION: u32 = 45;
+
+/// Upgrade save data from any older version to the current format, in place.
+pub fn migrate(save: &mut Value) -> Result<(), Box<Error>> {
+    // Saves from before versioning have no version field.
+    let version = save.get("version").and_then(Value::as_u64).unwrap_or(1) as u32;
+    if version > VERSION {
+        return Err(format!("The save is from a newer version of the game \
+                            (format {}, this one reads up to {}).", version, VERSION).into());
+    }
+
+    if version < 2 {
+        v1_to_v2(save)?;
+    }
+    if version < 3 {
+        v2_to_v3(save)?;
+    }
+    if version < 4 {
+        v3_to_v4(save)?;
+    }
+    if version < 5 {
+        v4_to_v5(save)?;
+    }
+    if version < 6 {
+        v5_to_v6(save)?;
+    }
+    if version < 7 {
+        v6_to_v7(save)?;
+    }
+    if version < 8 {
+        v7_to_v8(save)?;
+    }
+    if version < 9 {
+        v8_to_v9(save)?;
+    }
+    if version < 10 {
+        v9_to_v10(save)?;
+    }
+    if version < 11 {
+        v10_to_v11(save)?;
+    }
+    if version < 12 {
+        v11_to_v12(save)?;
+    }
+    if version < 13 {
+        v12_to_v13(save)?;
+    }
+    if version < 14 {
+        v13_to_v14(save)?;
+    }
+    if version < 15 {
+        v14_to_v15(save)?;
+    }
+    if version < 16 {
+        v15_to_v16(save)?;
+    }
+    if version < 17 {
+        v16_to_v17(save)?;
+    }
+    if version < 18 {
+        v17_to_v18(save)?;
+    }
+    if version < 19 {
+        v18_to_v19(save)?;
+    }
+    if version < 20 {
+        v19_to_v20(save)?;
+    }
+    if version < 21 {
+        v20_to_v21(save)?;
+    }
+    if version < 22 {
+        v21_to_v22(save)?;
+    }
+    if version < 23 {
+        v22_to_v23(save)?;
+    }
+    if version < 24 {
+        v23_to_v24(save)?;
+    }
+    if version < 25 {
+        v24_to_v25(save)?;
+    }
+    if version < 26 {
+        v25_to_v26(save)?;
+    }
+    if version < 27 {
+        v26_to_v27(save)?;
+    }
+    if version < 28 {
+        v27_to_v28(save)?;
+    }
+    if version < 29 {
+        v28_to_v29(save)?;
+    }
+    if version < 30 {
+        v29_to_v30(save)?;
+    }
+    if version < 31 {
+        v30_to_v31(save)?;
+    }
+    if version < 32 {
+        v31_to_v32(save)?;
+    }
+    if version < 33 {
+        v32_to_v33(save)?;
+    }
+    if version < 34 {
+        v33_to_v34(save)?;
+    }
+    if version < 35 {
+        v34_to_v35(save)?;
+    }
+    if version < 36 {
+        v35_to_v36(save)?;
+    }
+    if version < 37 {
+        v36_to_v37(save)?;
+    }
+    if version < 38 {
+        v37_to_v38(save)?;
+    }
+    if version < 39 {
+        v38_to_v39(save)?;
+    }
+    if version < 40 {
+        v39_to_v40(save)?;
+    }
+    if version < 41 {
+        v40_to_v41(save)?;
+    }
+    if version < 42 {
+        v41_to_v42(save)?;
+    }
+    if version < 43 {
+        v42_to_v43(save)?;
+    }
+    if version < 44 {
+        v43_to_v44(save)?;
+    }
+    if version < 45 {
+        v44_to_v45(save)?;
+    }
+
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("version".into(), VERSION.into());
+    Ok(())
+}
+
+/// Version 2 added the turn counter, the sight radius and lantern, level
+/// themes, traps and item stacks.
+fn v1_to_v2(save: &mut Value) -> Result<(), Box<Error>> {
+    let level = save.get("dungeon_level").and_then(Value::as_u64).unwrap_or(1) as u32;
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+
+    state.insert("turn".into(), 0.into());
+    state.insert("torch_radius".into(), 10.into());
+    state.insert("lantern_turns".into(), 0.into());
+    state.insert("theme".into(), json::to_value(Theme::for_level(level))?);
+
+    for list in &["objects", "inventory"] {
+        if let Some(objects) = state.get_mut(*list).and_then(Value::as_array_mut) {
+            for object in objects.iter_mut().filter_map(Value::as_object_mut) {
+                object.insert("trap".into(), Value::Null);
+                object.insert("count".into(), 1.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 3 added the player's confusion.
+fn v2_to_v3(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("player_confused_turns".into(), 0.into());
+    Ok(())
+}
+
+/// Version 4 added the HP threshold under which fighters flee. Monsters from
+/// older saves get the usual one, and the player never flees.
+fn v3_to_v4(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    if let Some(objects) = state.get_mut("objects").and_then(Value::as_array_mut) {
+        for object in objects.iter_mut() {
+            if let Some(fighter) = object.get_mut("fighter").and_then(Value::as_object_mut) {
+                let is_player = fighter.get("on_death").and_then(Value::as_str) == Some("Player");
+                fighter.insert("flee_below".into(), if is_player { 0 } else { 25 }.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 5 tagged messages with their kind. Old messages are all info.
+fn v4_to_v5(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    if let Some(messages) = state.get_mut("messages").and_then(Value::as_array_mut) {
+        for message in messages.iter_mut().filter_map(Value::as_array_mut) {
+            message.push("Info".into());
+        }
+    }
+    Ok(())
+}
+
+/// Version 6 added equipment.
+fn v5_to_v6(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    for list in &["objects", "inventory"] {
+        if let Some(objects) = state.get_mut(*list).and_then(Value::as_array_mut) {
+            for object in objects.iter_mut().filter_map(Value::as_object_mut) {
+                object.insert("equipment".into(), Value::Null);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 7 let monsters carry items.
+fn v6_to_v7(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    for list in &["objects", "inventory"] {
+        if let Some(objects) = state.get_mut(*list).and_then(Value::as_array_mut) {
+            for object in objects.iter_mut().filter_map(Value::as_object_mut) {
+                object.insert("collects_items".into(), false.into());
+                object.insert("carried".into(), Value::Array(Vec::new()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 8 gave equipment stat bonuses. Older equipment gets the bonuses
+/// new items of the same kind have.
+fn v7_to_v8(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_equipment(save, &mut |object, equipment| {
+        let (power, defense) = match object.get("item").and_then(Value::as_str) {
+            Some("Sword") => (3, 0),
+            Some("Greatsword") => (6, 0),
+            Some("Shield") => (0, 2),
+            _ => (0, 0),
+        };
+        equipment.insert("power_bonus".into(), power.into());
+        equipment.insert("defense_bonus".into(), defense.into());
+        equipment.insert("max_hp_bonus".into(), 0.into());
+    })
+}
+
+/// Version 9 added the player's gold.
+fn v8_to_v9(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("gold".into(), 0.into());
+    Ok(())
+}
+
+/// Version 10 added cursed equipment. Nothing was cursed before.
+fn v9_to_v10(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_equipment(save, &mut |_, equipment| {
+        equipment.insert("cursed".into(), false.into());
+        equipment.insert("curse_known".into(), false.into());
+    })
+}
+
+/// Version 11 added wand charges. Nothing had any before.
+fn v10_to_v11(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        object.insert("charges".into(), 0.into());
+    })
+}
+
+/// Version 12 added chests.
+fn v11_to_v12(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        object.insert("chest".into(), Value::Null);
+    })
+}
+
+/// Version 13 added enchanted equipment.
+fn v12_to_v13(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_equipment(save, &mut |_, equipment| {
+        equipment.insert("enchantment".into(), 0.into());
+    })
+}
+
+/// Version 14 added item rarity. Everything before was common.
+fn v13_to_v14(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        object.insert("rarity".into(), "Common".into());
+    })
+}
+
+/// Version 15 added locked doors.
+fn v14_to_v15(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    if let Some(columns) = state.get_mut("map").and_then(Value::as_array_mut) {
+        for column in columns.iter_mut().filter_map(Value::as_array_mut) {
+            for tile in column.iter_mut().filter_map(Value::as_object_mut) {
+                tile.insert("lock".into(), Value::Null);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 16 added weapon brands, and the burning and slowing they cause.
+fn v15_to_v16(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        object.insert("burning_turns".into(), 0.into());
+        object.insert("slowed_turns".into(), 0.into());
+    })?;
+    for_each_equipment(save, &mut |_, equipment| {
+        equipment.insert("brand".into(), Value::Null);
+    })
+}
+
+/// Version 17 added artifacts. None were generated before.
+fn v16_to_v17(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_equipment(save, &mut |_, equipment| {
+        equipment.insert("artifact".into(), Value::Null);
+    })?;
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("artifacts".into(), Value::Array(Vec::new()));
+    Ok(())
+}
+
+/// Version 18 added resistances, from equipment affixes.
+fn v17_to_v18(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_equipment(save, &mut |_, equipment| {
+        equipment.insert("resistance".into(), Value::Null);
+    })
+}
+
+/// Version 19 added potion mixing, with its own seed for each run.
+fn v18_to_v19(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("alchemy_seed".into(), rand::random::<u32>().into());
+    state.insert("known_mixtures".into(), Value::Array(Vec::new()));
+    Ok(())
+}
+
+/// Version 20 added hotkeys. Nothing was bound yet.
+fn v19_to_v20(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("hotkeys".into(), Value::Array(vec![Value::Null; 9]));
+    Ok(())
+}
+
+/// Version 21 added the quiver, making ammo equipment.
+fn v20_to_v21(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        let is_ammo = match object.get("item").and_then(Value::as_str) {
+            Some("Arrow") | Some("Stone") => true,
+            _ => false,
+        };
+        if is_ammo {
+            let mut equipment = Map::new();
+            equipment.insert("slot".into(), "Quiver".into());
+            for field in &["two_handed", "equipped", "cursed", "curse_known"] {
+                equipment.insert((*field).into(), false.into());
+            }
+            for field in &["power_bonus", "defense_bonus", "max_hp_bonus", "enchantment"] {
+                equipment.insert((*field).into(), 0.into());
+            }
+            for field in &["brand", "resistance", "artifact"] {
+                equipment.insert((*field).into(), Value::Null);
+            }
+            object.insert("equipment".into(), Value::Object(equipment));
+        }
+    })
+}
+
+/// Version 22 made torches and lanterns equipment that burns fuel, in place
+/// of the fixed sight radius and lit lantern. Old lanterns come full, and the
+/// player gets a torch, lit if their left hand is free. A full pack leaves it
+/// on the floor under them.
+fn v21_to_v22(save: &mut Value) -> Result<(), Box<Error>> {
+    let lantern = json::to_value(map::make_item(Item::Lantern, 0, 0, 1, &mut rand::thread_rng()))?;
+    for_each_object(save, &mut |object| {
+        if object.get("item").and_then(Value::as_str) == Some("Lantern") {
+            object.insert("equipment".into(), lantern["equipment"].clone());
+            object.insert("fuel".into(), lantern["fuel"].clone());
+            object.insert("count".into(), 1.into());
+        } else {
+            object.insert("fuel".into(), 0.into());
+        }
+    })?;
+
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    let torch_radius = state.remove("torch_radius").and_then(|r| r.as_i64()).unwrap_or(10);
+    state.insert("perception".into(), (torch_radius - 10).into());
+    state.remove("lantern_turns");
+
+    let inventory = state.get("inventory").and_then(Value::as_array).ok_or("The save has no inventory.")?;
+    let hand_full = inventory.iter().filter_map(|item| item.get("equipment")).any(|equipment| {
+        equipment["equipped"] == true && (equipment["slot"] == "LeftHand" || equipment["two_handed"] == true)
+    });
+    let pack_full = inventory.len() >= INVENTORY_SIZE;
+
+    let mut torch = map::make_item(Item::Torch, 0, 0, 1, &mut rand::thread_rng());
+    if pack_full {
+        let objects = state.get_mut("objects").and_then(Value::as_array_mut).ok_or("The save has no objects.")?;
+        let (x, y) = {
+            let player = objects.first().ok_or("The save has no player.")?;
+            (player["x"].as_i64().unwrap_or(0), player["y"].as_i64().unwrap_or(0))
+        };
+        torch.set_pos(x as i32, y as i32);
+        objects.push(json::to_value(torch)?);
+    } else {
+        if let Some(ref mut equipment) = torch.equipment {
+            equipment.equipped = !hand_full;
+        }
+        let inventory = state.get_mut("inventory").and_then(Value::as_array_mut)
+            .ok_or("The save has no inventory.")?;
+        inventory.push(json::to_value(torch)?);
+    }
+    Ok(())
+}
+
+/// Version 23 added thorns and reflection, from armor affixes.
+fn v22_to_v23(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_equipment(save, &mut |_, equipment| {
+        equipment.insert("thorns".into(), 0.into());
+        equipment.insert("reflection".into(), false.into());
+    })
+}
+
+/// Version 24 gave themes a kind, which decides what lives on the level.
+/// The old caves and lava caverns became the overgrown and volcanic themes.
+fn v23_to_v24(save: &mut Value) -> Result<(), Box<Error>> {
+    let level = save.get("dungeon_level").and_then(Value::as_u64).unwrap_or(1) as u32;
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    if let Some(theme) = state.get_mut("theme").and_then(Value::as_object_mut) {
+        let kind = match theme.get("name").and_then(Value::as_str) {
+            Some("tombs") => ThemeKind::Tombs,
+            Some("caves") => ThemeKind::Overgrown,
+            Some("crypt") => ThemeKind::Crypt,
+            Some("lava caverns") => ThemeKind::Volcanic,
+            _ => Theme::for_level(level).kind,
+        };
+        theme.insert("kind".into(), json::to_value(kind)?);
+    }
+    Ok(())
+}
+
+/// Version 25 added terrain, like water and lava. Old levels are all ground.
+fn v24_to_v25(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    if let Some(columns) = state.get_mut("map").and_then(Value::as_array_mut) {
+        for column in columns.iter_mut().filter_map(Value::as_array_mut) {
+            for tile in column.iter_mut().filter_map(Value::as_object_mut) {
+                tile.insert("terrain".into(), "Ground".into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 26 added digging. Every wall can be dug through, but for locked
+/// doors and the border of the map.
+fn v25_to_v26(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    if let Some(columns) = state.get_mut("map").and_then(Value::as_array_mut) {
+        let width = columns.len();
+        for (x, column) in columns.iter_mut().enumerate() {
+            if let Some(column) = column.as_array_mut() {
+                let height = column.len();
+                for (y, tile) in column.iter_mut().enumerate() {
+                    if let Some(tile) = tile.as_object_mut() {
+                        let border = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+                        let locked = tile.get("lock").map_or(false, |lock| !lock.is_null());
+                        tile.insert("diggable".into(), (!border && !locked).into());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 27 added the run seed. Old runs get a random one for the levels
+/// still to come.
+fn v26_to_v27(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("seed".into(), rand::random::<u64>().into());
+    Ok(())
+}
+
+/// Version 28 stored the map's tiles in a single list, row by row, in place
+/// of a list of columns.
+fn v27_to_v28(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    let columns = match state.remove("map") {
+        Some(Value::Array(columns)) => columns,
+        _ => return Err("The save has no map.".into()),
+    };
+    let columns: Vec<Vec<Value>> = columns.into_iter()
+        .map(|column| match column {
+            Value::Array(tiles) => tiles,
+            _ => vec![],
+        })
+        .collect();
+    let width = columns.len();
+    let height = columns.first().map_or(0, Vec::len);
+    let mut tiles = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for column in &columns {
+            tiles.push(column.get(y).cloned().ok_or("The map's columns are not all the same height.")?);
+        }
+    }
+
+    let mut map = Map::new();
+    map.insert("width".into(), width.into());
+    map.insert("height".into(), height.into());
+    map.insert("tiles".into(), Value::Array(tiles));
+    state.insert("map".into(), Value::Object(map));
+    Ok(())
+}
+
+/// Version 29 kept the levels the player left, to go back to. Levels left
+/// before that are gone, and get generated again.
+fn v28_to_v29(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("levels".into(), Value::Object(Map::new()));
+    Ok(())
+}
+
+/// Version 30 added dungeon branches. The player's dungeon level became a
+/// location, in a branch, and the levels they left are kept in a list with
+/// their locations.
+fn v29_to_v30(save: &mut Value) -> Result<(), Box<Error>> {
+    fn location(depth: u64) -> Value {
+        let mut location = Map::new();
+        location.insert("branch".into(), "Dungeon".into());
+        location.insert("depth".into(), depth.into());
+        Value::Object(location)
+    }
+
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    let depth = state.remove("dungeon_level").and_then(|depth| depth.as_u64()).unwrap_or(1);
+    state.insert("location".into(), location(depth));
+
+    let mut levels = vec![];
+    if let Some(Value::Object(by_depth)) = state.remove("levels") {
+        for (depth, mut level) in by_depth {
+            let depth = depth.parse::<u64>()?;
+            if let Some(level) = level.as_object_mut() {
+                level.insert("location".into(), location(depth));
+            }
+            levels.push(level);
+        }
+    }
+    state.insert("levels".into(), Value::Array(levels));
+    Ok(())
+}
+
+/// Version 31 added bosses. No monster in an old save is one.
+fn v30_to_v31(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        object.insert("boss".into(), Value::Null);
+    })
+}
+
+/// Version 32 added a way to win, by bringing the relic back up and out of
+/// the first level. Its upstairs lead out of the dungeon, so a first level
+/// made before that gets them: under the player if they're on it, or else on
+/// its stairs down.
+fn v31_to_v32(save: &mut Value) -> Result<(), Box<Error>> {
+    fn is_first_level(location: Option<&Value>) -> bool {
+        location.map_or(false, |location| {
+            location.get("branch").and_then(Value::as_str) == Some("Dungeon") &&
+                location.get("depth").and_then(Value::as_u64) == Some(1)
+        })
+    }
+
+    // The upstairs are just like the stairs down, but for their name and glyph.
+    fn add_upstairs(objects: &mut Vec<Value>, pos: Option<(Value, Value)>) {
+        let stairs = objects.iter()
+            .find(|object| object.get("name").and_then(Value::as_str) == Some("stairs"))
+            .cloned();
+        if let Some(Value::Object(mut upstairs)) = stairs {
+            upstairs.insert("name".into(), "upstairs".into());
+            upstairs.insert("char".into(), "<".into());
+            if let Some((x, y)) = pos {
+                upstairs.insert("x".into(), x);
+                upstairs.insert("y".into(), y);
+            }
+            objects.push(Value::Object(upstairs));
+        }
+    }
+
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("ascending".into(), false.into());
+    if is_first_level(state.get("location")) {
+        if let Some(objects) = state.get_mut("objects").and_then(Value::as_array_mut) {
+            let player_pos = objects.first().and_then(|player| Some((player.get("x")?.clone(),
+                                                                     player.get("y")?.clone())));
+            add_upstairs(objects, player_pos);
+        }
+    }
+    if let Some(levels) = state.get_mut("levels").and_then(Value::as_array_mut) {
+        for level in levels.iter_mut().filter(|level| is_first_level(level.get("location"))) {
+            if let Some(objects) = level.get_mut("objects").and_then(Value::as_array_mut) {
+                add_upstairs(objects, None);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 33 added the town, with the player's stash. No chest in an old
+/// save is the stash.
+fn v32_to_v33(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        if let Some(chest) = object.get_mut("chest").and_then(Value::as_object_mut) {
+            chest.insert("stash".into(), false.into());
+        }
+    })
+}
+
+/// Version 34 added flying monsters. None of those in an old save fly.
+fn v33_to_v34(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        object.insert("flying".into(), false.into());
+    })
+}
+
+/// Version 35 added magical darkness. No tile of an old level is dark.
+fn v34_to_v35(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_tile(save, &mut |tile| {
+        tile.insert("dark".into(), false.into());
+    })
+}
+
+/// Version 36 added level feelings. Old levels have been arrived on already.
+fn v35_to_v36(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_map(save, &mut |map| {
+        map.insert("feeling".into(), Value::Null);
+    })
+}
+
+/// Version 37 added the wilderness around the town, with more dungeons out in
+/// it, and the road out of town that leads there.
+fn v36_to_v37(save: &mut Value) -> Result<(), Box<Error>> {
+    fn is_town(location: Option<&Value>) -> bool {
+        location.map_or(false, |location| {
+            location.get("branch").and_then(Value::as_str) == Some("Dungeon") &&
+                location.get("depth").and_then(Value::as_u64) == Some(0)
+        })
+    }
+
+    // The road goes where the town puts it, going by the size of its map.
+    fn add_road(level: &mut Map<String, Value>) -> Result<(), Box<Error>> {
+        let size = level.get("map").and_then(|map| Some((map.get("width")?.as_i64()?, map.get("height")?.as_i64()?)));
+        let objects = level.get_mut("objects").and_then(Value::as_array_mut);
+        if let (Some((width, height)), Some(objects)) = (size, objects) {
+            let (x, y) = town::road(width as i32, height as i32);
+            objects.push(json::to_value(map::make_road(x, y))?);
+        }
+        Ok(())
+    }
+
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    let seed = state.get("seed").and_then(Value::as_u64).ok_or("The save has no seed.")?;
+    state.insert("overworld".into(), json::to_value(Overworld::new(seed))?);
+    if is_town(state.get("location")) {
+        add_road(state)?;
+    }
+    if let Some(levels) = state.get_mut("levels").and_then(Value::as_array_mut) {
+        for level in levels.iter_mut().filter(|level| is_town(level.get("location"))) {
+            if let Some(level) = level.as_object_mut() {
+                add_road(level)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 38 added rubble, bones and the like lying on the floor. Old levels
+/// are bare of them.
+fn v37_to_v38(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_tile(save, &mut |tile| {
+        tile.insert("feature".into(), Value::Null);
+    })
+}
+
+/// Version 39 added names, ambience and modifiers to levels. Old levels get
+/// a name and ambience made up for their theme, and no modifiers.
+fn v38_to_v39(save: &mut Value) -> Result<(), Box<Error>> {
+    fn describe(level: &mut Map<String, Value>) -> Result<(), Box<Error>> {
+        let location: Location = json::from_value(level.get("location").cloned().ok_or("A level has no location.")?)?;
+        let theme = Theme::for_location(location);
+        let mut rng = rand::thread_rng();
+        if let Some(map) = level.get_mut("map").and_then(Value::as_object_mut) {
+            map.insert("name".into(), theme.level_name(&mut rng).into());
+            map.insert("ambience".into(), theme.ambience(&mut rng).into());
+            map.insert("modifiers".into(), Value::Array(Vec::new()));
+        }
+        Ok(())
+    }
+
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    describe(state)?;
+    if let Some(levels) = state.get_mut("levels").and_then(Value::as_array_mut) {
+        for level in levels.iter_mut().filter_map(Value::as_object_mut) {
+            describe(level)?;
+        }
+    }
+    Ok(())
+}
+
+/// Version 40 made fleeing monsters remember what they were doing before,
+/// to go back to it once calm. Those fleeing in old saves were all basic ones.
+fn v39_to_v40(save: &mut Value) -> Result<(), Box<Error>> {
+    fn remember(ai: &mut Value) {
+        if let Some(ai) = ai.as_object_mut() {
+            if let Some(fleeing) = ai.get_mut("Fleeing").and_then(Value::as_object_mut) {
+                fleeing.insert("previous_ai".into(), "Basic".into());
+            }
+            if let Some(confused) = ai.get_mut("Confused").and_then(Value::as_object_mut) {
+                if let Some(previous_ai) = confused.get_mut("previous_ai") {
+                    remember(previous_ai);
+                }
+            }
+        }
+    }
+
+    for_each_object(save, &mut |object| {
+        if let Some(ai) = object.get_mut("ai") {
+            remember(ai);
+        }
+    })
+}
+
+/// Version 41 made packs of monsters hunt together. Those of an old save each
+/// go it alone.
+fn v40_to_v41(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        object.insert("pack".into(), Value::Null);
+    })
+}
+
+/// Version 42 let the player sneak. No one was sneaking in an old save.
+fn v41_to_v42(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    state.insert("sneaking".into(), false.into());
+    Ok(())
+}
+
+/// Version 43 had the player leave a scent behind. None lingers on old levels.
+fn v42_to_v43(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_tile(save, &mut |tile| {
+        tile.insert("scent".into(), Value::Null);
+    })
+}
+
+/// Version 44 kept the theme on each level's map. The current level takes the
+/// game's, and the ones left behind get the one for where they are.
+fn v43_to_v44(save: &mut Value) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    let theme = state.remove("theme").ok_or("The save has no theme.")?;
+    if let Some(map) = state.get_mut("map").and_then(Value::as_object_mut) {
+        map.insert("theme".into(), theme);
+    }
+    if let Some(levels) = state.get_mut("levels").and_then(Value::as_array_mut) {
+        for level in levels.iter_mut().filter_map(Value::as_object_mut) {
+            let location: Location = json::from_value(level.get("location").cloned()
+                .ok_or("A level has no location.")?)?;
+            if let Some(map) = level.get_mut("map").and_then(Value::as_object_mut) {
+                map.insert("theme".into(), json::to_value(Theme::for_location(location))?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 45 gave lights a slot of their own instead of the left hand. A
+/// player who had put theirs away for a shield or a big weapon gets it lit
+/// again.
+fn v44_to_v45(save: &mut Value) -> Result<(), Box<Error>> {
+    for_each_equipment(save, &mut |object, equipment| {
+        if object["item"] == "Torch" || object["item"] == "Lantern" {
+            equipment.insert("slot".into(), "Light".into());
+        }
+    })?;
+
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    let inventory = state.get_mut("inventory").and_then(Value::as_array_mut).ok_or("The save has no inventory.")?;
+    let is_light = |item: &Value| item["equipment"]["slot"] == "Light";
+    if !inventory.iter().any(|item| is_light(item) && item["equipment"]["equipped"] == true) {
+        let unlit = inventory.iter_mut().find(|item| is_light(item) && item["fuel"].as_i64().unwrap_or(0) > 0);
+        if let Some(equipment) = unlit.and_then(|item| item.get_mut("equipment")).and_then(Value::as_object_mut) {
+            equipment.insert("equipped".into(), true.into());
+        }
+    }
+    Ok(())
+}
+
+/// Call `f` with every tile in the save, on the current map and on the levels
+/// the player left.
+fn for_each_tile(save: &mut Value, f: &mut FnMut(&mut Map<String, Value>)) -> Result<(), Box<Error>> {
+    for_each_map(save, &mut |map| {
+        if let Some(tiles) = map.get_mut("tiles").and_then(Value::as_array_mut) {
+            for tile in tiles.iter_mut().filter_map(Value::as_object_mut) {
+                f(tile);
+            }
+        }
+    })
+}
+
+/// Call `f` with every map in the save: the current one, and those of the
+/// levels the player left.
+fn for_each_map(save: &mut Value, f: &mut FnMut(&mut Map<String, Value>)) -> Result<(), Box<Error>> {
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    if let Some(map) = state.get_mut("map").and_then(Value::as_object_mut) {
+        f(map);
+    }
+    if let Some(levels) = state.get_mut("levels").and_then(Value::as_array_mut) {
+        for level in levels.iter_mut() {
+            if let Some(map) = level.get_mut("map").and_then(Value::as_object_mut) {
+                f(map);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Call `f` with every piece of equipment in the save, and the object it
+/// belongs to.
+fn for_each_equipment(save: &mut Value, f: &mut FnMut(&Map<String, Value>, &mut Map<String, Value>))
+                      -> Result<(), Box<Error>> {
+    for_each_object(save, &mut |object| {
+        if let Some(mut equipment) = object.remove("equipment") {
+            if let Some(equipment_fields) = equipment.as_object_mut() {
+                f(object, equipment_fields);
+            }
+            object.insert("equipment".into(), equipment);
+        }
+    })
+}
+
+/// Call `f` with every object in the save, on the map, in the inventory or on
+/// the levels the player left, including the items monsters carry.
+fn for_each_object(save: &mut Value, f: &mut FnMut(&mut Map<String, Value>)) -> Result<(), Box<Error>> {
+    fn visit(object: &mut Value, f: &mut FnMut(&mut Map<String, Value>)) {
+        if let Some(object) = object.as_object_mut() {
+            f(object);
+            if let Some(carried) = object.get_mut("carried").and_then(Value::as_array_mut) {
+                for item in carried.iter_mut() {
+                    visit(item, f);
+                }
+            }
+        }
+    }
+
+    let state = save.as_object_mut().ok_or("The save data is not an object.")?;
+    for list in &["objects", "inventory"] {
+        if let Some(objects) = state.get_mut(*list).and_then(Value::as_array_mut) {
+            for object in objects.iter_mut() {
+                visit(object, f);
+            }
+        }
+    }
+    if let Some(levels) = state.get_mut("levels").and_then(Value::as_array_mut) {
+        for level in levels.iter_mut() {
+            if let Some(objects) = level.get_mut("objects").and_then(Value::as_array_mut) {
+                for object in objects.iter_mut() {
+                    visit(object, f);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use json::{self, Value};
+
+    use equipment::Slot;
+    use GameState;
+    use super::*;
+
+    // A save written by the last build before saves had a version.
+    const V1_SAVE: &str = include_str!("../tests/fixtures/save_v1.json");
+
+    #[test]
+    fn v1_save_loads() {
+        let mut save: Value = json::from_str(V1_SAVE).unwrap();
+        migrate(&mut save).unwrap();
+        assert_eq!(save["version"], VERSION);
+
+        let game_state: GameState = json::from_value(save).unwrap();
+        assert_eq!(game_state.objects[0].name, "player");
+        assert_eq!(game_state.objects[0].pos(), (12, 8));
+        assert!(game_state.inventory.iter().any(|item| item.item == Some(Item::Heal)));
+
+        // What the old format didn't have is filled in with defaults.
+        assert_eq!(game_state.turn, 0);
+        let torch = game_state.inventory.iter()
+            .find(|item| item.item == Some(Item::Torch))
+            .expect("the player should get a torch");
+        assert!(torch.equipment.map_or(false, |e| e.equipped) && torch.fuel > 0);
+        assert!(game_state.objects.iter().chain(&game_state.inventory).all(|object| object.count == 1));
+    }
+
+    #[test]
+    fn torch_for_a_full_pack_is_left_on_the_floor() {
+        let mut save: Value = json::from_str(V1_SAVE).unwrap();
+        let potion = save["inventory"][0].clone();
+        save["inventory"] = Value::Array(vec![potion; INVENTORY_SIZE]);
+        migrate(&mut save).unwrap();
+
+        let game_state: GameState = json::from_value(save).unwrap();
+        assert_eq!(game_state.inventory.len(), INVENTORY_SIZE);
+        assert!(game_state.objects.iter()
+            .any(|object| object.item == Some(Item::Torch) && object.pos() == game_state.objects[0].pos()));
+    }
+
+    #[test]
+    fn light_held_in_hand_moves_to_its_own_slot() {
+        let mut save: Value = json::from_str(V1_SAVE).unwrap();
+        migrate(&mut save).unwrap();
+        // Put it back the way a version 44 save had it, put away for a shield.
+        save["version"] = 44.into();
+        for item in save["inventory"].as_array_mut().unwrap() {
+            if item["item"] == "Torch" {
+                item["equipment"]["slot"] = "LeftHand".into();
+                item["equipment"]["equipped"] = false.into();
+            }
+        }
+        migrate(&mut save).unwrap();
+
+        let game_state: GameState = json::from_value(save).unwrap();
+        let torch = game_state.inventory.iter().find(|item| item.item == Some(Item::Torch)).unwrap();
+        let equipment = torch.equipment.unwrap();
+        assert_eq!(equipment.slot, Slot::Light);
+        assert!(equipment.equipped);
+    }
+
+    #[test]
+    fn newer_save_is_refused() {
+        let mut save: Value = json::from_str(&format!("{{\"version\": {}}}", VERSION + 1)).unwrap();
+        assert!(migrate(&mut save).is_err());
+    }
+}
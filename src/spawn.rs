@@ -0,0 +1,113 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::rc::Rc;
+
+use json;
+use rand::distributions::Weighted;
+
+use map::{self, Transition};
+use object::Item;
+use theme::ThemeKind;
+
+const SPAWNS_FILE: &str = "assets/spawns.json";
+// The tables the game shipped with, for when the file is missing or broken.
+const DEFAULT_SPAWNS: &str = include_str!("../assets/spawns.json");
+
+thread_local! {
+    // Read once, the first time they're asked for, like the monster catalog.
+    static TABLES: Rc<SpawnTables> = Rc::new(SpawnTables::read());
+}
+
+/// How much more or less common something is in one kind of place, in percent.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ThemeWeight {
+    theme: ThemeKind,
+    percent: u32,
+}
+
+/// How likely something is at a dungeon level, going by its chance by depth
+/// and how that changes with the theme of the level.
+pub fn weight(chance: &[Transition], themes: &[ThemeWeight], level: u32, theme: ThemeKind) -> u32 {
+    let percent = themes.iter().find(|weight| weight.theme == theme).map_or(100, |weight| weight.percent);
+    map::from_dungeon_level(chance, level) * percent / 100
+}
+
+/// One entry of a spawn table: what spawns, how likely it is by depth, and
+/// how that changes with the theme of the level.
+#[derive(Clone, Debug, Deserialize)]
+struct Spawn<T> {
+    spawn: T,
+    chance: Vec<Transition>,
+    #[serde(default)]
+    themes: Vec<ThemeWeight>,
+}
+
+impl<T: Clone> Spawn<T> {
+    fn weighted(&self, level: u32, theme: ThemeKind) -> Weighted<T> {
+        Weighted {
+            weight: weight(&self.chance, &self.themes, level, theme),
+            item: self.spawn.clone(),
+        }
+    }
+}
+
+/// The weighted tables items are picked from as levels get generated, loaded
+/// from `assets/spawns.json` so they can be tuned without rebuilding the
+/// game. Monsters are found where the monster catalog says.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpawnTables {
+    items: Vec<Spawn<Item>>,
+}
+
+impl SpawnTables {
+    /// The spawn tables, read from their file the first time. A missing or
+    /// broken file gives the ones the game shipped with.
+    pub fn load() -> Rc<Self> {
+        TABLES.with(Rc::clone)
+    }
+
+    fn read() -> Self {
+        read_tables().unwrap_or_else(|err| {
+            eprintln!("Could not load {}, using the built-in spawn tables: {}", SPAWNS_FILE, err);
+            json::from_str(DEFAULT_SPAWNS).expect("The built-in spawn tables are broken.")
+        })
+    }
+
+    /// How likely each item is to be found at a dungeon level.
+    pub fn items(&self, level: u32, theme: ThemeKind) -> Vec<Weighted<Item>> {
+        self.items.iter().map(|spawn| spawn.weighted(level, theme)).collect()
+    }
+
+    /// Make sure there's an item to pick at every depth, in every theme.
+    fn validate(&self) -> Result<(), Box<Error>> {
+        // Past the deepest change in the table, nothing changes anymore.
+        let deepest = self.items.iter()
+            .flat_map(|spawn| &spawn.chance)
+            .map(|transition| transition.level)
+            .max()
+            .unwrap_or(1);
+        for level in 1..(deepest + 1) {
+            for &theme in &ThemeKind::ALL {
+                if total(&self.items(level, theme)) == 0 {
+                    return Err(format!("The item table is empty at level {} in the {:?} theme.", level, theme)
+                        .into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The sum of the weights in a table, which can't be picked from at 0.
+pub fn total<T>(weights: &[Weighted<T>]) -> u32 {
+    weights.iter().map(|weighted| weighted.weight).sum()
+}
+
+fn read_tables() -> Result<SpawnTables, Box<Error>> {
+    let mut text = String::new();
+    File::open(SPAWNS_FILE)?.read_to_string(&mut text)?;
+    let tables: SpawnTables = json::from_str(&text)?;
+    tables.validate()?;
+    Ok(tables)
+}
@@ -0,0 +1,271 @@
+use rand::Rng;
+use tcod::colors::{self, Color};
+
+use branch::{Branch, Location};
+use map::{Corridor, Terrain};
+
+/// The kinds of places the dungeon goes through on the way down.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ThemeKind {
+    Tombs,
+    Overgrown,
+    Flooded,
+    Crypt,
+    Volcanic,
+    Mines,
+    Town,
+}
+
+impl ThemeKind {
+    pub const ALL: [ThemeKind; 7] = [ThemeKind::Tombs, ThemeKind::Overgrown, ThemeKind::Flooded, ThemeKind::Crypt,
+                                     ThemeKind::Volcanic, ThemeKind::Mines, ThemeKind::Town];
+
+    fn flavor(self) -> Flavor {
+        match self {
+            ThemeKind::Tombs => Flavor {
+                adjectives: &["Silent", "Forgotten", "Dusty", "Sealed", "Ancient"],
+                nouns: &["Tombs", "Ossuary", "Catacombs", "Burial Halls", "Sepulchre"],
+                ambience: &["Dust drifts down from the ceiling.", "The air is still and stale.",
+                            "Somewhere, stone grinds against stone."],
+            },
+            ThemeKind::Overgrown => Flavor {
+                adjectives: &["Tangled", "Mossy", "Verdant", "Choked", "Rotting"],
+                nouns: &["Grove", "Caverns", "Thicket", "Hollows", "Roots"],
+                ambience: &["Roots creak in the walls around you.", "The air smells of damp earth.",
+                            "Something rustles in the undergrowth."],
+            },
+            ThemeKind::Flooded => Flavor {
+                adjectives: &["Sunken", "Drowned", "Dripping", "Flooded", "Murky"],
+                nouns: &["Gallery", "Halls", "Cisterns", "Reservoir", "Channels"],
+                ambience: &["Water drips steadily somewhere nearby.", "The air is cold and damp.",
+                            "You hear the lapping of still water."],
+            },
+            ThemeKind::Crypt => Flavor {
+                adjectives: &["Haunted", "Shadowed", "Cursed", "Forsaken", "Hallowed"],
+                nouns: &["Crypt", "Vaults", "Mausoleum", "Charnel House", "Barrow"],
+                ambience: &["A chill runs down your spine.", "You hear faint whispers, then nothing.",
+                            "The smell of old death hangs in the air."],
+            },
+            ThemeKind::Volcanic => Flavor {
+                adjectives: &["Smoldering", "Burning", "Ashen", "Scorched", "Molten"],
+                nouns: &["Depths", "Forge", "Furnace", "Rift", "Pits"],
+                ambience: &["The heat is stifling.", "The ground rumbles beneath your feet.",
+                            "Sulfurous fumes sting your eyes."],
+            },
+            ThemeKind::Mines => Flavor {
+                adjectives: &["Abandoned", "Collapsed", "Deep", "Glittering", "Old"],
+                nouns: &["Shafts", "Tunnels", "Workings", "Diggings", "Lode"],
+                ambience: &["Timbers groan overhead.", "You hear the distant tap of a pick.",
+                            "Loose gravel skitters down the walls."],
+            },
+            ThemeKind::Town => Flavor {
+                adjectives: &["Sleepy", "Quiet", "Sunny"],
+                nouns: &["Village", "Market Town", "Hamlet"],
+                ambience: &["Townsfolk go about their business.", "A dog barks somewhere in town."],
+            },
+        }
+    }
+}
+
+/// Words that go into the names of levels of a kind of place, and what the
+/// player may notice on arriving on one.
+struct Flavor {
+    adjectives: &'static [&'static str],
+    nouns: &'static [&'static str],
+    ambience: &'static [&'static str],
+}
+
+/// Pools of terrain dotted around a level.
+pub struct Pools {
+    pub middle: Terrain,
+    pub edge: Terrain,
+    /// Percent chance of a pool in each room.
+    pub chance: u32,
+}
+
+/// How a dungeon level looks: tile colors and glyphs, and pools of terrain.
+/// Picked by branch and depth. Which monsters and items are more or less
+/// common in each kind of place is up to the spawn tables.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Theme {
+    pub kind: ThemeKind,
+    pub name: String,
+    pub dark_wall: Color,
+    pub light_wall: Color,
+    pub dark_ground: Color,
+    pub light_ground: Color,
+    pub wall_char: char,
+    pub stairs_char: char,
+    /// Color of messages about the level, like arriving on it.
+    pub message_color: Color,
+}
+
+impl Theme {
+    pub fn for_location(location: Location) -> Self {
+        match location.branch {
+            Branch::Dungeon if location.is_town() => Theme {
+                kind: ThemeKind::Town,
+                name: "town".into(),
+                dark_wall: Color { r: 50, g: 35, b: 25 },
+                light_wall: Color { r: 140, g: 100, b: 70 },
+                dark_ground: Color { r: 30, g: 60, b: 30 },
+                light_ground: Color { r: 90, g: 160, b: 70 },
+                wall_char: '#',
+                stairs_char: '>',
+                message_color: colors::LIGHT_GREEN,
+            },
+            Branch::Dungeon => Theme::for_level(location.depth),
+            Branch::Mines => Theme {
+                kind: ThemeKind::Mines,
+                name: "mines".into(),
+                dark_wall: Color { r: 40, g: 30, b: 15 },
+                light_wall: Color { r: 120, g: 90, b: 50 },
+                dark_ground: Color { r: 50, g: 40, b: 25 },
+                light_ground: Color { r: 160, g: 130, b: 80 },
+                wall_char: '#',
+                stairs_char: '>',
+                message_color: colors::GOLD,
+            },
+            Branch::Barrows => Theme {
+                kind: ThemeKind::Crypt,
+                name: "barrows".into(),
+                dark_wall: Color { r: 35, g: 35, b: 25 },
+                light_wall: Color { r: 110, g: 105, b: 80 },
+                dark_ground: Color { r: 40, g: 40, b: 35 },
+                light_ground: Color { r: 140, g: 135, b: 110 },
+                wall_char: '#',
+                stairs_char: '>',
+                message_color: colors::LIGHT_SEPIA,
+            },
+            Branch::Grotto => Theme {
+                kind: ThemeKind::Flooded,
+                name: "sunken grotto".into(),
+                dark_wall: Color { r: 10, g: 40, b: 50 },
+                light_wall: Color { r: 40, g: 110, b: 120 },
+                dark_ground: Color { r: 20, g: 45, b: 55 },
+                light_ground: Color { r: 80, g: 150, b: 160 },
+                wall_char: '#',
+                stairs_char: '>',
+                message_color: colors::LIGHT_CYAN,
+            },
+            Branch::FirePits => Theme {
+                kind: ThemeKind::Volcanic,
+                name: "fire pits".into(),
+                dark_wall: Color { r: 60, g: 15, b: 5 },
+                light_wall: Color { r: 160, g: 50, b: 20 },
+                dark_ground: Color { r: 55, g: 25, b: 15 },
+                light_ground: Color { r: 190, g: 90, b: 40 },
+                wall_char: '#',
+                stairs_char: '>',
+                message_color: colors::LIGHT_RED,
+            },
+        }
+    }
+
+    pub fn for_level(level: u32) -> Self {
+        if level <= 2 {
+            Theme {
+                kind: ThemeKind::Tombs,
+                name: "tombs".into(),
+                dark_wall: Color { r: 0, g: 0, b: 100 },
+                light_wall: Color { r: 130, g: 110, b: 50 },
+                dark_ground: Color { r: 50, g: 50, b: 150 },
+                light_ground: Color { r: 200, g: 180, b: 50 },
+                wall_char: ' ',
+                stairs_char: '>',
+                message_color: colors::RED,
+            }
+        } else if level <= 4 {
+            Theme {
+                kind: ThemeKind::Overgrown,
+                name: "overgrown caves".into(),
+                dark_wall: Color { r: 20, g: 40, b: 20 },
+                light_wall: Color { r: 60, g: 110, b: 50 },
+                dark_ground: Color { r: 35, g: 50, b: 30 },
+                light_ground: Color { r: 120, g: 160, b: 80 },
+                wall_char: '#',
+                stairs_char: '>',
+                message_color: colors::LIGHT_GREEN,
+            }
+        } else if level <= 6 {
+            Theme {
+                kind: ThemeKind::Flooded,
+                name: "flooded halls".into(),
+                dark_wall: Color { r: 10, g: 30, b: 60 },
+                light_wall: Color { r: 50, g: 90, b: 130 },
+                dark_ground: Color { r: 20, g: 40, b: 70 },
+                light_ground: Color { r: 90, g: 140, b: 180 },
+                wall_char: '#',
+                stairs_char: '>',
+                message_color: colors::LIGHT_AZURE,
+            }
+        } else if level <= 8 {
+            Theme {
+                kind: ThemeKind::Crypt,
+                name: "crypt".into(),
+                dark_wall: Color { r: 30, g: 20, b: 50 },
+                light_wall: Color { r: 100, g: 90, b: 120 },
+                dark_ground: Color { r: 45, g: 40, b: 60 },
+                light_ground: Color { r: 150, g: 145, b: 160 },
+                wall_char: ' ',
+                stairs_char: '>',
+                message_color: colors::LIGHT_PURPLE,
+            }
+        } else {
+            Theme {
+                kind: ThemeKind::Volcanic,
+                name: "volcanic depths".into(),
+                dark_wall: Color { r: 50, g: 10, b: 0 },
+                light_wall: Color { r: 140, g: 40, b: 10 },
+                dark_ground: Color { r: 60, g: 30, b: 20 },
+                light_ground: Color { r: 200, g: 110, b: 40 },
+                wall_char: '#',
+                stairs_char: '>',
+                message_color: colors::ORANGE,
+            }
+        }
+    }
+
+    /// How the tunnels between rooms or caves are dug: built halls have
+    /// straight corridors, and natural caves winding passages.
+    pub fn corridor(&self) -> Corridor {
+        match self.kind {
+            ThemeKind::Tombs | ThemeKind::Crypt | ThemeKind::Town => Corridor::Straight,
+            ThemeKind::Flooded | ThemeKind::Volcanic => Corridor::Diagonal,
+            ThemeKind::Overgrown | ThemeKind::Mines => Corridor::Winding,
+        }
+    }
+
+    /// Make up a name for a level, like "The Sunken Gallery".
+    pub fn level_name<R: Rng>(&self, rng: &mut R) -> String {
+        let flavor = self.kind.flavor();
+        format!("The {} {}", rng.choose(flavor.adjectives).unwrap(), rng.choose(flavor.nouns).unwrap())
+    }
+
+    /// Something the player notices on arriving on a level.
+    pub fn ambience<R: Rng>(&self, rng: &mut R) -> &'static str {
+        rng.choose(self.kind.flavor().ambience).unwrap()
+    }
+
+    /// The pools of water or lava found here, if any.
+    pub fn pools(&self) -> Option<Pools> {
+        match self.kind {
+            ThemeKind::Overgrown => Some(Pools {
+                middle: Terrain::ShallowWater,
+                edge: Terrain::ShallowWater,
+                chance: 15,
+            }),
+            ThemeKind::Flooded => Some(Pools {
+                middle: Terrain::DeepWater,
+                edge: Terrain::ShallowWater,
+                chance: 50,
+            }),
+            ThemeKind::Volcanic => Some(Pools {
+                middle: Terrain::Lava,
+                edge: Terrain::Ground,
+                chance: 30,
+            }),
+            ThemeKind::Tombs | ThemeKind::Crypt | ThemeKind::Mines | ThemeKind::Town => None,
+        }
+    }
+}
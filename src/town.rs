@@ -0,0 +1,79 @@
+use rand::{Rng, StdRng};
+
+use map::{self, Map, Rect, Terrain, Tile};
+
+// Size of each building, walls included.
+const HOUSE_WIDTH: i32 = 10;
+const HOUSE_HEIGHT: i32 = 7;
+// How far the buildings stand from the edge of the map, give or take.
+const HOUSE_MARGIN: i32 = 4;
+const HOUSE_JITTER: i32 = 2;
+const FOUNTAIN_RADIUS: i32 = 2;
+
+/// Where things go in the town.
+pub struct Town {
+    pub start: (i32, i32),
+    pub stairs: (i32, i32),
+    pub shop: (i32, i32),
+    pub healer: (i32, i32),
+    pub stash: (i32, i32),
+    /// Where the road out into the wilderness starts.
+    pub road: (i32, i32),
+}
+
+/// Lay out the town on the surface: an open square with a fountain, houses
+/// for the shop, the healer and the player's stash, the way down into the
+/// dungeon, and the road out into the wilderness.
+pub fn make_town(map: &mut Map, rng: &mut StdRng) -> Town {
+    let (width, height) = (map.width, map.height);
+    map::create_room(Rect::new(0, 0, width - 1, height - 1), map);
+
+    let mut house = |x: i32, y: i32, door_dy: i32, map: &mut Map| {
+        let x = x + rng.gen_range(-HOUSE_JITTER, HOUSE_JITTER + 1);
+        let y = y + rng.gen_range(-HOUSE_JITTER, HOUSE_JITTER + 1);
+        let room = Rect::new(x, y, HOUSE_WIDTH - 1, HOUSE_HEIGHT - 1);
+        for wall_x in room.x1..(room.x2 + 1) {
+            for wall_y in room.y1..(room.y2 + 1) {
+                map[(wall_x, wall_y)] = Tile::wall();
+            }
+        }
+        map::create_room(room, map);
+        // The door faces the square.
+        let (center_x, center_y) = room.center();
+        let door_y = if door_dy > 0 { room.y2 } else { room.y1 };
+        map[(center_x, door_y)] = Tile::empty();
+        (center_x, center_y)
+    };
+    let left = HOUSE_MARGIN;
+    let right = width - HOUSE_MARGIN - HOUSE_WIDTH;
+    let top = HOUSE_MARGIN;
+    let bottom = height - HOUSE_MARGIN - HOUSE_HEIGHT;
+    let shop = house(left, top, 1, map);
+    let healer = house(right, top, 1, map);
+    let stash = house(left, bottom, -1, map);
+
+    // A fountain in the middle of the square.
+    let (center_x, center_y) = (width / 2, height / 2);
+    for x in (center_x - FOUNTAIN_RADIUS)..(center_x + FOUNTAIN_RADIUS + 1) {
+        for y in (center_y - FOUNTAIN_RADIUS)..(center_y + FOUNTAIN_RADIUS + 1) {
+            map[(x, y)].terrain = Terrain::ShallowWater;
+        }
+    }
+
+    // The way down is in the corner across from the player's house.
+    let stairs = (right + HOUSE_WIDTH / 2, bottom + HOUSE_HEIGHT / 2);
+    Town {
+        start: (stairs.0 - 1, stairs.1),
+        stairs,
+        shop,
+        healer,
+        stash,
+        road: road(width, height),
+    }
+}
+
+/// Where the road out of a town of the given size starts: in the middle of
+/// its bottom edge, between the houses.
+pub fn road(width: i32, height: i32) -> (i32, i32) {
+    (width / 2, height - 2)
+}
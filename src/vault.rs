@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use map::{Map, Tile};
+
+const VAULT_DIR: &str = "assets/vaults";
+
+/// Something to put on a vault's floor, when it's stamped into a level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Marker {
+    Monster,
+    Item,
+    Stairs,
+}
+
+/// A hand-made room layout, loaded from a text file in `assets/vaults`.
+///
+/// In the files, `#` is wall that can't be dug through and `.` is floor, while `M`, `I` and `>` are
+/// floor with a monster, an item or the stairs on it. Anything else is left
+/// as solid rock. Lines starting with `;` are comments.
+pub struct Vault {
+    rows: Vec<Vec<char>>,
+}
+
+impl Vault {
+    pub fn width(&self) -> i32 {
+        self.rows.iter().map(|row| row.len()).max().unwrap_or(0) as i32
+    }
+
+    pub fn height(&self) -> i32 {
+        self.rows.len() as i32
+    }
+
+    /// Carve the vault's floor and walls into the map, with its top left corner
+    /// at the given position. Returns every floor tile, with what goes on it.
+    pub fn stamp(&self, x: i32, y: i32, map: &mut Map) -> Vec<((i32, i32), Option<Marker>)> {
+        let mut floor = vec![];
+        for (dy, row) in self.rows.iter().enumerate() {
+            for (dx, &glyph) in row.iter().enumerate() {
+                let pos = (x + dx as i32, y + dy as i32);
+                let marker = match glyph {
+                    '#' => {
+                        map[pos] = Tile::permanent_wall();
+                        continue;
+                    }
+                    '.' => None,
+                    'M' => Some(Marker::Monster),
+                    'I' => Some(Marker::Item),
+                    '>' => Some(Marker::Stairs),
+                    _ => continue,
+                };
+                map[pos] = Tile::empty();
+                floor.push((pos, marker));
+            }
+        }
+        floor
+    }
+}
+
+/// Load every vault in the assets, in order of their file names, so the same
+/// seed picks the same vault on every system. Files that can't be read are
+/// skipped.
+pub fn load_vaults() -> Vec<Vault> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(VAULT_DIR)
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().map_or(false, |ext| ext == "txt"))
+                .collect()
+        })
+        .unwrap_or_else(|_| Vec::new());
+    paths.sort();
+    paths.iter()
+        .filter_map(|path| read_vault(path).ok())
+        .filter(|vault| vault.height() > 0)
+        .collect()
+}
+
+fn read_vault(path: &Path) -> Result<Vault, Box<Error>> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    let rows = text.lines()
+        .filter(|line| !line.starts_with(';'))
+        .map(|line| line.chars().collect())
+        .collect();
+    Ok(Vault { rows })
+}